@@ -2,7 +2,7 @@ use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
 use syn::parse::{Parse, ParseStream};
-use syn::{parse_macro_input, Ident, Result, Token, Type};
+use syn::{parse_macro_input, Ident, LitStr, Result, Token, Type};
 
 mod kw {
     syn::custom_keyword!(to);
@@ -17,7 +17,6 @@ pub fn attach(input: TokenStream) -> TokenStream {
     // create new node
     let instantiation_expr;
     let pass_object_expr;
-    let tag_str;
     let tag_type;
     match input.tag {
         Tag::Ident(tag) => {
@@ -25,24 +24,38 @@ pub fn attach(input: TokenStream) -> TokenStream {
                 let mut new_node = #tag::default();
             };
             pass_object_expr = quote! {
-                new_tag = Some(MathNode::#tag(new_node));
+                let mut node = MathNode::#tag(new_node);
             };
 
-            tag_str = tag.to_string();
             tag_type = tag;
         }
         Tag::Enum(enum_name, enum_type) => {
             let enum_str = enum_name.to_string().to_lowercase();
-            let fn_str = format!("new_{}", enum_str);
-            let fn_ident = Ident::new(&fn_str, Span::call_site());
+            if input.attr_idents.is_empty() {
+                let fn_str = format!("new_{}", enum_str);
+                let fn_ident = Ident::new(&fn_str, Span::call_site());
 
-            instantiation_expr = quote! {};
+                instantiation_expr = quote! {};
 
-            pass_object_expr = quote! {
-                new_tag = Some(MathNode::#fn_ident(#enum_name::#enum_type));
-            };
+                pass_object_expr = quote! {
+                    let mut node = MathNode::#fn_ident(#enum_name::#enum_type);
+                };
+            } else {
+                // attributes are stored on the node struct, e.g. the
+                // `container` field of a `ContainerNode`
+                let struct_ident = Ident::new(&format!("{}Node", enum_name), Span::call_site());
+                let field_ident = Ident::new(&enum_str, Span::call_site());
+
+                instantiation_expr = quote! {
+                    let mut new_node = #struct_ident::default();
+                    new_node.#field_ident = Some(#enum_name::#enum_type);
+                };
+
+                pass_object_expr = quote! {
+                    let mut node = MathNode::#enum_name(new_node);
+                };
+            }
 
-            tag_str = format!("{}::{}", enum_name.to_string(), enum_type.to_string());
             tag_type = enum_name;
         }
     }
@@ -50,54 +63,78 @@ pub fn attach(input: TokenStream) -> TokenStream {
     // attributes field names and types
     let attr_idents = input.attr_idents;
     let attr_types = input.attr_types;
-    // also need strings for matching tokens
+    // also need strings for matching tokens, unless the attribute was renamed
     let mut attr_str = Vec::new();
-    for ident in &attr_idents {
-        let mut ident_str = ident.to_string();
-        if ident_str.starts_with("r#") {
-            ident_str = ident_str.trim_start_matches("r#").to_string()
-        }
+    for (ident, name) in attr_idents.iter().zip(input.attr_names) {
+        let ident_str = match name {
+            Some(name) => name.value(),
+            None => ident.to_string().trim_start_matches("r#").to_string(),
+        };
         attr_str.push(ident_str);
     }
 
     // create code to parse attributes
     let store_attr = quote! {
-        // parse any attributes, keeping their types in mind
-        let attributes = e.attributes().map(|a| a.unwrap()).collect::<Vec<_>>();
-        //println!("{:?}", attributes);
-        for attribute in attributes {
-            let key = std::str::from_utf8(attribute.key).unwrap();
-            let value = attribute.unescape_and_decode_value(&reader).unwrap();
+        // parse any attributes, keeping their types in mind and the rest as
+        // they are
+        let mut attributes = Attributes::default();
+        for attribute in e.attributes() {
+            let attribute = attribute.map_err(|error| ParseError::XmlError { error, position })?;
+            let key = std::str::from_utf8(attribute.key)
+                .map_err(|error| ParseError::XmlError { error: error.into(), position })?;
+            let value = attribute
+                .unescape_and_decode_value(reader)
+                .map_err(|error| ParseError::XmlError { error, position })?;
+            let (namespace, _) = reader.attribute_namespace(attribute.key, namespace_buffer);
+            let namespace = namespace.map(String::from_utf8_lossy);
             match key {
                 #(#attr_str => {
-                    new_node.#attr_idents =
-                        Some(value.parse::<#attr_types>().expect("Incorrect type"));
+                    new_node.#attr_idents = Some(value.parse::<#attr_types>().map_err(|_| {
+                        ParseError::InvalidAttribute {
+                            element: element_name.clone(),
+                            name: key.to_string(),
+                            value: value.clone(),
+                            position,
+                        }
+                    })?);
                 })*
-                _ => {
-                    //println!("{:?}", #attr_str);
-                    panic!("Attribute {} not parsed for {}", key, #tag_str);
-                }
+                _ => attributes.insert_namespaced(key, namespace.as_deref(), value),
             }
         }
     };
 
-    let index_expr = quote! {
-        parent.index(MathNodeType::#tag_type, current.clone());
-    };
-
     let parents = &input.parents;
     // create strings for debugging
     let mut parent_strs: Vec<String> = Vec::new();
     let mut index_exprs: Vec<proc_macro2::TokenStream> = Vec::new();
     // TODO: Convert this to trait
-    let parents_to_index: Vec<String> = vec!["Apply", "Lambda", "Piecewise", "Piece", "Otherwise"]
-        .iter()
-        .map(|&a| a.into())
-        .collect();
+    let parents_to_index: Vec<String> = [
+        "Root",
+        "Apply",
+        "Lambda",
+        "BVar",
+        "Piecewise",
+        "Piece",
+        "Otherwise",
+        "Qualifier",
+        "Semantics",
+        "Container",
+    ]
+    .iter()
+    .map(|&a| a.into())
+    .collect();
     for parent in parents {
         let parent_str = parent.to_string();
         if parents_to_index.contains(&parent_str) {
-            index_exprs.push(index_expr.clone());
+            index_exprs.push(quote! {
+                parent.index(MathNodeType::#tag_type, current).map_err(|_| {
+                    ParseError::UnexpectedChild {
+                        parent: #parent_str.to_string(),
+                        child: element_name.clone(),
+                        position,
+                    }
+                })?;
+            });
         } else {
             index_exprs.push(quote! {})
         }
@@ -106,6 +143,7 @@ pub fn attach(input: TokenStream) -> TokenStream {
 
     let tokens = quote! {
         {
+            let element_name = String::from_utf8_lossy(e.name()).into_owned();
             // create new object
             #instantiation_expr
             #store_attr
@@ -114,6 +152,8 @@ pub fn attach(input: TokenStream) -> TokenStream {
                 // with the parent
                 #(MathNode::#parents (ref mut parent) => {
                     #pass_object_expr
+                    *node.attributes_mut() = attributes;
+                    new_tag = Some(node);
                     // update current pointer (which is really an int)
                     current = container_len;
                     // update parent pointer of new tag
@@ -121,10 +161,13 @@ pub fn attach(input: TokenStream) -> TokenStream {
                     #index_exprs
                     // push current pointer to stack
                     stack.push(current.clone());
-                    //println!("Opened {}", #tag_str);
                 })*
                 _ => {
-                    panic!("Tag {:?} not parsed under parent {:?}", #tag_str, container[current]);
+                    return Err(ParseError::UnexpectedChild {
+                        parent: container[current].node_type().to_string(),
+                        child: element_name,
+                        position,
+                    });
                 }
             }
         }
@@ -137,6 +180,7 @@ struct OpenInput {
     tag: Tag,
     parents: Vec<Ident>,
     attr_idents: Vec<Ident>,
+    attr_names: Vec<Option<LitStr>>,
     attr_types: Vec<Type>,
 }
 
@@ -150,21 +194,21 @@ impl Parse for OpenInput {
     fn parse(input: ParseStream) -> Result<Self> {
         // parse tag
         let tag_ident = syn::Ident::parse(input)?;
-        let tag: Tag;
         // see if this is an enum
         let mut lookahead = input.lookahead1();
-        if lookahead.peek(Token![:]) {
+        let tag = if lookahead.peek(Token![:]) {
             input.parse::<Token![:]>()?;
             input.parse::<Token![:]>()?;
 
             let enum_type = syn::Ident::parse(input)?;
-            tag = Tag::Enum(tag_ident, enum_type);
+            Tag::Enum(tag_ident, enum_type)
         } else {
-            tag = Tag::Ident(tag_ident);
-        }
+            Tag::Ident(tag_ident)
+        };
 
         // define fields used later
         let mut attr_idents = Vec::new();
+        let mut attr_names = Vec::new();
         let mut attr_types = Vec::new();
         // define lookahead function
         lookahead = input.lookahead1();
@@ -178,6 +222,13 @@ impl Parse for OpenInput {
                 // parse attribute field name as ident
                 let ident = syn::Ident::parse(input)?;
                 attr_idents.push(ident);
+                // the XML name, if it differs from the field name
+                if input.peek(Token![=]) {
+                    input.parse::<Token![=]>()?;
+                    attr_names.push(Some(input.parse::<LitStr>()?));
+                } else {
+                    attr_names.push(None);
+                }
                 let _as = input.parse::<Token![as]>();
                 // parse attribute type
                 let ty = syn::Type::parse(input)?;
@@ -222,6 +273,7 @@ impl Parse for OpenInput {
             tag,
             parents,
             attr_idents,
+            attr_names,
             attr_types,
         })
     }
@@ -233,19 +285,30 @@ pub fn close(input: TokenStream) -> TokenStream {
     //println!("{:?}", input);
 
     let tag = &input.tag;
-    let tag_str = input.tag.to_string();
 
     let tokens = quote! {
-        match container[current] {
-            MathNode::#tag (ref mut tag_field) => {
-                stack.pop();
-                current = stack.last().unwrap().to_owned();
-                tag_field.parent = Some(current.clone());
-                //println!("Closing {}", #tag_str);
-            }
-            _ => {
-                //println!("{:#?}", container);
-                panic!("Trying to close {} but currently in {:?}", #tag_str, container[current]);
+        {
+            let missing = container[current].missing_child();
+            match container[current] {
+                MathNode::#tag (ref mut tag_field) => {
+                    // refuse to close elements that lack a required child
+                    if let Some(missing) = missing {
+                        return Err(ParseError::IncompleteElement {
+                            element: String::from_utf8_lossy(e.name()).into_owned(),
+                            missing,
+                            position,
+                        });
+                    }
+                    stack.pop();
+                    current = stack.last().unwrap().to_owned();
+                    tag_field.parent = Some(current.clone());
+                }
+                _ => {
+                    return Err(ParseError::UnexpectedEnd {
+                        name: String::from_utf8_lossy(e.name()).into_owned(),
+                        position,
+                    });
+                }
             }
         }
     };
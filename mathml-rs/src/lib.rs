@@ -1,34 +1,195 @@
 use mathml_macros::*;
 use quick_xml::events::Event;
 use quick_xml::Reader;
-use std::fs::File;
-use std::io::BufReader;
+use std::io::BufRead;
 pub mod structs;
 pub use structs::apply::*;
+pub use structs::attributes::*;
 pub use structs::bindings::*;
 pub use structs::ci::*;
 pub use structs::cn::*;
 pub use structs::constants::*;
+pub use structs::container::*;
+pub use structs::csymbol::*;
+pub use structs::error::*;
+pub use structs::expr::*;
 pub use structs::lambda::*;
 pub use structs::math_node::*;
+pub use structs::math_tree::*;
 pub use structs::numbers::*;
 pub use structs::op::*;
 pub use structs::piecewise::*;
+pub use structs::qualifier::*;
 pub use structs::root::*;
+pub use structs::semantics::*;
+pub use structs::value::*;
 
 pub mod methods;
+pub use methods::compile::*;
 pub use methods::evaluate::*;
+pub use methods::formula::*;
+pub use methods::infix::*;
+pub use methods::latex::*;
+pub use methods::linear_algebra::*;
+#[cfg(feature = "rayon")]
+pub use methods::parallel::*;
+pub use methods::serialize::*;
 
-pub fn parse_fragment(
-    mut reader: Reader<BufReader<File>>,
-) -> (Vec<MathNode>, Reader<BufReader<File>>) {
+/// What the parser does with elements from namespaces other than MathML.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ForeignElements {
+    /// Fail with [`ParseError::ForeignElement`].
+    Reject,
+    /// Leave out the element together with everything inside it.
+    Skip,
+}
+
+/// Controls how the parser treats its input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseOptions {
+    pub foreign_elements: ForeignElements,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            foreign_elements: ForeignElements::Reject,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Options that skip foreign elements instead of rejecting them.
+    pub fn skip_foreign() -> Self {
+        ParseOptions {
+            foreign_elements: ForeignElements::Skip,
+        }
+    }
+}
+
+/// Parses a MathML document held in memory as a string.
+///
+/// Everything up to and including the first `<math>` start tag is skipped,
+/// so the input may carry an XML declaration or comments before it.
+pub fn parse_str(s: &str) -> Result<MathTree, ParseError> {
+    parse_bytes(s.as_bytes())
+}
+
+/// Parses a MathML document held in memory as a string, with options.
+pub fn parse_str_with(s: &str, options: &ParseOptions) -> Result<MathTree, ParseError> {
+    parse_bytes_with(s.as_bytes(), options)
+}
+
+/// Parses a MathML document held in memory as raw bytes.
+pub fn parse_bytes(bytes: &[u8]) -> Result<MathTree, ParseError> {
+    parse_bytes_with(bytes, &ParseOptions::default())
+}
+
+/// Parses a MathML document held in memory as raw bytes, with options.
+pub fn parse_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<MathTree, ParseError> {
+    parse_document(&mut Reader::from_reader(bytes), options).map_err(|error| error.locate(bytes))
+}
+
+/// Parses a MathML document from any buffered reader.
+///
+/// The input is streamed through the XML reader rather than read into
+/// memory, so errors report the byte offset they occurred at but no line
+/// or column.
+pub fn parse_reader<R: BufRead>(reader: R) -> Result<MathTree, ParseError> {
+    parse_reader_with(reader, &ParseOptions::default())
+}
+
+/// Parses a MathML document from any buffered reader, with options.
+pub fn parse_reader_with<R: BufRead>(
+    reader: R,
+    options: &ParseOptions,
+) -> Result<MathTree, ParseError> {
+    parse_document(&mut Reader::from_reader(reader), options)
+}
+
+// Skips to the first MathML <math> start tag and parses its contents.
+fn parse_document<R: BufRead>(
+    reader: &mut Reader<R>,
+    options: &ParseOptions,
+) -> Result<MathTree, ParseError> {
+    reader.expand_empty_elements(true);
+    let mut buf = Vec::new();
+    let mut namespace_buffer = Vec::new();
+    let mut attributes = Attributes::default();
+    loop {
+        let position = Position::new(reader.buffer_position());
+        match reader.read_namespaced_event(&mut buf, &mut namespace_buffer) {
+            Ok((namespace, Event::Start(ref e)))
+                if e.local_name() == b"math" && is_mathml(namespace) =>
+            {
+                for attribute in e.attributes() {
+                    let attribute =
+                        attribute.map_err(|error| ParseError::XmlError { error, position })?;
+                    let value = attribute
+                        .unescape_and_decode_value(reader)
+                        .map_err(|error| ParseError::XmlError { error, position })?;
+                    let (namespace, _) =
+                        reader.attribute_namespace(attribute.key, &namespace_buffer);
+                    attributes.insert_namespaced(
+                        &String::from_utf8_lossy(attribute.key),
+                        namespace.map(String::from_utf8_lossy).as_deref(),
+                        value,
+                    );
+                }
+                break;
+            }
+            Ok((_, Event::Eof)) => return Err(ParseError::UnexpectedEof { position }),
+            Err(error) => return Err(ParseError::XmlError { error, position }),
+            _ => (),
+        }
+        buf.clear();
+    }
+    let mut nodes = parse_fragment_with(reader, &mut namespace_buffer, options)?.into_nodes();
+    *nodes[0].attributes_mut() = attributes;
+    Ok(MathTree::from_nodes(nodes))
+}
+
+/// Parses the contents of a `<math>` element from a reader that has just
+/// consumed its start tag, e.g. while walking a larger SBML document.
+///
+/// Parsing stops after the matching `</math>` end tag, leaving the reader
+/// positioned so that the caller can continue with the rest of the document.
+/// Errors carry the reader's byte offset but no line or column, which can be
+/// filled in with [`ParseError::locate`] if the caller has the source text.
+///
+/// The caller must have read the document with `read_event`. Prefixes that
+/// were declared on the `math` element are then unknown here, so prefixed
+/// elements are taken to be MathML; use [`parse_fragment_with`] to resolve
+/// them properly.
+pub fn parse_fragment<R: BufRead>(reader: &mut Reader<R>) -> Result<MathTree, ParseError> {
+    parse_fragment_with(reader, &mut Vec::new(), &ParseOptions::default())
+}
+
+/// Parses the contents of a `<math>` element from a reader that walks the
+/// document with `read_namespaced_event`.
+///
+/// `namespace_buffer` is the buffer the caller passes to
+/// `read_namespaced_event`, so that elements are resolved against the
+/// namespaces declared on `math` and its ancestors. Elements whose prefix is
+/// not bound, or that are in no namespace at all, are taken to be MathML.
+pub fn parse_fragment_with<R: BufRead>(
+    reader: &mut Reader<R>,
+    namespace_buffer: &mut Vec<u8>,
+    options: &ParseOptions,
+) -> Result<MathTree, ParseError> {
     reader.trim_text(true);
     reader.expand_empty_elements(true);
     let mut buf = Vec::new();
+    // how deep we are inside a skipped foreign element
+    let mut skipped = 0;
     let mut stack: Vec<NodeIndex> = Vec::new();
 
     let mut container = Vec::new();
     let mut container_len = 0;
+    // the text of the current cn, split at its sep elements
+    let mut cn_parts: Vec<String> = Vec::new();
+    // an annotation whose start tag was just read
+    let mut pending_annotation: Option<Annotation> = None;
 
     container.push(MathNode::default());
     container_len += 1;
@@ -36,12 +197,29 @@ pub fn parse_fragment(
     stack.push(current);
 
     loop {
-        match reader.read_event(&mut buf) {
+        let position = Position::new(reader.buffer_position());
+        match reader.read_namespaced_event(&mut buf, namespace_buffer) {
+            Ok((_, Event::Start(_))) if skipped > 0 => skipped += 1,
+            Ok((namespace, Event::Start(ref e))) if !is_mathml(namespace) => {
+                match options.foreign_elements {
+                    ForeignElements::Reject => {
+                        return Err(ParseError::ForeignElement {
+                            name: String::from_utf8_lossy(e.name()).into_owned(),
+                            namespace: String::from_utf8_lossy(namespace.unwrap_or_default())
+                                .into_owned(),
+                            position,
+                        });
+                    }
+                    ForeignElements::Skip => skipped = 1,
+                }
+            }
             // for each starting tag
-            Ok(Event::Start(ref e)) => {
+            Ok((_, Event::Start(ref e))) => {
                 let new_tag;
-                match e.name() {
-                    b"apply" => attach![Apply to Root | Apply | Lambda | Piece | Otherwise],
+                match e.local_name() {
+                    b"apply" => {
+                        attach![Apply to Root | Apply | Lambda | Piece | Otherwise | Qualifier | Semantics | Container]
+                    }
                     b"times" => attach![Op::Times to Apply],
                     b"divide" => attach![Op::Divide to Apply],
                     b"minus" => attach![Op::Minus to Apply],
@@ -94,24 +272,175 @@ pub fn parse_fragment(
                     b"implies" => attach![Op::Implies to Apply],
                     b"ceiling" => attach![Op::Ceiling to Apply],
                     b"floor" => attach![Op::Floor to Apply],
-                    b"true" => attach![Constant::True to Apply | Piece ],
-                    b"false" => attach![Constant::False to Apply | Piece | Otherwise],
-                    b"pi" => attach![Constant::Pi to Apply | Piece | Lambda ],
-                    b"exponentiale" => attach![Constant::ExponentialE to Apply | Piece | Lambda ],
+                    b"conjugate" => attach![Op::Conjugate to Apply],
+                    b"arg" => attach![Op::Arg to Apply],
+                    b"real" => attach![Op::Real to Apply],
+                    b"imaginary" => attach![Op::Imaginary to Apply],
+                    b"inverse" => attach![Op::Inverse to Apply],
+                    b"ident" => attach![Op::Ident to Apply],
+                    b"domain" => attach![Op::Domain to Apply],
+                    b"codomain" => attach![Op::Codomain to Apply],
+                    b"image" => attach![Op::Image to Apply],
+                    b"log" => attach![Op::Log to Apply],
+                    b"determinant" => attach![Op::Determinant to Apply],
+                    b"transpose" => attach![Op::Transpose to Apply],
+                    b"divergence" => attach![Op::Divergence to Apply],
+                    b"grad" => attach![Op::Grad to Apply],
+                    b"curl" => attach![Op::Curl to Apply],
+                    b"laplacian" => attach![Op::Laplacian to Apply],
+                    b"card" => attach![Op::Card to Apply],
+                    b"equivalent" => attach![Op::Equivalent to Apply],
+                    b"approx" => attach![Op::Approx to Apply],
+                    b"setdiff" => attach![Op::Setdiff to Apply],
+                    b"vectorproduct" => attach![Op::Vectorproduct to Apply],
+                    b"scalarproduct" => attach![Op::Scalarproduct to Apply],
+                    b"outerproduct" => attach![Op::Outerproduct to Apply],
+                    b"gcd" => attach![Op::Gcd to Apply],
+                    b"lcm" => attach![Op::Lcm to Apply],
+                    b"mean" => attach![Op::Mean to Apply],
+                    b"sdev" => attach![Op::Sdev to Apply],
+                    b"variance" => attach![Op::Variance to Apply],
+                    b"median" => attach![Op::Median to Apply],
+                    b"mode" => attach![Op::Mode to Apply],
+                    b"selector" => attach![Op::Selector to Apply],
+                    b"union" => attach![Op::Union to Apply],
+                    b"intersect" => attach![Op::Intersect to Apply],
+                    b"cartesianproduct" => attach![Op::Cartesianproduct to Apply],
+                    b"compose" => attach![Op::Compose to Apply],
+                    // deprecated since MathML 2, and only a wrapper around
+                    // the function it applies
+                    b"fn" => {
+                        return Err(ParseError::UnsupportedElement {
+                            name: "fn".to_string(),
+                            hint: "use the function itself as the operator of the apply",
+                            position,
+                        });
+                    }
+                    b"int" => attach![Op::Int to Apply],
+                    b"sum" => attach![Op::Sum to Apply],
+                    b"product" => attach![Op::Product to Apply],
+                    b"diff" => attach![Op::Diff to Apply],
+                    b"partialdiff" => attach![Op::Partialdiff to Apply],
+                    b"forall" => attach![Op::Forall to Apply],
+                    b"exists" => attach![Op::Exists to Apply],
+                    b"root" => attach![Op::Root to Apply],
+                    b"true" => attach![Constant::True
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"false" => attach![Constant::False
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"pi" => attach![Constant::Pi
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"exponentiale" => attach![Constant::ExponentialE
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"eulergamma" => attach![Constant::EulerGamma
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"imaginaryi" => attach![Constant::ImaginaryI
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"infinity" => attach![Constant::Infinity
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"notanumber" => attach![Constant::NotANumber
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
                     b"ci" => {
-                        attach![Ci to Root | Apply | BVar | Piece | Otherwise | Lambda ]
+                        attach![Ci to Root | Apply | BVar | Piece | Otherwise | Lambda | Qualifier | Semantics | Container ]
+                    }
+                    b"csymbol" => attach![Csymbol with
+                                        definition_url = "definitionURL" as String,
+                                        encoding as String,
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container ],
+                    b"cn" => {
+                        cn_parts = vec![String::new()];
+                        attach![Cn with
+                                    r#type as NumType,
+                                    base as u32,
+                                to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container ]
+                    }
+                    b"lambda" => attach![Lambda to Root | Semantics],
+                    b"bvar" => attach![BVar to Lambda | Apply],
+                    b"degree" => attach![Qualifier::Degree to Apply | BVar],
+                    b"logbase" => attach![Qualifier::LogBase to Apply],
+                    b"lowlimit" => attach![Qualifier::LowLimit to Apply],
+                    b"uplimit" => attach![Qualifier::UpLimit to Apply],
+                    b"interval" if takes_domain(&container, current) => {
+                        attach![Qualifier::Interval to Apply]
+                    }
+                    b"vector" | b"matrix" | b"matrixrow" | b"list" | b"set" | b"interval" => {
+                        check_container_child(&container[current], e.local_name(), position)?;
+                        match e.local_name() {
+                            b"vector" => attach![Container::Vector
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                            b"matrix" => attach![Container::Matrix
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                            b"matrixrow" => attach![Container::MatrixRow to Container],
+                            b"list" => attach![Container::List
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                            b"set" => attach![Container::Set
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                            _ => attach![Container::Interval with closure as Closure,
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                        }
+                    }
+                    b"condition" => attach![Qualifier::Condition to Apply],
+                    b"domainofapplication" => attach![Qualifier::DomainOfApplication to Apply],
+                    b"piecewise" => {
+                        attach![Piecewise to Root | Apply | Lambda | Piece | Otherwise | Qualifier | Semantics | Container]
+                    }
+                    b"semantics" => attach![Semantics
+                                    to Root | Apply | Piece | Otherwise | Lambda | Qualifier | Semantics | Container],
+                    b"annotation" | b"annotation-xml" => {
+                        if let MathNode::Semantics(Semantics { expr: Some(..), .. }) =
+                            container[current]
+                        {
+                            let mut annotation = Annotation {
+                                xml: e.local_name() == b"annotation-xml",
+                                ..Default::default()
+                            };
+                            for attribute in e.attributes() {
+                                let attribute = attribute
+                                    .map_err(|error| ParseError::XmlError { error, position })?;
+                                let value = attribute
+                                    .unescape_and_decode_value(reader)
+                                    .map_err(|error| ParseError::XmlError { error, position })?;
+                                let (namespace, _) =
+                                    reader.attribute_namespace(attribute.key, namespace_buffer);
+                                match attribute.key {
+                                    b"encoding" => annotation.encoding = Some(value),
+                                    key => annotation.attributes.insert_namespaced(
+                                        &String::from_utf8_lossy(key),
+                                        namespace.map(String::from_utf8_lossy).as_deref(),
+                                        value,
+                                    ),
+                                }
+                            }
+                            // the content is read once the start tag is released
+                            pending_annotation = Some(annotation);
+                            new_tag = None;
+                        } else {
+                            return Err(ParseError::UnexpectedChild {
+                                parent: container[current].node_type().to_string(),
+                                child: String::from_utf8_lossy(e.name()).into_owned(),
+                                position,
+                            });
+                        }
                     }
-                    b"cn" => attach![Cn with
-                                        r#type as NumType,
-                                    to Root | Apply | BVar | Piece | Otherwise | Lambda ],
-                    b"lambda" => attach![Lambda to Root],
-                    b"bvar" => attach![BVar to Lambda],
-                    b"piecewise" => attach![Piecewise to Root | Apply | Lambda | Piece | Otherwise],
                     b"piece" => attach![Piece to Piecewise],
                     b"otherwise" => attach![Otherwise to Piecewise],
-                    b"sep" => new_tag = None,
+                    b"sep" => {
+                        if let MathNode::Cn(..) = container[current] {
+                            cn_parts.push(String::new());
+                            new_tag = None;
+                        } else {
+                            return Err(ParseError::UnexpectedChild {
+                                parent: container[current].node_type().to_string(),
+                                child: "sep".to_string(),
+                                position,
+                            });
+                        }
+                    }
                     _ => {
-                        panic!("Tag not parsed: {}", std::str::from_utf8(e.name()).unwrap());
+                        return Err(ParseError::UnknownElement {
+                            name: String::from_utf8_lossy(e.name()).into_owned(),
+                            position,
+                        });
                     }
                 }
                 if let Some(t) = new_tag {
@@ -119,7 +448,8 @@ pub fn parse_fragment(
                     container_len += 1;
                 }
             }
-            Ok(Event::End(ref e)) => match e.name() {
+            Ok((_, Event::End(_))) if skipped > 0 => skipped -= 1,
+            Ok((_, Event::End(ref e))) => match e.local_name() {
                 b"apply" => close![Apply],
                 b"times" => close![Op],
                 b"divide" => close![Op],
@@ -173,6 +503,49 @@ pub fn parse_fragment(
                 b"implies" => close![Op],
                 b"ceiling" => close![Op],
                 b"floor" => close![Op],
+                b"conjugate" => close![Op],
+                b"arg" => close![Op],
+                b"real" => close![Op],
+                b"imaginary" => close![Op],
+                b"inverse" => close![Op],
+                b"ident" => close![Op],
+                b"domain" => close![Op],
+                b"codomain" => close![Op],
+                b"image" => close![Op],
+                b"log" => close![Op],
+                b"determinant" => close![Op],
+                b"transpose" => close![Op],
+                b"divergence" => close![Op],
+                b"grad" => close![Op],
+                b"curl" => close![Op],
+                b"laplacian" => close![Op],
+                b"card" => close![Op],
+                b"equivalent" => close![Op],
+                b"approx" => close![Op],
+                b"setdiff" => close![Op],
+                b"vectorproduct" => close![Op],
+                b"scalarproduct" => close![Op],
+                b"outerproduct" => close![Op],
+                b"gcd" => close![Op],
+                b"lcm" => close![Op],
+                b"mean" => close![Op],
+                b"sdev" => close![Op],
+                b"variance" => close![Op],
+                b"median" => close![Op],
+                b"mode" => close![Op],
+                b"selector" => close![Op],
+                b"union" => close![Op],
+                b"intersect" => close![Op],
+                b"cartesianproduct" => close![Op],
+                b"compose" => close![Op],
+                b"int" => close![Op],
+                b"sum" => close![Op],
+                b"product" => close![Op],
+                b"diff" => close![Op],
+                b"partialdiff" => close![Op],
+                b"forall" => close![Op],
+                b"exists" => close![Op],
+                b"root" => close![Op],
                 b"piecewise" => close![Piecewise],
                 b"piece" => close![Piece],
                 b"otherwise" => close![Otherwise],
@@ -180,88 +553,969 @@ pub fn parse_fragment(
                 b"false" => close![Constant],
                 b"pi" => close![Constant],
                 b"exponentiale" => close![Constant],
+                b"eulergamma" => close![Constant],
+                b"imaginaryi" => close![Constant],
+                b"infinity" => close![Constant],
+                b"notanumber" => close![Constant],
                 b"ci" => close![Ci],
-                b"cn" => close![Cn],
+                b"cn" => {
+                    if let MathNode::Cn(ref mut cn) = container[current] {
+                        cn.value = cn_value(cn, &cn_parts, position)?;
+                    }
+                    close![Cn]
+                }
+                b"csymbol" => close![Csymbol],
+                b"semantics" => close![Semantics],
                 b"lambda" => close![Lambda],
                 b"bvar" => close![BVar],
-                b"math" => break,
+                b"degree" => close![Qualifier],
+                b"logbase" => close![Qualifier],
+                b"lowlimit" => close![Qualifier],
+                b"uplimit" => close![Qualifier],
+                b"interval" => match container[current] {
+                    MathNode::Qualifier(..) => close![Qualifier],
+                    _ => close![Container],
+                },
+                b"vector" | b"matrix" | b"matrixrow" | b"list" | b"set" => close![Container],
+                b"condition" => close![Qualifier],
+                b"domainofapplication" => close![Qualifier],
+                b"math" => {
+                    if let Some(missing) = container[current].missing_child() {
+                        return Err(ParseError::IncompleteElement {
+                            element: "math".to_string(),
+                            missing,
+                            position,
+                        });
+                    }
+                    break;
+                }
                 _ => {}
             },
+            Ok((_, Event::Text(_))) if skipped > 0 => {}
             // unescape and decode the text event using the reader encoding
-            Ok(Event::Text(e)) => {
-                let s = e.unescape_and_decode(&reader).unwrap();
+            Ok((_, Event::Text(e))) => {
+                let s = e
+                    .unescape_and_decode(reader)
+                    .map_err(|error| ParseError::XmlError { error, position })?;
                 match container[current] {
-                    MathNode::Ci(..) => {
-                        container[current] = MathNode::Ci(Ci::with_name(s));
+                    MathNode::Ci(ref mut ci) => {
+                        ci.name = Some(s);
                     }
-                    MathNode::Cn(ref mut cn) => match cn.r#type {
-                        Some(NumType::Real) | None => {
-                            let value = s.parse::<f64>().expect("Incorrect type");
-                            cn.value = Some(Number::Real(value));
-                        }
-                        Some(NumType::Integer) => {
-                            let value = s.parse::<i32>().expect("Incorrect type");
-                            cn.value = Some(Number::Integer(value));
-                        }
-                        Some(NumType::Rational) => {
-                            let value = s.parse::<i32>().expect("Incorrect type");
-                            if cn.value.is_none() {
-                                cn.value = Some(Number::Rational(value.into(), 1));
-                            } else if let Some(Number::Rational(x, y)) = cn.value {
-                                if y != 1 {
-                                    panic!("Error occurred while storing rational number.");
-                                }
-                                cn.value = Some(Number::Rational(x, value.into()));
-                            }
-                        }
-
-                        Some(NumType::ENotation) => {
-                            let value = s.parse::<f64>().expect("Incorrect type");
-                            if cn.value.is_none() {
-                                cn.value = Some(Number::ENotation(value.into(), 1));
-                            } else if let Some(Number::ENotation(x, y)) = cn.value {
-                                if y != 1 {
-                                    panic!("Error occurred while storing rational number.");
-                                }
-                                cn.value = Some(Number::ENotation(x, value as i64));
-                            }
-                        }
-                        _ => {
-                            panic!("Math type did not match for cn: {:?}", cn);
+                    MathNode::Csymbol(ref mut csymbol) => {
+                        csymbol.text = Some(s);
+                    }
+                    MathNode::Cn(..) => {
+                        if let Some(part) = cn_parts.last_mut() {
+                            part.push_str(&s);
                         }
-                    },
+                    }
                     _ => {
-                        panic!("Text not parsed in {:?}: {}", container[current], s);
+                        return Err(ParseError::UnexpectedText {
+                            parent: container[current].node_type().to_string(),
+                            text: s,
+                            position,
+                        });
                     }
                 }
             }
-            Ok(Event::Eof) => break, // exits the loop when reaching end of file
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+            // the math element must be closed before the input runs out
+            Ok((_, Event::Eof)) => return Err(ParseError::UnexpectedEof { position }),
+            Err(error) => return Err(ParseError::XmlError { error, position }),
             _ => (), // There are several other `Event`s we do not consider here
         }
+        buf.clear();
+
+        if let Some(mut annotation) = pending_annotation.take() {
+            annotation.content = read_annotation(reader, namespace_buffer)?;
+            if let MathNode::Semantics(ref mut semantics) = container[current] {
+                semantics.annotations.push(annotation);
+            }
+        }
     }
 
-    //println!("");
-    //let mut count = 0;
-    //for item in &container {
-    //println!("{:0>2}: {}", count, item);
-    //count += 1;
-    //}
-    //println!("{:?}", txt);
-    //println!("{:?}", stack);
-    //println!("{:?}", current);
-
-    (container, reader)
+    Ok(MathTree::from_nodes(container))
+}
+
+// Whether an interval opened at this point is the domain of the bound
+// variables of an apply, rather than an operand. An integral may also take
+// its domain without a bound variable.
+fn takes_domain(container: &[MathNode], current: NodeIndex) -> bool {
+    match &container[current] {
+        MathNode::Apply(apply) if apply.operands.is_empty() => {
+            !apply.bvars.is_empty()
+                || matches!(
+                    apply.operator.map(|operator| &container[operator]),
+                    Some(MathNode::Op(OpNode {
+                        op: Some(Op::Int),
+                        ..
+                    }))
+                )
+        }
+        _ => false,
+    }
+}
+
+// Matrices hold matrix rows, and matrix rows belong in matrices.
+fn check_container_child(
+    parent: &MathNode,
+    child: &[u8],
+    position: Position,
+) -> Result<(), ParseError> {
+    let in_matrix = matches!(
+        parent,
+        MathNode::Container(ContainerNode {
+            container: Some(Container::Matrix),
+            ..
+        })
+    );
+    if in_matrix == (child == b"matrixrow") {
+        Ok(())
+    } else {
+        Err(ParseError::UnexpectedChild {
+            parent: parent.node_type().to_string(),
+            child: String::from_utf8_lossy(child).into_owned(),
+            position,
+        })
+    }
+}
+
+// Reads the content of an annotation up to its end tag as raw XML. Text and
+// empty elements are read as written for the duration, so the content only
+// differs from the input in whitespace inside end tags.
+fn read_annotation<R: BufRead>(
+    reader: &mut Reader<R>,
+    namespace_buffer: &mut Vec<u8>,
+) -> Result<String, ParseError> {
+    reader.trim_text(false).expand_empty_elements(false);
+    let content = read_raw_content(reader, namespace_buffer);
+    reader.trim_text(true).expand_empty_elements(true);
+    content
+}
+
+fn read_raw_content<R: BufRead>(
+    reader: &mut Reader<R>,
+    namespace_buffer: &mut Vec<u8>,
+) -> Result<String, ParseError> {
+    let mut buf = Vec::new();
+    let mut writer = quick_xml::Writer::new(Vec::new());
+    let mut depth = 0;
+    loop {
+        let position = Position::new(reader.buffer_position());
+        let event = match reader.read_namespaced_event(&mut buf, namespace_buffer) {
+            Ok((_, Event::End(_))) if depth == 0 => break,
+            Ok((_, Event::Eof)) => return Err(ParseError::UnexpectedEof { position }),
+            Ok((_, event)) => event,
+            Err(error) => return Err(ParseError::XmlError { error, position }),
+        };
+        match event {
+            Event::Start(..) => depth += 1,
+            Event::End(..) => depth -= 1,
+            _ => (),
+        }
+        writer
+            .write_event(event)
+            .map_err(|error| ParseError::XmlError { error, position })?;
+        buf.clear();
+    }
+    Ok(String::from_utf8_lossy(&writer.into_inner()).into_owned())
+}
+
+// Elements in no namespace are taken to be MathML, as many documents leave
+// out the declaration.
+fn is_mathml(namespace: Option<&[u8]>) -> bool {
+    namespace.map_or(true, |namespace| namespace == MATHML_NAMESPACE.as_bytes())
+}
+
+// Parses the collected text of a cn. An empty cn has no value, which is
+// reported when it is closed.
+fn cn_value(cn: &Cn, parts: &[String], position: Position) -> Result<Option<Number>, ParseError> {
+    let base = cn.base.unwrap_or(10);
+    if !(2..=36).contains(&base) {
+        return Err(ParseError::InvalidAttribute {
+            element: "cn".to_string(),
+            name: "base".to_string(),
+            value: base.to_string(),
+            position,
+        });
+    }
+    let parts: Vec<&str> = parts.iter().map(|part| part.trim()).collect();
+    if parts.iter().all(|part| part.is_empty()) {
+        return Ok(None);
+    }
+    let r#type = cn.r#type.as_ref().unwrap_or(&NumType::Real);
+    match Number::parse(r#type, base, &parts) {
+        Some(number) => Ok(Some(number)),
+        None => Err(ParseError::InvalidNumber {
+            text: parts.join("<sep/>"),
+            position,
+        }),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryFrom;
+    #[test]
+    fn parse_fragment_from_sbml_model() {
+        let filename = "../models/small.xml";
+        let mut reader = Reader::from_file(filename).expect("File error.");
+
+        // walk the surrounding SBML until the kinetic law's <math> element
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(Event::Start(ref e)) if e.name() == b"math" => break,
+                Ok(Event::Eof) => panic!("No math element found"),
+                _ => (),
+            }
+        }
+
+        let tree = parse_fragment(&mut reader).unwrap();
+        assert_eq!(tree.len(), 5);
+
+        // the reader is left just after </math>
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(Event::End(ref e)) => {
+                    assert_eq!(e.name(), b"kineticLaw");
+                    break;
+                }
+                Ok(Event::Eof) => panic!("Reader was not handed back"),
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_from_memory() {
+        let math = r#"<?xml version="1.0"?>
+            <math xmlns="http://www.w3.org/1998/Math/MathML">
+                <apply><plus/><ci>a</ci><cn type="integer">2</cn></apply>
+            </math>"#;
+
+        let from_str = parse_str(math).unwrap();
+        let from_bytes = parse_bytes(math.as_bytes()).unwrap();
+        let from_reader = parse_reader(std::io::Cursor::new(math)).unwrap();
+        let skipping = ParseOptions::skip_foreign();
+        let from_reader_with =
+            parse_reader_with(std::io::BufReader::new(math.as_bytes()), &skipping).unwrap();
+
+        assert_eq!(from_str.len(), 5);
+        assert_eq!(from_bytes.len(), 5);
+        assert_eq!(from_reader.len(), 5);
+        assert_eq!(from_reader_with.len(), 5);
+
+        // errors from a stream only know their byte offset
+        match parse_reader(std::io::Cursor::new("<math><apply><foo/></apply></math>")) {
+            Err(ParseError::UnknownElement { position, .. }) => {
+                assert_eq!((position.line, position.column), (None, None))
+            }
+            other => panic!("expected an unknown element, got {:?}", other.ok()),
+        }
+        assert_eq!(from_str.ci_name(3), Some("a"));
+
+        let values = std::collections::HashMap::from([("a".to_string(), 1.5)]);
+        let result = eval_f64(from_str.nodes(), 0, &values, &Default::default());
+        assert_eq!(result, Ok(3.5));
+    }
+
+    #[test]
+    fn errors_instead_of_panics() {
+        let unknown = "<math>\n  <apply>\n    <frobnicate/>\n  </apply>\n</math>";
+        match parse_str(unknown) {
+            Err(ParseError::UnknownElement { name, position }) => {
+                assert_eq!(name, "frobnicate");
+                assert_eq!(position.offset, 21);
+                assert_eq!(position.line, Some(3));
+                assert_eq!(position.column, Some(5));
+            }
+            other => panic!("Expected UnknownElement, got {:?}", other),
+        }
+
+        let wrapped = "<math><apply><fn><ci>f</ci></fn><ci>x</ci></apply></math>";
+        match parse_str(wrapped) {
+            Err(error @ ParseError::UnsupportedElement { .. }) => assert_eq!(
+                error.to_string(),
+                "unsupported element <fn> at line 1, column 14: \
+                 use the function itself as the operator of the apply"
+            ),
+            other => panic!("Expected UnsupportedElement, got {:?}", other),
+        }
+
+        let misplaced = "<math><piecewise><ci>x</ci></piecewise></math>";
+        assert!(matches!(
+            parse_str(misplaced),
+            Err(ParseError::UnexpectedChild { ref parent, ref child, .. })
+                if parent == "Piecewise" && child == "ci"
+        ));
+
+        let bad_number = "<math><cn type=\"integer\">1.5</cn></math>";
+        assert!(matches!(
+            parse_str(bad_number),
+            Err(ParseError::InvalidNumber { ref text, .. }) if text == "1.5"
+        ));
+
+        let bad_attribute = "<math><cn type=\"fraction\">1</cn></math>";
+        assert!(matches!(
+            parse_str(bad_attribute),
+            Err(ParseError::InvalidAttribute { ref name, .. }) if name == "type"
+        ));
+
+        let truncated = "<math><apply><plus/><ci>x</ci>";
+        assert!(matches!(
+            parse_str(truncated),
+            Err(ParseError::UnexpectedEof { .. })
+        ));
+
+        let malformed = "<math><apply></ci></math>";
+        assert!(matches!(
+            parse_str(malformed),
+            Err(ParseError::XmlError { .. })
+        ));
+    }
+
+    #[test]
+    fn incomplete_elements_are_rejected() {
+        for math in [
+            "<math></math>",
+            "<math><apply></apply></math>",
+            "<math><ci></ci></math>",
+            "<math><lambda><bvar><ci>x</ci></bvar></lambda></math>",
+            "<math><piecewise><piece><cn>1</cn></piece></piecewise></math>",
+        ] {
+            assert!(
+                matches!(parse_str(math), Err(ParseError::IncompleteElement { .. })),
+                "{}",
+                math
+            );
+        }
+        assert!(matches!(
+            parse_str("<math><ci>x</ci><ci>y</ci></math>"),
+            Err(ParseError::UnexpectedChild { .. })
+        ));
+    }
+
+    #[test]
+    fn every_operator_parses() {
+        for name in [
+            "log",
+            "root",
+            "gcd",
+            "lcm",
+            "conjugate",
+            "arg",
+            "real",
+            "imaginary",
+            "sum",
+            "product",
+            "int",
+            "diff",
+            "partialdiff",
+            "mean",
+            "sdev",
+            "variance",
+            "median",
+            "mode",
+            "card",
+            "union",
+            "intersect",
+            "setdiff",
+            "compose",
+            "inverse",
+            "ident",
+            "domain",
+            "codomain",
+            "image",
+            "determinant",
+            "transpose",
+            "selector",
+            "forall",
+            "exists",
+            "equivalent",
+            "approx",
+            "curl",
+            "grad",
+            "divergence",
+            "laplacian",
+            "vectorproduct",
+            "scalarproduct",
+            "outerproduct",
+            "cartesianproduct",
+        ] {
+            let math = format!("<math><apply><{}/><ci>x</ci></apply></math>", name);
+            let tree = parse_str(&math).unwrap();
+            let op = tree.op_of(tree.expr().unwrap()).unwrap();
+            assert_eq!(op.name(), name);
+            assert_eq!(
+                to_mathml(&tree),
+                to_mathml(&parse_str(&to_mathml(&tree)).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn qualifiers() {
+        let math = "<math><apply><plus/>
+                <apply><root/><degree><cn type=\"integer\">3</cn></degree><ci>x</ci></apply>
+                <apply><log/><logbase><cn type=\"integer\">2</cn></logbase><ci>x</ci></apply>
+                <apply><int/><bvar><ci>t</ci></bvar>
+                    <lowlimit><cn>0</cn></lowlimit><uplimit><ci>T</ci></uplimit>
+                    <apply><ci>f</ci><ci>t</ci></apply>
+                </apply>
+                <apply><diff/><bvar><ci>t</ci><degree><cn type=\"integer\">2</cn></degree></bvar>
+                    <ci>y</ci>
+                </apply>
+            </apply></math>";
+        let tree = parse_str(math).unwrap();
+        let operands = tree.operands_of(tree.expr().unwrap()).to_vec();
+        let (root, log, int, diff) = (operands[0], operands[1], operands[2], operands[3]);
+
+        let degree = tree.qualifier_value_of(root, &Qualifier::Degree).unwrap();
+        assert_eq!(tree.cn_value(degree), Some(&Number::Integer(3)));
+        assert_eq!(tree.operands_of(root).len(), 1);
+        let base = tree.qualifier_value_of(log, &Qualifier::LogBase).unwrap();
+        assert_eq!(tree.cn_value(base), Some(&Number::Integer(2)));
+        assert_eq!(tree.bound_variables_of(int), ["t"]);
+        assert_eq!(tree.qualifiers_of(int).len(), 2);
+        let upper = tree.qualifier_value_of(int, &Qualifier::UpLimit).unwrap();
+        assert_eq!(tree.ci_name(upper), Some("T"));
+        let bvar = tree.bvars_of(diff)[0];
+        assert!(tree.qualifier_value_of(bvar, &Qualifier::Degree).is_some());
+
+        // qualifiers survive the conversions and the writer
+        let expr = Expr::try_from(&tree).unwrap();
+        assert_eq!(
+            format!("{:?}", MathTree::from(&expr).nodes()),
+            format!("{:?}", tree.nodes())
+        );
+        let written = parse_str(&to_mathml(&tree)).unwrap();
+        assert_eq!(Expr::try_from(&written).unwrap(), expr);
+
+        let formula = parse_formula("root(3, x) + log(2, x) - sqrt(log(x))").unwrap();
+        assert_eq!(to_infix(&formula), "root(3, x) + log(2, x) - sqrt(log(x))");
+        assert_eq!(
+            Expr::try_from(&formula).unwrap().to_string(),
+            "root(3, x) + log(2, x) - sqrt(log(x))"
+        );
+        assert_eq!(
+            to_latex(&tree),
+            "\\sqrt[3]{x} + \\log_{2}\\left(x\\right) + \\left(\\int_{0}^{T} f\\left(t\\right) \\, dt\\right) \
+             + \\frac{d^{2}}{dt^{2}} y"
+        );
+
+        let misplaced = "<math><apply><root/><ci>x</ci><degree><cn>3</cn></degree></apply></math>";
+        assert!(matches!(
+            parse_str(misplaced),
+            Err(ParseError::UnexpectedChild { ref child, .. }) if child == "degree"
+        ));
+    }
+
+    #[test]
+    fn thread_safety() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MathTree>();
+        assert_send_sync::<MathNode>();
+        assert_send_sync::<Expr>();
+        assert_send_sync::<Value>();
+        assert_send_sync::<EvalError>();
+        assert_send_sync::<SymbolTable>();
+        assert_send_sync::<CompiledExpr>();
+        assert_send_sync::<SbmlContext>();
+        assert_send_sync::<MapEnvironment>();
+    }
+
     #[test]
-    fn it_works() {
-        let filename = "../../testsuites/core-semantic/00004/00004-sbml-l3v2.xml";
-        let reader = Reader::from_file(filename).expect("File error.");
+    fn number_types() {
+        let value = |cn: &str| {
+            let tree = parse_str(&format!("<math>{}</math>", cn))?;
+            Ok(tree.cn_value(tree.expr().unwrap()).cloned().unwrap())
+        };
+        let cases = [
+            ("<cn base=\"16\">FF.8</cn>", Number::Real(255.5)),
+            (
+                "<cn type=\"integer\" base=\"2\">-101</cn>",
+                Number::Integer(-5),
+            ),
+            (
+                "<cn type=\"integer\">9007199254740993</cn>",
+                Number::Integer(9007199254740993),
+            ),
+            (
+                "<cn type=\"rational\" base=\"16\">A<sep/>F</cn>",
+                Number::Rational(10, 15),
+            ),
+            (
+                "<cn type=\"complex-cartesian\">1.5<sep/>-2</cn>",
+                Number::ComplexCartesian(1.5, -2.0),
+            ),
+            (
+                "<cn type=\"complex-polar\">2<sep/>0.75</cn>",
+                Number::ComplexPolar(2.0, 0.75),
+            ),
+            (
+                "<cn type=\"e-notation\">1.5<sep/>-3</cn>",
+                Number::ENotation(1.5, -3),
+            ),
+            (
+                "<cn type=\"constant\">π</cn>",
+                Number::Constant("π".to_string()),
+            ),
+            ("<cn> 2.5e3 </cn>", Number::Real(2500.0)),
+            (
+                "<cn type=\"hexdouble\">400921FB54442D18</cn>",
+                Number::Real(std::f64::consts::PI),
+            ),
+            (
+                "<cn type=\"hexdouble\"> c000000000000000 </cn>",
+                Number::Real(-2.0),
+            ),
+        ];
+        for (cn, expected) in cases.iter() {
+            assert_eq!(value(cn).ok().as_ref(), Some(expected), "{}", cn);
+        }
+
+        assert!(matches!(
+            value("<cn type=\"rational\">1</cn>"),
+            Err(ParseError::InvalidNumber { ref text, .. }) if text == "1"
+        ));
+        assert!(matches!(
+            value("<cn type=\"rational\">1<sep/>0</cn>"),
+            Err(ParseError::InvalidNumber { ref text, .. }) if text == "1<sep/>0"
+        ));
+        assert!(matches!(
+            value("<cn type=\"integer\">99999999999999999999</cn>"),
+            Err(ParseError::InvalidNumber { .. })
+        ));
+        assert!(matches!(
+            value("<cn type=\"hexdouble\">7F800000</cn>"),
+            Err(ParseError::InvalidNumber { .. })
+        ));
+
+        // hexdoubles keep their bits through evaluation and writing
+        let tree = parse_str("<math><cn type=\"hexdouble\">7FF0000000000000</cn></math>").unwrap();
+        let result = eval_f64(
+            tree.nodes(),
+            tree.root(),
+            &Default::default(),
+            &Default::default(),
+        );
+        assert_eq!(result, Ok(f64::INFINITY));
+        assert!(to_mathml(&tree).contains(r#"<cn type="hexdouble">7FF0000000000000</cn>"#));
+        assert!(matches!(
+            value("<cn base=\"37\">1</cn>"),
+            Err(ParseError::InvalidAttribute { ref name, .. }) if name == "base"
+        ));
+        assert!(matches!(
+            value("<ci>x<sep/>y</ci>"),
+            Err(ParseError::UnexpectedChild { ref child, .. }) if child == "sep"
+        ));
+
+        // every form is written back in base 10 and reads back the same,
+        // except for the base
+        for (cn, _) in cases.iter() {
+            let tree = parse_str(&format!("<math>{}</math>", cn)).unwrap();
+            let mut expr = Expr::try_from(&tree).unwrap();
+            if let Expr::Cn { base, .. } = &mut expr {
+                *base = None;
+            }
+            assert_eq!(
+                Expr::try_from(&parse_str(&to_mathml(&tree)).unwrap()).unwrap(),
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn attributes() {
+        let doc = r#"<math xmlns="http://www.w3.org/1998/Math/MathML" id="m">
+            <apply id="a" class="c">
+                <plus/>
+                <cn sbml:units="mole" type="integer">1</cn>
+                <ci definitionURL="u" xmlns:x="urn:x" x:note="n">x</ci>
+            </apply>
+        </math>"#;
+        let tree = parse_str(doc).unwrap();
+        let root = tree.root();
+        assert_eq!(tree.attributes(root).unwrap().id.as_deref(), Some("m"));
+        let apply = tree.children(root)[0];
+        let attributes = tree.attributes(apply).unwrap();
+        assert_eq!(attributes.id.as_deref(), Some("a"));
+        assert_eq!(attributes.class.as_deref(), Some("c"));
+        let args = tree.children(apply);
+        let cn = tree.attributes(args[1]).unwrap();
+        assert_eq!(cn.units(), Some("mole"));
+        assert!(cn.others.is_empty());
+        assert_eq!(
+            tree.attributes(args[2]).unwrap().get("definitionURL"),
+            Some("u")
+        );
+        assert_eq!(tree.ci_name(args[2]), Some("x"));
+
+        // an unbound prefix stays unbound, as it was in the input
+        let written = to_mathml(&tree);
+        assert!(written.contains(r#"<math xmlns="http://www.w3.org/1998/Math/MathML" id="m">"#));
+        assert!(written.contains(r#"<apply id="a" class="c">"#));
+        assert!(written.contains(r#"<cn type="integer" sbml:units="mole">1</cn>"#));
+        assert!(written.contains(r#"<ci xmlns:x="urn:x" definitionURL="u" x:note="n">x</ci>"#));
+        let reparsed = parse_str(&written).unwrap();
+        let apply = reparsed.children(reparsed.root())[0];
+        assert_eq!(
+            reparsed.attributes(apply),
+            tree.attributes(tree.children(root)[0])
+        );
+
+        // units keep the prefix and namespace they were written with, and a
+        // prefix declared outside of the math element is declared on it
+        let level3_version1 = "http://www.sbml.org/sbml/level3/version1/core";
+        let outside = parse_str(&format!(
+            r#"<sbml xmlns="http://www.sbml.org/sbml/level3/version1/core" xmlns:s="{}">
+                <math xmlns="http://www.w3.org/1998/Math/MathML">
+                    <apply><plus/><cn s:units="second">1</cn><cn s:units="mole">2</cn></apply>
+                </math>
+            </sbml>"#,
+            level3_version1
+        ))
+        .unwrap();
+        let units = tree_units(&outside);
+        assert_eq!(units.name, "s:units");
+        assert_eq!(units.namespace.as_deref(), Some(level3_version1));
+        let written = to_mathml(&outside);
+        assert_eq!(written.matches("xmlns:s=").count(), 1);
+        assert!(written.contains(&format!(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML" xmlns:s="{}">"#,
+            level3_version1
+        )));
+        assert_eq!(tree_units(&parse_str(&written).unwrap()), units);
+
+        // a declaration inside the math element is kept instead of added
+        let declared = parse_str(&format!(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML" xmlns:sbml="{}">
+                <cn sbml:units="second">1</cn>
+            </math>"#,
+            level3_version1
+        ))
+        .unwrap();
+        assert_eq!(to_mathml(&declared).matches("xmlns:sbml").count(), 1);
+        assert_eq!(
+            tree_units(&parse_str(&to_mathml(&declared)).unwrap())
+                .namespace
+                .as_deref(),
+            Some(level3_version1)
+        );
 
-        parse_fragment(reader);
+        // units from other namespaces are just other attributes
+        let foreign =
+            parse_str(r#"<math xmlns:x="urn:x"><cn x:units="furlong">1</cn></math>"#).unwrap();
+        let cn = foreign.attributes(foreign.expr().unwrap()).unwrap();
+        assert_eq!(cn.units, None);
+        assert_eq!(cn.get("x:units"), Some("furlong"));
+
+        // every prefix in the output is bound, whatever the element prefix
+        for written in [
+            write_mathml(&outside, &WriteOptions::compact().with_prefix("m")),
+            to_mathml(&outside),
+            to_mathml(&declared),
+        ] {
+            let mut reader = Reader::from_str(&written);
+            let mut buf = Vec::new();
+            let mut namespace_buffer = Vec::new();
+            loop {
+                match reader.read_namespaced_event(&mut buf, &mut namespace_buffer) {
+                    Ok((namespace, Event::Start(ref e))) => {
+                        assert!(namespace.is_some(), "{}", written);
+                        for attribute in e.attributes() {
+                            let key = attribute.unwrap().key;
+                            if key.contains(&b':') && !key.starts_with(b"xmlns") {
+                                let (namespace, _) =
+                                    reader.attribute_namespace(key, &namespace_buffer);
+                                assert!(namespace.is_some(), "{}", written);
+                            }
+                        }
+                    }
+                    Ok((_, Event::Eof)) => break,
+                    Err(error) => panic!("{}: {}", error, written),
+                    _ => (),
+                }
+                buf.clear();
+            }
+        }
+    }
+
+    // The units of the first number in a tree.
+    fn tree_units(tree: &MathTree) -> QualifiedAttribute {
+        let cn = tree
+            .nodes()
+            .iter()
+            .find(|node| matches!(node, MathNode::Cn(..)))
+            .unwrap();
+        cn.attributes().units.clone().unwrap()
+    }
+
+    #[test]
+    fn namespaces() {
+        let plain = parse_str(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+                <apply><plus/><ci>a</ci><cn>1</cn></apply>
+            </math>"#,
+        )
+        .unwrap();
+        let prefixed = parse_str(
+            r#"<m:math xmlns:m="http://www.w3.org/1998/Math/MathML">
+                <m:apply><m:plus/><m:ci>a</m:ci><m:cn>1</m:cn></m:apply>
+            </m:math>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Expr::try_from(&prefixed).unwrap(),
+            Expr::try_from(&plain).unwrap()
+        );
+
+        // a math element from another namespace is not the one we look for
+        let shadowed = parse_str(
+            r#"<doc xmlns:o="urn:other" xmlns:m="http://www.w3.org/1998/Math/MathML">
+                <o:math><o:x/></o:math>
+                <m:math><m:apply><m:plus/><m:ci>a</m:ci><m:cn>1</m:cn></m:apply></m:math>
+            </doc>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Expr::try_from(&shadowed).unwrap(),
+            Expr::try_from(&plain).unwrap()
+        );
+
+        let foreign = r#"<math xmlns="http://www.w3.org/1998/Math/MathML" xmlns:x="urn:x">
+                <apply><plus/><ci>a</ci><x:note><x:ci>b</x:ci>text</x:note><cn>1</cn></apply>
+            </math>"#;
+        match parse_str(foreign) {
+            Err(ParseError::ForeignElement {
+                name, namespace, ..
+            }) => {
+                assert_eq!(name, "x:note");
+                assert_eq!(namespace, "urn:x");
+            }
+            other => panic!("expected a foreign element error, got {:?}", other.ok()),
+        }
+        let skipped = parse_str_with(foreign, &ParseOptions::skip_foreign()).unwrap();
+        assert_eq!(
+            Expr::try_from(&skipped).unwrap(),
+            Expr::try_from(&plain).unwrap()
+        );
+
+        // an SBML document walked with namespace resolution, handing over
+        // the namespace buffer so the prefix declared above math resolves
+        let sbml = r#"<sbml xmlns="http://www.sbml.org/sbml/level3/version2/core"
+                xmlns:m="http://www.w3.org/1998/Math/MathML">
+            <kineticLaw>
+                <m:math><m:apply><m:plus/><m:ci>a</m:ci><m:cn>1</m:cn></m:apply></m:math>
+            </kineticLaw>
+        </sbml>"#;
+        let mut reader = Reader::from_str(sbml);
+        let mut buf = Vec::new();
+        let mut namespace_buffer = Vec::new();
+        loop {
+            match reader.read_namespaced_event(&mut buf, &mut namespace_buffer) {
+                Ok((Some(namespace), Event::Start(ref e)))
+                    if namespace == MATHML_NAMESPACE.as_bytes() && e.local_name() == b"math" =>
+                {
+                    break
+                }
+                Ok((_, Event::Eof)) => panic!("No math element found"),
+                _ => (),
+            }
+            buf.clear();
+        }
+        let tree =
+            parse_fragment_with(&mut reader, &mut namespace_buffer, &ParseOptions::default())
+                .unwrap();
+        assert_eq!(
+            Expr::try_from(&tree).unwrap(),
+            Expr::try_from(&plain).unwrap()
+        );
+        buf.clear();
+        match reader.read_namespaced_event(&mut buf, &mut namespace_buffer) {
+            Ok((_, Event::End(ref e))) => assert_eq!(e.name(), b"kineticLaw"),
+            _ => panic!("Reader was not handed back"),
+        }
+    }
+
+    #[test]
+    fn semantics() {
+        let math = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+            <apply><times/>
+                <cn>2</cn>
+                <semantics id="s">
+                    <apply><plus/><ci>a</ci><cn>1</cn></apply>
+                    <annotation encoding="text/plain">a + 1 &lt; b</annotation>
+                    <annotation-xml encoding="MathML-Presentation">
+                        <mrow><mi>a</mi><mo>+</mo><mn>1</mn><!-- one --><mspace /></mrow>
+                    </annotation-xml>
+                </semantics>
+            </apply>
+        </math>"#;
+        let tree = parse_str(math).unwrap();
+        let idx = tree.operands_of(tree.expr().unwrap())[1];
+        let semantics = tree.as_semantics(idx).unwrap();
+        assert_eq!(semantics.annotations.len(), 2);
+        let text = semantics.annotation("text/plain").unwrap();
+        assert!(!text.xml);
+        assert_eq!(text.content, "a + 1 &lt; b");
+        let presentation = semantics.annotation("MathML-Presentation").unwrap();
+        assert!(presentation.xml);
+        assert_eq!(
+            presentation.content,
+            "
+                        <mrow><mi>a</mi><mo>+</mo><mn>1</mn><!-- one --><mspace /></mrow>
+                    "
+        );
+
+        // evaluation and the text forms look through the annotations
+        let mut values = std::collections::HashMap::new();
+        values.insert("a".to_string(), 2.0);
+        let functions = std::collections::HashMap::new();
+        assert_eq!(
+            eval_f64(tree.nodes(), tree.root(), &values, &functions),
+            Ok(6.0)
+        );
+        assert_eq!(to_infix(&tree), "2.0*(a + 1.0)");
+
+        // the annotations are written back and survive the Expr form
+        let written = to_mathml(&tree);
+        assert!(written.contains(r#"<annotation encoding="text/plain">a + 1 &lt; b</annotation>"#));
+        assert!(written.contains(&format!(
+            r#"<annotation-xml encoding="MathML-Presentation">{}</annotation-xml>"#,
+            presentation.content
+        )));
+        let reparsed = parse_str(&written).unwrap();
+        assert_eq!(
+            Expr::try_from(&reparsed).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+        assert_eq!(
+            Expr::try_from(&MathTree::from(Expr::try_from(&tree).unwrap())).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+
+        // an annotated function definition can be called like a plain one
+        let double = parse_str(
+            r#"<math>
+                <semantics>
+                    <lambda><bvar><ci>x</ci></bvar><apply><times/><cn>2</cn><ci>x</ci></apply></lambda>
+                    <annotation encoding="text/plain">lambda(x, 2 * x)</annotation>
+                </semantics>
+            </math>"#,
+        )
+        .unwrap();
+        let functions =
+            std::collections::HashMap::from([("double".to_string(), double.into_nodes())]);
+        let call = parse_formula("double(x) + 1").unwrap();
+        let values = std::collections::HashMap::from([("x".to_string(), 3.0)]);
+        assert_eq!(
+            eval_f64(call.nodes(), call.root(), &values, &functions),
+            Ok(7.0)
+        );
+        let symbols = SymbolTable::from_variables(&["x"])
+            .with_function("double", functions["double"].clone());
+        assert_eq!(compile(&call, &symbols).unwrap().eval(&[3.0]), 7.0);
+
+        // annotations belong to a semantics element, after its expression
+        assert!(matches!(
+            parse_str("<math><apply><plus/><annotation>x</annotation></apply></math>"),
+            Err(ParseError::UnexpectedChild { .. })
+        ));
+        assert!(matches!(
+            parse_str("<math><semantics><annotation>x</annotation><ci>a</ci></semantics></math>"),
+            Err(ParseError::UnexpectedChild { .. })
+        ));
+    }
+
+    #[test]
+    fn containers() {
+        let math = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+            <list>
+                <vector><ci>x</ci><cn>1</cn></vector>
+                <matrix>
+                    <matrixrow><cn>1</cn><cn>0</cn></matrixrow>
+                    <matrixrow><cn>0</cn><cn>1</cn></matrixrow>
+                </matrix>
+                <set><ci>a</ci><ci>b</ci></set>
+                <interval closure="open-closed"><cn>0</cn><ci>t</ci></interval>
+            </list>
+        </math>"#;
+        let tree = parse_str(math).unwrap();
+        let list = tree.as_container(tree.expr().unwrap()).unwrap();
+        assert_eq!(list.container, Some(Container::List));
+        let kinds: Vec<_> = list
+            .children
+            .iter()
+            .map(|&child| tree.as_container(child).unwrap().container.clone().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Container::Vector,
+                Container::Matrix,
+                Container::Set,
+                Container::Interval
+            ]
+        );
+        let matrix = tree.as_container(list.children[1]).unwrap();
+        assert_eq!(matrix.children.len(), 2);
+        let interval = tree.as_container(list.children[3]).unwrap();
+        assert_eq!(interval.closure, Some(Closure::OpenClosed));
+
+        assert_eq!(
+            to_latex(&tree),
+            "\\left[\\left(x, 1\\right), \\begin{pmatrix} 1 & 0 \\\\ 0 & 1 \\end{pmatrix}, \\left\\{a, b\\right\\}, \\left(0, t\\right]\\right]"
+        );
+        let written = to_mathml(&tree);
+        assert!(written.contains(r#"<interval closure="open-closed">"#));
+        let reparsed = parse_str(&written).unwrap();
+        assert_eq!(
+            Expr::try_from(&reparsed).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+        assert_eq!(
+            Expr::try_from(&MathTree::from(Expr::try_from(&tree).unwrap())).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+
+        // an interval after bound variables is still the domain of an apply,
+        // but one among the operands is a value
+        let integral = parse_str(
+            "<math><apply><int/><bvar><ci>x</ci></bvar>\
+                <interval><cn>0</cn><cn>1</cn></interval><ci>x</ci></apply></math>",
+        )
+        .unwrap();
+        assert!(integral.interval_of(integral.expr().unwrap()).is_some());
+        let union = parse_str(
+            "<math><apply><union/><interval><cn>0</cn><cn>1</cn></interval>\
+                <interval><cn>2</cn><cn>3</cn></interval></apply></math>",
+        )
+        .unwrap();
+        let operands = union.operands_of(union.expr().unwrap());
+        assert_eq!(operands.len(), 2);
+        assert!(union.as_container(operands[0]).is_some());
+
+        for bad in [
+            "<math><vector><matrixrow><cn>1</cn></matrixrow></vector></math>",
+            "<math><matrix><vector><cn>1</cn></vector></matrix></math>",
+            "<math><matrix><cn>1</cn></matrix></math>",
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                parse_str(bad),
+                Err(ParseError::UnexpectedChild { .. })
+            ));
+        }
+        assert!(matches!(
+            parse_str("<math><interval><cn>1</cn></interval></math>"),
+            Err(ParseError::IncompleteElement { .. })
+        ));
+        assert!(matches!(
+            parse_str(r#"<math><interval closure="half"><cn>0</cn><cn>1</cn></interval></math>"#),
+            Err(ParseError::InvalidAttribute { .. })
+        ));
     }
 }
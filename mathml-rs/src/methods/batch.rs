@@ -0,0 +1,194 @@
+use super::compile::{truth, Binary, CompiledExpr, Instruction, Unary};
+
+// Rows are evaluated in chunks of this size, which keeps the columns of
+// intermediate results in cache.
+pub(crate) const CHUNK: usize = 256;
+
+impl CompiledExpr {
+    /// Evaluates the expression on many rows of inputs at once, writing the
+    /// value for each row to `output`.
+    ///
+    /// The inputs are column-major: `columns[slot][row]` is the value of the
+    /// variable in `slot` for `row`. Each instruction runs over a whole chunk
+    /// of rows before the next one, and a piecewise expression computes all
+    /// of its branches and picks one per row.
+    ///
+    /// # Panics
+    ///
+    /// If a column the expression uses is missing or shorter than `output`.
+    pub fn eval_batch(&self, columns: &[&[f64]], output: &mut [f64]) {
+        let chunk = CHUNK.min(output.len());
+        let mut registers = vec![0.0; (self.max_stack + self.locals) * chunk];
+        for (i, output) in output.chunks_mut(CHUNK).enumerate() {
+            let start = i * CHUNK;
+            self.run_chunk(columns, start, &mut registers, chunk, output);
+        }
+    }
+
+    // Runs the instructions on the rows from `start` on, with the stack
+    // and the locals held in registers of `chunk` values each.
+    fn run_chunk(
+        &self,
+        columns: &[&[f64]],
+        start: usize,
+        registers: &mut [f64],
+        chunk: usize,
+        output: &mut [f64],
+    ) {
+        let len = output.len();
+        let mut top = 0;
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::Constant(value) => {
+                    register(registers, chunk, len, top).fill(value);
+                    top += 1;
+                }
+                Instruction::Input(slot) => {
+                    register(registers, chunk, len, top)
+                        .copy_from_slice(&columns[slot][start..start + len]);
+                    top += 1;
+                }
+                Instruction::Local(slot) => {
+                    let (target, source) = pair(registers, chunk, len, top, self.max_stack + slot);
+                    target.copy_from_slice(source);
+                    top += 1;
+                }
+                Instruction::Store(slot) => {
+                    top -= 1;
+                    let (target, source) = pair(registers, chunk, len, self.max_stack + slot, top);
+                    target.copy_from_slice(source);
+                }
+                Instruction::Unary(op) => op.apply_all(register(registers, chunk, len, top - 1)),
+                Instruction::Binary(op) => {
+                    top -= 1;
+                    let (a, b) = pair(registers, chunk, len, top - 1, top);
+                    op.apply_all(a, b);
+                }
+                Instruction::Select => {
+                    top -= 2;
+                    let (conditions, branches) = registers.split_at_mut(top * chunk);
+                    let conditions = &mut conditions[(top - 1) * chunk..][..len];
+                    let (then, otherwise) = branches.split_at(chunk);
+                    for ((condition, &a), &b) in conditions
+                        .iter_mut()
+                        .zip(&then[..len])
+                        .zip(&otherwise[..len])
+                    {
+                        *condition = if *condition != 0.0 { a } else { b };
+                    }
+                }
+            }
+        }
+        output.copy_from_slice(register(registers, chunk, len, 0));
+    }
+}
+
+fn register(registers: &mut [f64], chunk: usize, len: usize, index: usize) -> &mut [f64] {
+    &mut registers[index * chunk..][..len]
+}
+
+// Two different registers, the first of them to be written.
+fn pair(
+    registers: &mut [f64],
+    chunk: usize,
+    len: usize,
+    target: usize,
+    source: usize,
+) -> (&mut [f64], &[f64]) {
+    if target < source {
+        let (low, high) = registers.split_at_mut(source * chunk);
+        (&mut low[target * chunk..][..len], &high[..len])
+    } else {
+        let (low, high) = registers.split_at_mut(target * chunk);
+        (&mut high[..len], &low[source * chunk..][..len])
+    }
+}
+
+fn map(values: &mut [f64], f: impl Fn(f64) -> f64) {
+    for value in values {
+        *value = f(*value);
+    }
+}
+
+fn zip(a: &mut [f64], b: &[f64], f: impl Fn(f64, f64) -> f64) {
+    for (a, &b) in a.iter_mut().zip(b) {
+        *a = f(*a, b);
+    }
+}
+
+// The cheap operators get loops of their own, which the compiler can
+// vectorize. The rest call into libm per value anyway.
+
+impl Unary {
+    fn apply_all(self, values: &mut [f64]) {
+        match self {
+            Unary::Neg => map(values, |a| -a),
+            Unary::Abs => map(values, f64::abs),
+            Unary::Ceiling => map(values, f64::ceil),
+            Unary::Floor => map(values, f64::floor),
+            op => map(values, |a| op.apply(a)),
+        }
+    }
+}
+
+impl Binary {
+    fn apply_all(self, a: &mut [f64], b: &[f64]) {
+        match self {
+            Binary::Add => zip(a, b, |a, b| a + b),
+            Binary::Sub => zip(a, b, |a, b| a - b),
+            Binary::Mul => zip(a, b, |a, b| a * b),
+            Binary::Div => zip(a, b, |a, b| a / b),
+            Binary::Min => zip(a, b, f64::min),
+            Binary::Max => zip(a, b, f64::max),
+            Binary::Gt => zip(a, b, |a, b| truth(a > b)),
+            Binary::Lt => zip(a, b, |a, b| truth(a < b)),
+            Binary::Geq => zip(a, b, |a, b| truth(a >= b)),
+            Binary::Leq => zip(a, b, |a, b| truth(a <= b)),
+            Binary::And => zip(a, b, |a, b| truth(a != 0.0 && b != 0.0)),
+            Binary::Or => zip(a, b, |a, b| truth(a != 0.0 || b != 0.0)),
+            op => zip(a, b, |a, b| op.apply(a, b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn batch_evaluation() {
+        let clamp = parse_formula("lambda(v, piecewise(0, v < 0, 1, v > 1, v))").unwrap();
+        let symbols =
+            SymbolTable::from_variables(&["x", "y"]).with_function("clamp", clamp.into_nodes());
+        let rows = 1000;
+        let x: Vec<f64> = (0..rows).map(|i| i as f64 / 100.0 - 3.0).collect();
+        let y: Vec<f64> = (0..rows).map(|i| (i % 7) as f64).collect();
+
+        for formula in [
+            "x * y + 1 / (1 + x^2)",
+            "clamp(x) + piecewise(y, x < 0 && y > 2, -y)",
+            "sin(x) * max(x, y, 0) - abs(x) % 2",
+            "x < y < 5",
+            "7",
+        ] {
+            let compiled = compile(&parse_formula(formula).unwrap(), &symbols).unwrap();
+            let mut output = vec![0.0; rows];
+            compiled.eval_batch(&[&x, &y], &mut output);
+            for row in 0..rows {
+                let expected = compiled.eval(&[x[row], y[row]]);
+                assert!(
+                    output[row] == expected || output[row].is_nan() && expected.is_nan(),
+                    "{} at row {}",
+                    formula,
+                    row
+                );
+            }
+        }
+
+        let compiled = compile(&parse_formula("x + y").unwrap(), &symbols).unwrap();
+        let mut output = vec![];
+        compiled.eval_batch(&[&[], &[]], &mut output);
+        let mut output = vec![0.0; 2];
+        compiled.eval_batch(&[&[1.0, 2.0], &[3.0, 4.0]], &mut output);
+        assert_eq!(output, [4.0, 6.0]);
+    }
+}
@@ -0,0 +1,1112 @@
+use super::evaluate::{
+    csymbol_name, evaluate, expect_operands, get_node, integer_division, Environment,
+};
+use crate::structs::apply::Apply;
+use crate::structs::csymbol::{SbmlSymbol, AVOGADRO};
+use crate::structs::error::EvalError;
+use crate::structs::math_node::{MathNode, NodeIndex};
+use crate::structs::math_tree::MathTree;
+use crate::structs::op::Op;
+use crate::structs::qualifier::Qualifier;
+use crate::structs::semantics::Semantics;
+use crate::structs::value::Value;
+use std::collections::HashMap;
+
+// Expressions whose stack and locals fit into this many values are
+// evaluated without allocating.
+const INLINE_STACK: usize = 64;
+
+/// The variables and functions an expression is compiled against.
+///
+/// Every variable is given a slot, which is its index in the inputs of
+/// [`CompiledExpr::eval`].
+#[derive(Default, Debug, Clone)]
+pub struct SymbolTable {
+    slots: HashMap<String, usize>,
+    functions: HashMap<String, Vec<MathNode>>,
+    time: Option<usize>,
+}
+
+impl SymbolTable {
+    /// A table whose variables take the slots 0, 1, 2, ... in order.
+    pub fn from_variables<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut table = SymbolTable::default();
+        for (slot, name) in names.iter().enumerate() {
+            table.slots.insert(name.as_ref().to_string(), slot);
+        }
+        table
+    }
+
+    pub fn with_variable(mut self, name: &str, slot: usize) -> Self {
+        self.slots.insert(name.to_string(), slot);
+        self
+    }
+
+    /// Adds a function, given by the nodes of a tree with a `lambda` at its
+    /// root like the function definitions of [`evaluate_node`](crate::evaluate_node).
+    pub fn with_function(mut self, name: &str, nodes: Vec<MathNode>) -> Self {
+        self.functions.insert(name.to_string(), nodes);
+        self
+    }
+
+    /// Reads SBML's `time` csymbol from the given slot.
+    pub fn with_time(mut self, slot: usize) -> Self {
+        self.time = Some(slot);
+        self
+    }
+
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+}
+
+/// An expression compiled for fast repeated evaluation.
+///
+/// Variables are resolved to slots and function calls are inlined, so
+/// evaluation does no lookups by name. All numbers are computed as `f64`,
+/// booleans are 1.0 or 0.0 and operands outside the domain of an operator
+/// give NaN instead of an error.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) max_stack: usize,
+    pub(crate) locals: usize,
+    boolean: bool,
+}
+
+impl CompiledExpr {
+    /// Evaluates the expression with `inputs[slot]` as the value of the
+    /// variable in each slot.
+    ///
+    /// # Panics
+    ///
+    /// If `inputs` has no value for a slot the expression uses.
+    pub fn eval(&self, inputs: &[f64]) -> f64 {
+        let size = self.max_stack + self.locals;
+        if size <= INLINE_STACK {
+            self.run(inputs, &mut [0.0; INLINE_STACK])
+        } else {
+            self.run(inputs, &mut vec![0.0; size])
+        }
+    }
+
+    /// Whether the expression is a condition, whose value is 1.0 for true
+    /// and 0.0 for false.
+    pub fn is_boolean(&self) -> bool {
+        self.boolean
+    }
+
+    fn run(&self, inputs: &[f64], memory: &mut [f64]) -> f64 {
+        let (stack, locals) = memory.split_at_mut(self.max_stack);
+        let mut top = 0;
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::Constant(value) => {
+                    stack[top] = value;
+                    top += 1;
+                }
+                Instruction::Input(slot) => {
+                    stack[top] = inputs[slot];
+                    top += 1;
+                }
+                Instruction::Local(slot) => {
+                    stack[top] = locals[slot];
+                    top += 1;
+                }
+                Instruction::Store(slot) => {
+                    top -= 1;
+                    locals[slot] = stack[top];
+                }
+                Instruction::Unary(op) => stack[top - 1] = op.apply(stack[top - 1]),
+                Instruction::Binary(op) => {
+                    top -= 1;
+                    stack[top - 1] = op.apply(stack[top - 1], stack[top]);
+                }
+                Instruction::Select => {
+                    top -= 2;
+                    stack[top - 1] = if stack[top - 1] != 0.0 {
+                        stack[top]
+                    } else {
+                        stack[top + 1]
+                    };
+                }
+            }
+        }
+        stack[0]
+    }
+}
+
+/// Compiles `tree` against the variables and functions in `symbols`.
+///
+/// Fails with the error evaluation would report for unbound variables,
+/// unknown functions, wrong arities and operands of the wrong type.
+/// Complex numbers, containers, `delay` and `rateOf` cannot be compiled.
+pub fn compile(tree: &MathTree, symbols: &SymbolTable) -> Result<CompiledExpr, EvalError> {
+    let mut compiler = Compiler {
+        symbols,
+        instructions: Vec::new(),
+        depth: 0,
+        max_stack: 0,
+        locals: 0,
+        calls: Vec::new(),
+    };
+    let r#type = compiler.node(tree.nodes(), tree.root(), None)?;
+    Ok(CompiledExpr {
+        instructions: compiler.instructions,
+        max_stack: compiler.max_stack,
+        locals: compiler.locals,
+        boolean: r#type == Type::Boolean,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Instruction {
+    Constant(f64),
+    /// Pushes the input in a slot.
+    Input(usize),
+    /// Pushes a local, which holds an argument of an inlined function.
+    Local(usize),
+    /// Pops the top of the stack into a local.
+    Store(usize),
+    Unary(Unary),
+    Binary(Binary),
+    /// Pops a condition, the value if it holds and the value if it does
+    /// not, and pushes the one it selects.
+    Select,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Unary {
+    Neg,
+    Not,
+    Abs,
+    Exp,
+    Ln,
+    Ceiling,
+    Floor,
+    Factorial,
+    Sin,
+    Cos,
+    Tan,
+    Sec,
+    Csc,
+    Cot,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sech,
+    Csch,
+    Coth,
+    Arcsin,
+    Arccos,
+    Arctan,
+    Arcsec,
+    Arccsc,
+    Arccot,
+    Arcsinh,
+    Arccosh,
+    Arctanh,
+    Arcsech,
+    Arccsch,
+    Arccoth,
+}
+
+impl Unary {
+    pub(crate) fn from_op(op: &Op) -> Option<Unary> {
+        Some(match op {
+            Op::Abs => Unary::Abs,
+            Op::Exp => Unary::Exp,
+            Op::Ln => Unary::Ln,
+            Op::Ceiling => Unary::Ceiling,
+            Op::Floor => Unary::Floor,
+            Op::Factorial => Unary::Factorial,
+            Op::Sin => Unary::Sin,
+            Op::Cos => Unary::Cos,
+            Op::Tan => Unary::Tan,
+            Op::Sec => Unary::Sec,
+            Op::Csc => Unary::Csc,
+            Op::Cot => Unary::Cot,
+            Op::Sinh => Unary::Sinh,
+            Op::Cosh => Unary::Cosh,
+            Op::Tanh => Unary::Tanh,
+            Op::Sech => Unary::Sech,
+            Op::Csch => Unary::Csch,
+            Op::Coth => Unary::Coth,
+            Op::Arcsin => Unary::Arcsin,
+            Op::Arccos => Unary::Arccos,
+            Op::Arctan => Unary::Arctan,
+            Op::Arcsec => Unary::Arcsec,
+            Op::Arccsc => Unary::Arccsc,
+            Op::Arccot => Unary::Arccot,
+            Op::Arcsinh => Unary::Arcsinh,
+            Op::Arccosh => Unary::Arccosh,
+            Op::Arctanh => Unary::Arctanh,
+            Op::Arcsech => Unary::Arcsech,
+            Op::Arccsch => Unary::Arccsch,
+            Op::Arccoth => Unary::Arccoth,
+            _ => return None,
+        })
+    }
+
+    /// Evaluation computes the real functions here too, so both give the
+    /// same results.
+    pub(crate) fn apply(self, a: f64) -> f64 {
+        match self {
+            Unary::Neg => -a,
+            Unary::Not => truth(a == 0.0),
+            Unary::Abs => a.abs(),
+            Unary::Exp => a.exp(),
+            Unary::Ln => a.ln(),
+            Unary::Ceiling => a.ceil(),
+            Unary::Floor => a.floor(),
+            Unary::Factorial => {
+                if a < 0.0 || a.fract() != 0.0 {
+                    f64::NAN
+                } else {
+                    // 171! is too large for an f64
+                    (1..=(a.min(171.0) as u32)).fold(1.0, |product, k| product * k as f64)
+                }
+            }
+            Unary::Sin => a.sin(),
+            Unary::Cos => a.cos(),
+            Unary::Tan => a.tan(),
+            Unary::Sec => 1.0 / a.cos(),
+            Unary::Csc => 1.0 / a.sin(),
+            Unary::Cot => 1.0 / a.tan(),
+            Unary::Sinh => a.sinh(),
+            Unary::Cosh => a.cosh(),
+            Unary::Tanh => a.tanh(),
+            Unary::Sech => 1.0 / a.cosh(),
+            Unary::Csch => 1.0 / a.sinh(),
+            Unary::Coth => 1.0 / a.tanh(),
+            Unary::Arcsin => a.asin(),
+            Unary::Arccos => a.acos(),
+            Unary::Arctan => a.atan(),
+            Unary::Arcsec => (1.0 / a).acos(),
+            Unary::Arccsc => (1.0 / a).asin(),
+            // the principal value lies in (0, pi)
+            Unary::Arccot => std::f64::consts::FRAC_PI_2 - a.atan(),
+            Unary::Arcsinh => a.asinh(),
+            Unary::Arccosh => a.acosh(),
+            Unary::Arctanh => a.atanh(),
+            Unary::Arcsech => (1.0 / a).acosh(),
+            Unary::Arccsch => (1.0 / a).asinh(),
+            Unary::Arccoth => (1.0 / a).atanh(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Binary {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    /// A logarithm, with the base as the second operand.
+    Log,
+    /// A root, with the degree as the second operand.
+    Root,
+    Quotient,
+    Rem,
+    Min,
+    Max,
+    Eq,
+    Neq,
+    Gt,
+    Lt,
+    Geq,
+    Leq,
+    And,
+    Or,
+    Xor,
+    Implies,
+}
+
+impl Binary {
+    pub(crate) fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Binary::Add => a + b,
+            Binary::Sub => a - b,
+            Binary::Mul => a * b,
+            Binary::Div => a / b,
+            Binary::Pow => a.powf(b),
+            Binary::Log => {
+                if b <= 0.0 || b == 1.0 || a <= 0.0 {
+                    f64::NAN
+                } else if b == 10.0 {
+                    a.log10()
+                } else {
+                    a.log(b)
+                }
+            }
+            Binary::Root => {
+                if b == 0.0 {
+                    f64::NAN
+                } else if a >= 0.0 {
+                    a.powf(1.0 / b)
+                } else if b.fract() == 0.0 && b % 2.0 != 0.0 {
+                    -(-a).powf(1.0 / b)
+                } else {
+                    f64::NAN
+                }
+            }
+            Binary::Quotient | Binary::Rem => {
+                let (a, b) = (a as i64, b as i64);
+                if b == 0 {
+                    return f64::NAN;
+                }
+                integer_division(self == Binary::Quotient, a, b)
+                    .as_f64()
+                    .unwrap_or(f64::NAN)
+            }
+            Binary::Min => a.min(b),
+            Binary::Max => a.max(b),
+            Binary::Eq => truth((a - b).abs() <= f64::EPSILON),
+            Binary::Neq => truth((a - b).abs() > f64::EPSILON),
+            Binary::Gt => truth(a > b),
+            Binary::Lt => truth(a < b),
+            Binary::Geq => truth(a >= b),
+            Binary::Leq => truth(a <= b),
+            Binary::And => truth(a != 0.0 && b != 0.0),
+            Binary::Or => truth(a != 0.0 || b != 0.0),
+            Binary::Xor => truth((a != 0.0) != (b != 0.0)),
+            Binary::Implies => truth(a == 0.0 || b != 0.0),
+        }
+    }
+}
+
+pub(crate) fn truth(condition: bool) -> f64 {
+    if condition {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Type {
+    Number,
+    Boolean,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Number => "number",
+            Type::Boolean => "boolean",
+        }
+    }
+}
+
+// The arguments of the function being inlined, with their locals. Outside
+// of functions the variables come from the symbol table.
+type Scope<'s> = Option<&'s [(String, usize, Type)]>;
+
+// Constants and numbers are evaluated once while compiling.
+struct NoVariables;
+
+impl Environment for NoVariables {
+    fn variable(&self, _name: &str) -> Option<Value> {
+        None
+    }
+}
+
+struct Compiler<'a> {
+    symbols: &'a SymbolTable,
+    instructions: Vec<Instruction>,
+    depth: usize,
+    max_stack: usize,
+    locals: usize,
+    // the functions being inlined, to reject recursion
+    calls: Vec<&'a str>,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Constant(..) | Instruction::Input(..) | Instruction::Local(..) => {
+                self.depth += 1
+            }
+            Instruction::Store(..) | Instruction::Binary(..) => self.depth -= 1,
+            Instruction::Select => self.depth -= 2,
+            Instruction::Unary(..) => {}
+        }
+        self.max_stack = self.max_stack.max(self.depth);
+        self.instructions.push(instruction);
+    }
+
+    fn expect(
+        &mut self,
+        nodes: &'a [MathNode],
+        idx: NodeIndex,
+        scope: Scope,
+        r#type: Type,
+        expected: &'static str,
+    ) -> Result<(), EvalError> {
+        if self.node(nodes, idx, scope)? == r#type {
+            Ok(())
+        } else {
+            Err(EvalError::TypeMismatch {
+                expected,
+                node: idx,
+            })
+        }
+    }
+
+    fn number(
+        &mut self,
+        nodes: &'a [MathNode],
+        idx: NodeIndex,
+        scope: Scope,
+    ) -> Result<(), EvalError> {
+        self.expect(nodes, idx, scope, Type::Number, "number")
+    }
+
+    fn real(
+        &mut self,
+        nodes: &'a [MathNode],
+        idx: NodeIndex,
+        scope: Scope,
+    ) -> Result<(), EvalError> {
+        self.expect(nodes, idx, scope, Type::Number, "real number")
+    }
+
+    fn boolean(
+        &mut self,
+        nodes: &'a [MathNode],
+        idx: NodeIndex,
+        scope: Scope,
+    ) -> Result<(), EvalError> {
+        self.expect(nodes, idx, scope, Type::Boolean, "boolean")
+    }
+
+    fn node(
+        &mut self,
+        nodes: &'a [MathNode],
+        idx: NodeIndex,
+        scope: Scope,
+    ) -> Result<Type, EvalError> {
+        match get_node(nodes, idx)? {
+            MathNode::Root(root) => {
+                if root.children.len() != 1 {
+                    return Err(EvalError::MalformedNode {
+                        reason: "root must have exactly one child",
+                        node: idx,
+                    });
+                }
+                self.node(nodes, root.children[0], scope)
+            }
+            MathNode::Semantics(semantics) => {
+                let expr = semantics.expr.ok_or(EvalError::MalformedNode {
+                    reason: "semantics element with no expression",
+                    node: idx,
+                })?;
+                self.node(nodes, expr, scope)
+            }
+            MathNode::Cn(..) | MathNode::Constant(..) => {
+                match evaluate(nodes, idx, &NoVariables)? {
+                    Value::Boolean(value) => {
+                        self.emit(Instruction::Constant(truth(value)));
+                        Ok(Type::Boolean)
+                    }
+                    value => {
+                        let value = value.as_f64().ok_or(EvalError::TypeMismatch {
+                            expected: "real number",
+                            node: idx,
+                        })?;
+                        self.emit(Instruction::Constant(value));
+                        Ok(Type::Number)
+                    }
+                }
+            }
+            MathNode::Ci(ci) => {
+                let name = ci.name.as_deref().ok_or(EvalError::MalformedNode {
+                    reason: "ci element with no content",
+                    node: idx,
+                })?;
+                let unbound = || EvalError::UnboundVariable {
+                    name: name.to_string(),
+                    node: idx,
+                };
+                match scope {
+                    Some(arguments) => {
+                        let &(_, local, r#type) = arguments
+                            .iter()
+                            .find(|(argument, ..)| argument == name)
+                            .ok_or_else(unbound)?;
+                        self.emit(Instruction::Local(local));
+                        Ok(r#type)
+                    }
+                    None => {
+                        let slot = self.symbols.slot(name).ok_or_else(unbound)?;
+                        self.emit(Instruction::Input(slot));
+                        Ok(Type::Number)
+                    }
+                }
+            }
+            MathNode::Csymbol(csymbol) => {
+                let unavailable = |name: String| EvalError::UnavailableSymbol { name, node: idx };
+                match csymbol.sbml_symbol() {
+                    Some(SbmlSymbol::Time) => {
+                        let slot = self
+                            .symbols
+                            .time
+                            .ok_or_else(|| unavailable(SbmlSymbol::Time.to_string()))?;
+                        self.emit(Instruction::Input(slot));
+                    }
+                    Some(SbmlSymbol::Avogadro) => self.emit(Instruction::Constant(AVOGADRO)),
+                    Some(..) => {
+                        return Err(EvalError::MalformedNode {
+                            reason: "csymbol function used without arguments",
+                            node: idx,
+                        })
+                    }
+                    None => return Err(unavailable(csymbol_name(csymbol))),
+                }
+                Ok(Type::Number)
+            }
+            MathNode::Piecewise(piecewise) => {
+                let r#type =
+                    self.pieces(nodes, &piecewise.pieces, piecewise.otherwise, scope, None)?;
+                Ok(r#type.unwrap_or(Type::Number))
+            }
+            MathNode::Apply(apply) => {
+                let operator_idx = apply.operator.ok_or(EvalError::MalformedNode {
+                    reason: "apply has no operator",
+                    node: idx,
+                })?;
+                if let Ok(op) = apply.get_op(nodes) {
+                    return self.operator(nodes, idx, apply, op, scope);
+                }
+                let (function_nodes, function_idx) = match get_node(nodes, operator_idx)? {
+                    MathNode::Ci(ci) => {
+                        let name = ci.name.as_deref().ok_or(EvalError::MalformedNode {
+                            reason: "ci element with no content",
+                            node: operator_idx,
+                        })?;
+                        let function = self.symbols.functions.get(name).ok_or_else(|| {
+                            EvalError::UnknownFunction {
+                                name: name.to_string(),
+                                node: operator_idx,
+                            }
+                        })?;
+                        if self.calls.contains(&name) {
+                            return Err(EvalError::MalformedNode {
+                                reason: "recursive function call",
+                                node: operator_idx,
+                            });
+                        }
+                        self.calls.push(name);
+                        let r#type = self.call(nodes, &apply.operands, function, 0, scope);
+                        self.calls.pop();
+                        return r#type;
+                    }
+                    MathNode::Lambda(..) => (nodes, operator_idx),
+                    MathNode::Csymbol(csymbol) => {
+                        return Err(EvalError::UnavailableSymbol {
+                            name: csymbol_name(csymbol),
+                            node: operator_idx,
+                        })
+                    }
+                    _ => {
+                        return Err(EvalError::MalformedNode {
+                            reason: "invalid operator",
+                            node: operator_idx,
+                        })
+                    }
+                };
+                self.call(nodes, &apply.operands, function_nodes, function_idx, scope)
+            }
+            _ => Err(EvalError::MalformedNode {
+                reason: "node cannot be evaluated on its own",
+                node: idx,
+            }),
+        }
+    }
+
+    // Compiles the pieces from the first one on into nested selections,
+    // which give NaN if no piece holds and there is no otherwise. All
+    // branches must have the same type as the first one.
+    fn pieces(
+        &mut self,
+        nodes: &'a [MathNode],
+        pieces: &[NodeIndex],
+        otherwise: Option<NodeIndex>,
+        scope: Scope,
+        r#type: Option<Type>,
+    ) -> Result<Option<Type>, EvalError> {
+        let (&piece_idx, rest) = match pieces.split_first() {
+            Some(first) => first,
+            None => {
+                return match otherwise {
+                    Some(otherwise_idx) => {
+                        let expr = match get_node(nodes, otherwise_idx)? {
+                            MathNode::Otherwise(otherwise) => otherwise.expr,
+                            _ => None,
+                        };
+                        let expr = expr.ok_or(EvalError::MalformedNode {
+                            reason: "otherwise branch is empty",
+                            node: otherwise_idx,
+                        })?;
+                        self.branch(nodes, expr, scope, r#type).map(Some)
+                    }
+                    None => {
+                        self.emit(Instruction::Constant(f64::NAN));
+                        Ok(r#type)
+                    }
+                };
+            }
+        };
+        let (expr, condition) = match get_node(nodes, piece_idx)? {
+            MathNode::Piece(piece) => (piece.expr, piece.condition),
+            _ => {
+                return Err(EvalError::MalformedNode {
+                    reason: "expected piece",
+                    node: piece_idx,
+                })
+            }
+        };
+        let expr = expr.ok_or(EvalError::MalformedNode {
+            reason: "piece has no expression",
+            node: piece_idx,
+        })?;
+        let condition = condition.ok_or(EvalError::MalformedNode {
+            reason: "piece has no condition",
+            node: piece_idx,
+        })?;
+        self.boolean(nodes, condition, scope)?;
+        let r#type = self.branch(nodes, expr, scope, r#type)?;
+        self.pieces(nodes, rest, otherwise, scope, Some(r#type))?;
+        self.emit(Instruction::Select);
+        Ok(Some(r#type))
+    }
+
+    fn branch(
+        &mut self,
+        nodes: &'a [MathNode],
+        expr: NodeIndex,
+        scope: Scope,
+        expected: Option<Type>,
+    ) -> Result<Type, EvalError> {
+        let r#type = self.node(nodes, expr, scope)?;
+        match expected {
+            Some(expected) if expected != r#type => Err(EvalError::TypeMismatch {
+                expected: expected.name(),
+                node: expr,
+            }),
+            _ => Ok(r#type),
+        }
+    }
+
+    // Inlines a call, storing the arguments in fresh locals that the body
+    // of the function refers to.
+    fn call(
+        &mut self,
+        nodes: &'a [MathNode],
+        operands: &[NodeIndex],
+        function_nodes: &'a [MathNode],
+        function_idx: NodeIndex,
+        scope: Scope,
+    ) -> Result<Type, EvalError> {
+        let lambda = match get_node(function_nodes, function_idx)? {
+            MathNode::Root(root) if root.children.len() == 1 => {
+                return self.call(nodes, operands, function_nodes, root.children[0], scope)
+            }
+            MathNode::Semantics(Semantics {
+                expr: Some(expr), ..
+            }) => return self.call(nodes, operands, function_nodes, *expr, scope),
+            MathNode::Lambda(lambda) => lambda,
+            // like evaluation, treat anything else as a function body
+            _ => return self.node(function_nodes, function_idx, None),
+        };
+        let mut names = Vec::new();
+        for &binding in &lambda.bindings {
+            if let MathNode::BVar(bvar) = get_node(function_nodes, binding)? {
+                for &child in &bvar.children {
+                    if let MathNode::Ci(ci) = get_node(function_nodes, child)? {
+                        names.push(ci.name.clone().ok_or(EvalError::MalformedNode {
+                            reason: "ci element with no content",
+                            node: child,
+                        })?);
+                    }
+                }
+            }
+        }
+        if operands.len() != names.len() {
+            return Err(EvalError::ArgumentMismatch {
+                name: "lambda".to_string(),
+                expected: names.len(),
+                got: operands.len(),
+                node: function_idx,
+            });
+        }
+        let mut arguments = Vec::new();
+        for (name, &operand) in names.into_iter().zip(operands) {
+            let r#type = self.node(nodes, operand, scope)?;
+            let local = self.locals;
+            self.locals += 1;
+            self.emit(Instruction::Store(local));
+            arguments.push((name, local, r#type));
+        }
+        let expr = lambda.expr.ok_or(EvalError::MalformedNode {
+            reason: "lambda has no expression",
+            node: function_idx,
+        })?;
+        self.node(function_nodes, expr, Some(&arguments))
+    }
+
+    fn operator(
+        &mut self,
+        nodes: &'a [MathNode],
+        idx: NodeIndex,
+        apply: &'a Apply,
+        op: Op,
+        scope: Scope,
+    ) -> Result<Type, EvalError> {
+        let operands = &apply.operands;
+        match op {
+            Op::Plus | Op::Times | Op::Min | Op::Max => {
+                let (binary, empty) = match op {
+                    Op::Plus => (Binary::Add, 0.0),
+                    Op::Times => (Binary::Mul, 1.0),
+                    Op::Min => (Binary::Min, f64::MAX),
+                    _ => (Binary::Max, f64::MIN),
+                };
+                if operands.is_empty() {
+                    self.emit(Instruction::Constant(empty));
+                }
+                for (i, &operand) in operands.iter().enumerate() {
+                    self.number(nodes, operand, scope)?;
+                    if i > 0 {
+                        self.emit(Instruction::Binary(binary));
+                    }
+                }
+                Ok(Type::Number)
+            }
+            Op::Minus => {
+                match operands.len() {
+                    1 => {
+                        self.number(nodes, operands[0], scope)?;
+                        self.emit(Instruction::Unary(Unary::Neg));
+                    }
+                    2 => {
+                        self.number(nodes, operands[0], scope)?;
+                        self.number(nodes, operands[1], scope)?;
+                        self.emit(Instruction::Binary(Binary::Sub));
+                    }
+                    got => {
+                        return Err(EvalError::ArityMismatch {
+                            op,
+                            expected: if got == 0 { 1 } else { 2 },
+                            got,
+                            node: idx,
+                        })
+                    }
+                }
+                Ok(Type::Number)
+            }
+            Op::Divide | Op::Power | Op::Quotient | Op::Rem => {
+                expect_operands(apply, &op, 2, idx)?;
+                self.number(nodes, operands[0], scope)?;
+                self.number(nodes, operands[1], scope)?;
+                let binary = match op {
+                    Op::Divide => Binary::Div,
+                    Op::Power => Binary::Pow,
+                    Op::Quotient => Binary::Quotient,
+                    _ => Binary::Rem,
+                };
+                self.emit(Instruction::Binary(binary));
+                Ok(Type::Number)
+            }
+            Op::Log | Op::Root => {
+                expect_operands(apply, &op, 1, idx)?;
+                self.real(nodes, operands[0], scope)?;
+                // MathML logarithms are base 10 and roots square roots
+                // unless a logbase or degree is given
+                let (qualifier, default, binary) = if op == Op::Log {
+                    (Qualifier::LogBase, 10.0, Binary::Log)
+                } else {
+                    (Qualifier::Degree, 2.0, Binary::Root)
+                };
+                match self.qualifier(nodes, &apply.qualifiers, qualifier)? {
+                    Some(value_idx) => self.real(nodes, value_idx, scope)?,
+                    None => self.emit(Instruction::Constant(default)),
+                }
+                self.emit(Instruction::Binary(binary));
+                Ok(Type::Number)
+            }
+            Op::Eq | Op::Neq | Op::Gt | Op::Lt | Op::Geq | Op::Leq => {
+                if op == Op::Neq {
+                    expect_operands(apply, &op, 2, idx)?;
+                }
+                let binary = match op {
+                    Op::Eq => Binary::Eq,
+                    Op::Neq => Binary::Neq,
+                    Op::Gt => Binary::Gt,
+                    Op::Lt => Binary::Lt,
+                    Op::Geq => Binary::Geq,
+                    _ => Binary::Leq,
+                };
+                self.chain(nodes, operands, binary, scope)?;
+                Ok(Type::Boolean)
+            }
+            Op::And | Op::Or | Op::Xor => {
+                let (binary, empty) = match op {
+                    Op::And => (Binary::And, 1.0),
+                    Op::Or => (Binary::Or, 0.0),
+                    _ => (Binary::Xor, 0.0),
+                };
+                if operands.is_empty() {
+                    self.emit(Instruction::Constant(empty));
+                }
+                for (i, &operand) in operands.iter().enumerate() {
+                    self.boolean(nodes, operand, scope)?;
+                    if i > 0 {
+                        self.emit(Instruction::Binary(binary));
+                    }
+                }
+                Ok(Type::Boolean)
+            }
+            Op::Not => {
+                expect_operands(apply, &op, 1, idx)?;
+                self.boolean(nodes, operands[0], scope)?;
+                self.emit(Instruction::Unary(Unary::Not));
+                Ok(Type::Boolean)
+            }
+            Op::Implies => {
+                expect_operands(apply, &op, 2, idx)?;
+                self.boolean(nodes, operands[0], scope)?;
+                self.boolean(nodes, operands[1], scope)?;
+                self.emit(Instruction::Binary(Binary::Implies));
+                Ok(Type::Boolean)
+            }
+            _ => match Unary::from_op(&op) {
+                Some(unary) => {
+                    expect_operands(apply, &op, 1, idx)?;
+                    // report the same errors as evaluation
+                    match unary {
+                        Unary::Abs | Unary::Ceiling | Unary::Floor | Unary::Factorial => {
+                            self.number(nodes, operands[0], scope)?
+                        }
+                        _ => self.real(nodes, operands[0], scope)?,
+                    }
+                    self.emit(Instruction::Unary(unary));
+                    Ok(Type::Number)
+                }
+                None => Err(EvalError::UnsupportedOperator { op, node: idx }),
+            },
+        }
+    }
+
+    // Compiles a relation between each operand and the next one, like
+    // `a < b < c`. Operands of longer chains are kept in locals so that
+    // each is computed once.
+    fn chain(
+        &mut self,
+        nodes: &'a [MathNode],
+        operands: &[NodeIndex],
+        binary: Binary,
+        scope: Scope,
+    ) -> Result<(), EvalError> {
+        let ordering = !matches!(binary, Binary::Eq | Binary::Neq);
+        let mut first_type: Option<Type> = None;
+        let mut locals = Vec::new();
+        for &operand in operands {
+            let r#type = if ordering {
+                self.real(nodes, operand, scope)?;
+                Type::Number
+            } else {
+                self.node(nodes, operand, scope)?
+            };
+            match first_type {
+                Some(first) if first != r#type => {
+                    return Err(EvalError::TypeMismatch {
+                        expected: first.name(),
+                        node: operand,
+                    })
+                }
+                _ => first_type = Some(r#type),
+            }
+            // a lone operand is dropped, since the relation always holds
+            if operands.len() != 2 {
+                let local = self.locals;
+                self.locals += 1;
+                self.emit(Instruction::Store(local));
+                locals.push(local);
+            }
+        }
+        match operands.len() {
+            0 | 1 => self.emit(Instruction::Constant(1.0)),
+            2 => self.emit(Instruction::Binary(binary)),
+            _ => {
+                for (i, pair) in locals.windows(2).enumerate() {
+                    self.emit(Instruction::Local(pair[0]));
+                    self.emit(Instruction::Local(pair[1]));
+                    self.emit(Instruction::Binary(binary));
+                    if i > 0 {
+                        self.emit(Instruction::Binary(Binary::And));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The value of a qualifier such as `logbase` on an apply.
+    fn qualifier(
+        &self,
+        nodes: &[MathNode],
+        qualifiers: &[NodeIndex],
+        qualifier: Qualifier,
+    ) -> Result<Option<NodeIndex>, EvalError> {
+        for &qualifier_idx in qualifiers {
+            if let MathNode::Qualifier(qualifiernode) = get_node(nodes, qualifier_idx)? {
+                if qualifiernode.qualifier.as_ref() == Some(&qualifier) {
+                    let value_idx =
+                        *qualifiernode
+                            .children
+                            .first()
+                            .ok_or(EvalError::MalformedNode {
+                                reason: "qualifier has no value",
+                                node: qualifier_idx,
+                            })?;
+                    return Ok(Some(value_idx));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn compiled_expressions() {
+        let hill = parse_formula("lambda(s, k, n, s^n / (k^n + s^n))").unwrap();
+        let clamp = parse_formula("lambda(v, piecewise(0, v < 0, 1, v > 1, v))").unwrap();
+        let symbols = SymbolTable::from_variables(&["x", "y"])
+            .with_time(2)
+            .with_function("hill", hill.clone().into_nodes())
+            .with_function("clamp", clamp.clone().into_nodes());
+        let functions = std::collections::HashMap::from([
+            ("hill".to_string(), hill.into_nodes()),
+            ("clamp".to_string(), clamp.into_nodes()),
+        ]);
+
+        let formulas = [
+            "x + 2 * y - 3 / x",
+            "hill(x, y, 2) * clamp(time - 1)",
+            "log(1000) + log(2, y) + root(3, -x) + sqrt(y)",
+            "piecewise(x, x < y && y < 10, y, x > y, -1)",
+            "x < y < 10",
+            "ceil(x / 3) + floor(y) + abs(-x) + exp(0) + sin(pi) + factorial(4)",
+            "max(x, y, 3) - min(x, y) + 7 % 3",
+        ];
+        for formula in formulas {
+            let tree = parse_formula(formula).unwrap();
+            let compiled = compile(&tree, &symbols).unwrap();
+            for inputs in [[1.0, 2.0, 0.5], [5.0, 4.0, 3.0], [-2.0, 16.0, 1.5]] {
+                let values = std::collections::HashMap::from([
+                    ("x".to_string(), inputs[0]),
+                    ("y".to_string(), inputs[1]),
+                ]);
+                let context = SbmlContext::at_time(inputs[2]);
+                let expected =
+                    evaluate_node_with(tree.nodes(), tree.root(), &values, &functions, &context)
+                        .unwrap();
+                let expected = match expected {
+                    Value::Boolean(condition) => f64::from(u8::from(condition)),
+                    value => value.as_f64().unwrap(),
+                };
+                approx::assert_abs_diff_eq!(compiled.eval(&inputs), expected, epsilon = 1e-12);
+            }
+        }
+        assert!(compile(&parse_formula("x < y").unwrap(), &symbols)
+            .unwrap()
+            .is_boolean());
+
+        let error =
+            |formula: &str| compile(&parse_formula(formula).unwrap(), &symbols).unwrap_err();
+        assert!(matches!(
+            error("x + z"),
+            EvalError::UnboundVariable { ref name, .. } if name == "z"
+        ));
+        assert!(matches!(
+            error("clamp(x, y)"),
+            EvalError::ArgumentMismatch {
+                expected: 1,
+                got: 2,
+                ..
+            }
+        ));
+        assert!(matches!(
+            error("x + (x < y)"),
+            EvalError::TypeMismatch {
+                expected: "number",
+                ..
+            }
+        ));
+        assert!(matches!(
+            error("piecewise(x < y, x > 0, 1)"),
+            EvalError::TypeMismatch {
+                expected: "boolean",
+                ..
+            }
+        ));
+        let recursive = SymbolTable::from_variables(&["x"])
+            .with_function("f", parse_formula("lambda(a, f(a))").unwrap().into_nodes());
+        assert!(matches!(
+            compile(&parse_formula("f(x)").unwrap(), &recursive),
+            Err(EvalError::MalformedNode {
+                reason: "recursive function call",
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn compiled_matches_evaluation_at_edges() {
+        let symbols = SymbolTable::from_variables(&["x", "y"]);
+        let formulas = [
+            "quotient(x, y)",
+            "x % y",
+            "factorial(x)",
+            "arccot(x) + arcsech(y) + arccsch(x) + arccoth(y)",
+        ];
+        let inputs = [
+            [i64::MIN as f64, -1.0],
+            [7.0, 0.0],
+            [-7.5, 2.0],
+            [-3.0, 0.5],
+            [4.0, 1.5],
+        ];
+        for formula in formulas {
+            let tree = parse_formula(formula).unwrap();
+            let compiled = compile(&tree, &symbols).unwrap();
+            for inputs in inputs {
+                let values = std::collections::HashMap::from([
+                    ("x".to_string(), inputs[0]),
+                    ("y".to_string(), inputs[1]),
+                ]);
+                let actual = compiled.eval(&inputs);
+                // domain errors are NaN when compiled
+                match evaluate_node(tree.nodes(), tree.root(), &values, &Default::default()) {
+                    Ok(value) => {
+                        let expected = value.as_f64().unwrap();
+                        assert!(
+                            actual == expected || actual.is_nan() && expected.is_nan(),
+                            "{} at {:?}: {} != {}",
+                            formula,
+                            inputs,
+                            actual,
+                            expected
+                        );
+                    }
+                    Err(EvalError::DomainError { .. }) => assert!(actual.is_nan()),
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        }
+    }
+}
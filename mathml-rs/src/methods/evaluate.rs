@@ -1,143 +1,530 @@
+use super::super::structs::apply::Apply;
 use super::super::structs::constants::Constant;
+use super::super::structs::csymbol::{Csymbol, SbmlSymbol, AVOGADRO};
+use super::super::structs::error::EvalError;
 use super::super::structs::math_node::{MathNode, NodeIndex};
 use super::super::structs::numbers::{NumType, Number};
 use super::super::structs::op::Op;
-use math::round;
-use mathru::statistics::combins::factorial;
+use super::super::structs::qualifier::Qualifier;
+use super::super::structs::value::Value;
+use super::compile::Unary;
+use super::linear_algebra::ArrayValue;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
+/// Gives the current rate of change of a variable.
+pub type RateOfFn<'a> = &'a (dyn Fn(&str) -> Option<f64> + Sync);
+/// Gives the value a variable had the given amount of time ago.
+pub type DelayFn<'a> = &'a (dyn Fn(&str, f64) -> Option<f64> + Sync);
+
+/// The simulation state that SBML's `time`, `delay` and `rateOf` csymbols
+/// refer to. Symbols whose value or callback is missing fail to evaluate.
+///
+/// The callbacks are `Sync`, so a context can be shared between threads.
+#[derive(Default, Clone, Copy)]
+pub struct SbmlContext<'a> {
+    /// The current simulation time.
+    pub time: Option<f64>,
+    /// Answers `rateOf(x)`.
+    pub rate_of: Option<RateOfFn<'a>>,
+    /// Answers `delay(x, d)`.
+    pub delay: Option<DelayFn<'a>>,
+}
+
+impl<'a> SbmlContext<'a> {
+    pub fn at_time(time: f64) -> Self {
+        SbmlContext {
+            time: Some(time),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_rate_of(mut self, rate_of: RateOfFn<'a>) -> Self {
+        self.rate_of = Some(rate_of);
+        self
+    }
+
+    pub fn with_delay(mut self, delay: DelayFn<'a>) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+/// Where evaluation looks up the values of variables, the definitions of
+/// functions and the simulation state.
+///
+/// Only variables have to be provided; an environment without functions or
+/// a simulation state fails to evaluate the expressions that use them.
+pub trait Environment {
+    /// The value of the variable with the given name.
+    fn variable(&self, name: &str) -> Option<Value>;
+
+    /// The value of a variable that may be a vector or a matrix, for
+    /// [`evaluate_array`](crate::evaluate_array).
+    fn array(&self, _name: &str) -> Option<ArrayValue> {
+        None
+    }
+
+    /// The nodes of the tree defining the function with the given name,
+    /// rooted at index 0 in a `lambda`.
+    fn function(&self, _name: &str) -> Option<&[MathNode]> {
+        None
+    }
+
+    /// The current simulation time.
+    fn time(&self) -> Option<f64> {
+        None
+    }
+
+    /// The current rate of change of a variable.
+    fn rate_of(&self, _name: &str) -> Option<f64> {
+        None
+    }
+
+    /// The value a variable had the given amount of time ago.
+    fn delay(&self, _name: &str, _delay: f64) -> Option<f64> {
+        None
+    }
+}
+
+/// An [`Environment`] with variables and functions in hash maps, and the
+/// simulation state of an [`SbmlContext`].
+///
+/// Variables that may be vectors or matrices are kept in a map of their
+/// own; the scalars among them can be used like any other variable.
+#[derive(Clone, Copy)]
+pub struct MapEnvironment<'a> {
+    pub values: &'a HashMap<String, f64>,
+    pub functions: &'a HashMap<String, Vec<MathNode>>,
+    pub arrays: Option<&'a HashMap<String, ArrayValue>>,
+    pub context: SbmlContext<'a>,
+}
+
+impl<'a> MapEnvironment<'a> {
+    pub fn new(
+        values: &'a HashMap<String, f64>,
+        functions: &'a HashMap<String, Vec<MathNode>>,
+    ) -> Self {
+        MapEnvironment {
+            values,
+            functions,
+            arrays: None,
+            context: SbmlContext::default(),
+        }
+    }
+
+    pub fn with_arrays(mut self, arrays: &'a HashMap<String, ArrayValue>) -> Self {
+        self.arrays = Some(arrays);
+        self
+    }
+
+    pub fn with_context(mut self, context: SbmlContext<'a>) -> Self {
+        self.context = context;
+        self
+    }
+}
+
+impl Environment for MapEnvironment<'_> {
+    fn variable(&self, name: &str) -> Option<Value> {
+        let value = self.values.get(name).copied();
+        value
+            .or_else(|| self.arrays?.get(name)?.as_scalar())
+            .map(Value::Real)
+    }
+
+    fn array(&self, name: &str) -> Option<ArrayValue> {
+        self.arrays?.get(name).cloned()
+    }
+
+    fn function(&self, name: &str) -> Option<&[MathNode]> {
+        self.functions.get(name).map(Vec::as_slice)
+    }
+
+    fn time(&self) -> Option<f64> {
+        self.context.time
+    }
+
+    fn rate_of(&self, name: &str) -> Option<f64> {
+        self.context.rate_of?(name)
+    }
+
+    fn delay(&self, name: &str, delay: f64) -> Option<f64> {
+        self.context.delay?(name, delay)
+    }
+}
+
+// The environment of a function body, where the arguments are the only
+// scalar variables. Vectors and matrices that no argument shadows, the
+// functions and the simulation state come from the outer environment.
+struct Arguments<'a> {
+    bindings: Vec<(String, Value)>,
+    outer: &'a dyn Environment,
+}
+
+impl Environment for Arguments<'_> {
+    fn variable(&self, name: &str) -> Option<Value> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| bound == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn array(&self, name: &str) -> Option<ArrayValue> {
+        if self.bindings.iter().any(|(bound, _)| bound == name) {
+            return None;
+        }
+        self.outer.array(name)
+    }
+
+    fn function(&self, name: &str) -> Option<&[MathNode]> {
+        self.outer.function(name)
+    }
+
+    fn time(&self) -> Option<f64> {
+        self.outer.time()
+    }
+
+    fn rate_of(&self, name: &str) -> Option<f64> {
+        self.outer.rate_of(name)
+    }
+
+    fn delay(&self, name: &str, delay: f64) -> Option<f64> {
+        self.outer.delay(name, delay)
+    }
+}
+
+// The Euler-Mascheroni constant, which std does not provide on stable.
+const EULER_GAMMA: f64 = 0.577_215_664_901_532_9;
+
+// Looks up a node, reporting dangling indices instead of panicking.
+pub(crate) fn get_node(nodes: &[MathNode], idx: NodeIndex) -> Result<&MathNode, EvalError> {
+    nodes.get(idx).ok_or(EvalError::MalformedNode {
+        reason: "node index out of bounds",
+        node: idx,
+    })
+}
+
+pub(crate) fn expect_operands(
+    apply: &Apply,
+    op: &Op,
+    expected: usize,
+    node: NodeIndex,
+) -> Result<(), EvalError> {
+    if apply.operands.len() != expected {
+        return Err(EvalError::ArityMismatch {
+            op: op.clone(),
+            expected,
+            got: apply.operands.len(),
+            node,
+        });
+    }
+    Ok(())
+}
+
+// Evaluates the value of a qualifier such as `logbase` or `degree` on an
+// apply, or returns `None` if the apply has no such qualifier.
+fn evaluate_qualifier(
+    nodes: &[MathNode],
+    apply: &Apply,
+    qualifier: Qualifier,
+    env: &dyn Environment,
+) -> Result<Option<f64>, EvalError> {
+    for &qualifier_idx in &apply.qualifiers {
+        if let MathNode::Qualifier(qualifiernode) = get_node(nodes, qualifier_idx)? {
+            if qualifiernode.qualifier.as_ref() == Some(&qualifier) {
+                let value_idx =
+                    *qualifiernode
+                        .children
+                        .first()
+                        .ok_or(EvalError::MalformedNode {
+                            reason: "qualifier has no value",
+                            node: qualifier_idx,
+                        })?;
+                let value = evaluate(nodes, value_idx, env)?;
+                return expect_real(value, value_idx).map(Some);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Evaluates the subtree rooted at `head_idx`.
+///
+/// SBML csymbols other than `avogadro` need a simulation state, see
+/// [`evaluate_node_with`].
 pub fn evaluate_node(
     nodes: &[MathNode],
     head_idx: NodeIndex,
     values: &HashMap<String, f64>,
     functions: &HashMap<String, Vec<MathNode>>,
-) -> Result<f64, String> {
-    let head = nodes[head_idx].clone();
-    //dbg!(values);
+) -> Result<Value, EvalError> {
+    evaluate_node_with(nodes, head_idx, values, functions, &SbmlContext::default())
+}
+
+/// Evaluates the subtree rooted at `head_idx` to a real number, failing
+/// with a type mismatch if it is a boolean or has an imaginary part.
+pub fn eval_f64(
+    nodes: &[MathNode],
+    head_idx: NodeIndex,
+    values: &HashMap<String, f64>,
+    functions: &HashMap<String, Vec<MathNode>>,
+) -> Result<f64, EvalError> {
+    expect_real(evaluate_node(nodes, head_idx, values, functions)?, head_idx)
+}
+
+pub(crate) fn expect_real(value: Value, node: NodeIndex) -> Result<f64, EvalError> {
+    value.as_f64().ok_or(EvalError::TypeMismatch {
+        expected: "real number",
+        node,
+    })
+}
+
+fn expect_number(value: Value, node: NodeIndex) -> Result<Value, EvalError> {
+    if value.is_number() {
+        Ok(value)
+    } else {
+        Err(EvalError::TypeMismatch {
+            expected: "number",
+            node,
+        })
+    }
+}
+
+fn expect_boolean(value: Value, node: NodeIndex) -> Result<bool, EvalError> {
+    value.as_bool().ok_or(EvalError::TypeMismatch {
+        expected: "boolean",
+        node,
+    })
+}
+
+/// Evaluates the subtree rooted at `head_idx` at the simulation state given
+/// by `context`.
+pub fn evaluate_node_with(
+    nodes: &[MathNode],
+    head_idx: NodeIndex,
+    values: &HashMap<String, f64>,
+    functions: &HashMap<String, Vec<MathNode>>,
+    context: &SbmlContext,
+) -> Result<Value, EvalError> {
+    let env = MapEnvironment::new(values, functions).with_context(*context);
+    evaluate(nodes, head_idx, &env)
+}
+
+/// Evaluates the subtree rooted at `head_idx`, looking up variables,
+/// functions and the simulation state in `env`.
+pub fn evaluate(
+    nodes: &[MathNode],
+    head_idx: NodeIndex,
+    env: &dyn Environment,
+) -> Result<Value, EvalError> {
+    let head = get_node(nodes, head_idx)?;
+    let eval = |idx| evaluate(nodes, idx, env);
+    let number = |idx| expect_number(eval(idx)?, idx);
+    let real = |idx| expect_real(eval(idx)?, idx);
+    let boolean = |idx| expect_boolean(eval(idx)?, idx);
     match head {
         MathNode::Root(root) => {
             if root.children.len() != 1 {
-                return Err("Root with multiple/zero children!".to_string());
+                return Err(EvalError::MalformedNode {
+                    reason: "root must have exactly one child",
+                    node: head_idx,
+                });
             }
-            evaluate_node(nodes, root.children[0], values, functions)
+            eval(root.children[0])
         }
         MathNode::Apply(apply) => {
+            let operator_idx = apply.operator.ok_or(EvalError::MalformedNode {
+                reason: "apply has no operator",
+                node: head_idx,
+            })?;
             let op_result = apply.get_op(nodes);
             // If this is a regular mathematical operator, go ahead
             if let Ok(op) = op_result {
                 match op {
                     Op::Times => {
-                        let mut result = 1.0;
-                        for operand_idx in apply.operands {
-                            result *= evaluate_node(nodes, operand_idx, values, functions)?;
+                        let mut result = Value::Integer(1);
+                        for &operand_idx in &apply.operands {
+                            result = result.multiply(number(operand_idx)?);
                         }
                         Ok(result)
                     }
                     Op::Plus => {
-                        let mut result = 0.0;
-                        for operand_idx in apply.operands {
-                            result += evaluate_node(nodes, operand_idx, values, functions)?;
+                        let mut result = Value::Integer(0);
+                        for &operand_idx in &apply.operands {
+                            result = result.add(number(operand_idx)?);
                         }
                         Ok(result)
                     }
-                    Op::Minus => {
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        match apply.operands.len() {
-                            1 => Ok(-a),
-                            2 => {
-                                let b = evaluate_node(nodes, apply.operands[1], values, functions)?;
-                                Ok(a - b)
-                            }
-                            _ => Err("Too many operands!".to_string()),
+                    Op::Minus => match apply.operands.len() {
+                        1 => {
+                            let a = number(apply.operands[0])?;
+                            Ok(a.negate())
                         }
-                    }
-                    Op::Divide => {
-                        if apply.operands.len() != 2 {
-                            return Err("Invalid number of operands.".to_string());
+                        2 => {
+                            let a = number(apply.operands[0])?;
+                            let b = number(apply.operands[1])?;
+                            Ok(a.subtract(b))
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        let b = evaluate_node(nodes, apply.operands[1], values, functions)?;
-                        Ok(a / b)
+                        got => Err(EvalError::ArityMismatch {
+                            op,
+                            expected: if got == 0 { 1 } else { 2 },
+                            got,
+                            node: head_idx,
+                        }),
+                    },
+                    Op::Divide => {
+                        expect_operands(apply, &op, 2, head_idx)?;
+                        let a = number(apply.operands[0])?;
+                        let b = number(apply.operands[1])?;
+                        Ok(a.divide(b))
                     }
                     Op::Power => {
-                        if apply.operands.len() != 2 {
-                            return Err("Invalid number of operands.".to_string());
-                        }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        let b = evaluate_node(nodes, apply.operands[1], values, functions)?;
-                        Ok(a.powf(b))
-                    }
-                    Op::Ceiling => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                        expect_operands(apply, &op, 2, head_idx)?;
+                        let a = number(apply.operands[0])?;
+                        let b = number(apply.operands[1])?;
+                        // integer powers of integers stay exact while they fit
+                        if let (Value::Integer(a), Value::Integer(b)) = (a, b) {
+                            if let Some(power) =
+                                u32::try_from(b).ok().and_then(|b| a.checked_pow(b))
+                            {
+                                return Ok(Value::Integer(power));
+                            }
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        Ok(round::ceil(a, 0))
+                        let a = expect_real(a, apply.operands[0])?;
+                        let b = expect_real(b, apply.operands[1])?;
+                        Ok(Value::Real(a.powf(b)))
                     }
-                    Op::Floor => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                    Op::Ceiling | Op::Floor => {
+                        expect_operands(apply, &op, 1, head_idx)?;
+                        match number(apply.operands[0])? {
+                            Value::Integer(i) => Ok(Value::Integer(i)),
+                            Value::Rational(n, d) if op == Op::Floor => {
+                                Ok(Value::Integer(n.div_euclid(d)))
+                            }
+                            Value::Rational(n, d) => Ok(Value::Integer(-(-n).div_euclid(d))),
+                            a => {
+                                let a = expect_real(a, apply.operands[0])?;
+                                if op == Op::Floor {
+                                    Ok(Value::Real(Unary::Floor.apply(a)))
+                                } else {
+                                    Ok(Value::Real(Unary::Ceiling.apply(a)))
+                                }
+                            }
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        Ok(round::floor(a, 0))
                     }
                     Op::Factorial => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                        expect_operands(apply, &op, 1, head_idx)?;
+                        let value = number(apply.operands[0])?;
+                        let a = expect_real(value, apply.operands[0])?;
+                        if a < 0.0 || a.fract() != 0.0 {
+                            return Err(EvalError::DomainError {
+                                op,
+                                reason: "factorial of a negative or non-integer number",
+                                node: head_idx,
+                            });
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        Ok(factorial(a as u32) as f64)
+                        let exact = match value {
+                            Value::Integer(n) => (1..=n).try_fold(1_i64, i64::checked_mul),
+                            _ => None,
+                        };
+                        Ok(exact
+                            .map_or_else(|| Value::Real(Unary::Factorial.apply(a)), Value::Integer))
                     }
-                    Op::Quotient => {
-                        if apply.operands.len() != 2 {
-                            return Err("Invalid number of operands.".to_string());
+                    Op::Quotient | Op::Rem => {
+                        expect_operands(apply, &op, 2, head_idx)?;
+                        // reals are truncated to integers
+                        let integer = |idx| match number(idx)? {
+                            Value::Integer(i) => Ok(i),
+                            value => expect_real(value, idx).map(|r| r as i64),
+                        };
+                        let a = integer(apply.operands[0])?;
+                        let b = integer(apply.operands[1])?;
+                        if b == 0 {
+                            return Err(EvalError::DomainError {
+                                op,
+                                reason: "division by zero",
+                                node: head_idx,
+                            });
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        let b = evaluate_node(nodes, apply.operands[1], values, functions)?;
-                        Ok(((a as i32) / (b as i32)) as f64)
+                        Ok(integer_division(op == Op::Quotient, a, b))
                     }
-                    Op::Rem => {
-                        if apply.operands.len() != 2 {
-                            return Err("Invalid number of operands.".to_string());
+                    Op::Min | Op::Max => {
+                        // like f64::min and f64::max, NaN operands are ignored
+                        let mut result: Option<(f64, Value)> = None;
+                        for &operand_idx in &apply.operands {
+                            let value = number(operand_idx)?;
+                            let current = expect_real(value, operand_idx)?;
+                            let replace = match result {
+                                None => true,
+                                Some((best, _)) if op == Op::Min => best.is_nan() || current < best,
+                                Some((best, _)) => best.is_nan() || current > best,
+                            };
+                            if replace {
+                                result = Some((current, value));
+                            }
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        let b = evaluate_node(nodes, apply.operands[1], values, functions)?;
-                        Ok(((a as i32) % (b as i32)) as f64)
+                        Ok(match result {
+                            Some((_, value)) => value,
+                            None if op == Op::Min => Value::Real(f64::MAX),
+                            None => Value::Real(f64::MIN),
+                        })
                     }
-                    Op::Min => {
-                        let mut min_result = f64::MAX;
-                        for i in 0..apply.operands.len() {
-                            let current =
-                                evaluate_node(nodes, apply.operands[i], values, functions)?;
-                            min_result = f64::min(min_result, current);
+                    Op::Log => {
+                        expect_operands(apply, &op, 1, head_idx)?;
+                        // MathML logarithms are base 10 unless a logbase is given
+                        let base = evaluate_qualifier(nodes, apply, Qualifier::LogBase, env)?
+                            .unwrap_or(10.0);
+                        let a = real(apply.operands[0])?;
+                        if base <= 0.0 || base == 1.0 {
+                            return Err(EvalError::DomainError {
+                                op,
+                                reason: "logarithm base must be positive and not 1",
+                                node: head_idx,
+                            });
                         }
-                        Ok(min_result)
-                    }
-                    Op::Max => {
-                        let mut max_result = f64::MIN;
-                        for i in 0..apply.operands.len() {
-                            let current =
-                                evaluate_node(nodes, apply.operands[i], values, functions)?;
-                            max_result = f64::max(max_result, current);
+                        if a <= 0.0 {
+                            return Err(EvalError::DomainError {
+                                op,
+                                reason: "logarithm of a non-positive number",
+                                node: head_idx,
+                            });
                         }
-                        Ok(max_result)
-                    }
-                    Op::Exp => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                        if base == 10.0 {
+                            Ok(Value::Real(a.log10()))
+                        } else {
+                            Ok(Value::Real(a.log(base)))
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        Ok(a.exp())
                     }
-                    Op::Ln => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                    Op::Root => {
+                        expect_operands(apply, &op, 1, head_idx)?;
+                        // MathML roots are square roots unless a degree is given
+                        let degree = evaluate_qualifier(nodes, apply, Qualifier::Degree, env)?
+                            .unwrap_or(2.0);
+                        let a = real(apply.operands[0])?;
+                        if degree == 0.0 {
+                            return Err(EvalError::DomainError {
+                                op,
+                                reason: "root of degree zero",
+                                node: head_idx,
+                            });
+                        }
+                        if a >= 0.0 {
+                            Ok(Value::Real(a.powf(1.0 / degree)))
+                        } else if degree.fract() == 0.0 && degree % 2.0 != 0.0 {
+                            // odd roots of negative numbers are real
+                            Ok(Value::Real(-(-a).powf(1.0 / degree)))
+                        } else {
+                            Err(EvalError::DomainError {
+                                op,
+                                reason: "even or fractional root of a negative number",
+                                node: head_idx,
+                            })
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        Ok(a.ln())
                     }
-                    // TRIGONOMETRIC FUNCTIONS
-                    Op::Sin
+                    // EXPONENTIAL AND TRIGONOMETRIC FUNCTIONS
+                    Op::Exp
+                    | Op::Ln
+                    | Op::Sin
                     | Op::Cos
                     | Op::Tan
                     | Op::Sec
@@ -161,476 +548,812 @@ pub fn evaluate_node(
                     | Op::Arcsech
                     | Op::Arccsch
                     | Op::Arccoth => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                        expect_operands(apply, &op, 1, head_idx)?;
+                        let a = real(apply.operands[0])?;
+
+                        match Unary::from_op(&op) {
+                            Some(unary) => Ok(Value::Real(unary.apply(a))),
+                            None => Err(EvalError::UnsupportedOperator { op, node: head_idx }),
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-
-                        if op == Op::Sin {
-                            Ok(a.sin())
-                        } else if op == Op::Cos {
-                            Ok(a.cos())
-                        } else if op == Op::Tan {
-                            Ok(a.tan())
-                        } else if op == Op::Sec {
-                            Ok(1.0 / a.cos())
-                        } else if op == Op::Csc {
-                            Ok(1.0 / a.sin())
-                        } else if op == Op::Cot {
-                            Ok(1.0 / a.tan())
-                        } else if op == Op::Sinh {
-                            Ok(a.sinh())
-                        } else if op == Op::Cosh {
-                            Ok(a.cosh())
-                        } else if op == Op::Tanh {
-                            Ok(a.tanh())
-                        } else if op == Op::Sech {
-                            Ok(1.0 / a.cosh())
-                        } else if op == Op::Csch {
-                            Ok(1.0 / a.sinh())
-                        } else if op == Op::Coth {
-                            Ok(1.0 / a.tanh())
-                        } else if op == Op::Arcsin {
-                            Ok(a.asin())
-                        } else if op == Op::Arccos {
-                            Ok(a.acos())
-                        } else if op == Op::Arctan {
-                            Ok(a.atan())
-                        } else if op == Op::Arcsec {
-                            Ok((1.0 / a).acos())
-                        } else if op == Op::Arccsc {
-                            Ok((1.0 / a).asin())
-                        } else if op == Op::Arccot {
-                            if a > 0.0 {
-                                Ok((1.0 / a).atan())
-                            } else {
-                                Ok(std::f64::consts::PI + (1.0 / a).atan())
+                    }
+                    Op::Abs => {
+                        expect_operands(apply, &op, 1, head_idx)?;
+                        let a = number(apply.operands[0])?;
+                        Ok(a.abs())
+                    }
+                    Op::Eq | Op::Neq => {
+                        if op == Op::Neq {
+                            expect_operands(apply, &op, 2, head_idx)?;
+                        }
+                        let mut result = true;
+                        let mut previous: Option<Value> = None;
+                        for &operand_idx in &apply.operands {
+                            let current = eval(operand_idx)?;
+                            if let Some(previous) = previous {
+                                let equal =
+                                    previous.equals(current).ok_or(EvalError::TypeMismatch {
+                                        expected: if previous.is_number() {
+                                            "number"
+                                        } else {
+                                            "boolean"
+                                        },
+                                        node: operand_idx,
+                                    })?;
+                                result = result && equal;
                             }
-                        } else if op == Op::Arcsinh {
-                            Ok(a.asinh())
-                        } else if op == Op::Arccosh {
-                            Ok(a.acosh())
-                        } else if op == Op::Arctanh {
-                            Ok(a.atanh())
-                        } else if op == Op::Arcsech {
-                            Ok(1.0 / a.acosh())
-                        } else if op == Op::Arccsch {
-                            Ok(1.0 / a.asinh())
-                        } else if op == Op::Arccoth {
-                            Ok(1.0 / a.atanh())
+                            previous = Some(current);
+                        }
+                        if op == Op::Neq {
+                            Ok(Value::Boolean(!result))
                         } else {
-                            Err("Invalid trig operator.".to_string())
+                            Ok(Value::Boolean(result))
                         }
                     }
-                    Op::Abs => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
+                    Op::Geq | Op::Leq | Op::Gt | Op::Lt => {
+                        let mut operand_results = Vec::new();
+                        for &operand_idx in &apply.operands {
+                            operand_results.push(real(operand_idx)?);
                         }
-                        let a = evaluate_node(nodes, apply.operands[0], values, functions)?;
-                        Ok(a.abs())
+                        let result = operand_results.windows(2).all(|pair| match op {
+                            Op::Geq => pair[0] >= pair[1],
+                            Op::Leq => pair[0] <= pair[1],
+                            Op::Gt => pair[0] > pair[1],
+                            _ => pair[0] < pair[1],
+                        });
+                        Ok(Value::Boolean(result))
                     }
-                    Op::And
-                    | Op::Or
-                    | Op::Xor
-                    | Op::Implies
-                    | Op::Eq
-                    | Op::Neq
-                    | Op::Geq
-                    | Op::Leq
-                    | Op::Gt
-                    | Op::Lt => {
-                        let condition_result =
-                            evaluate_condition(nodes, head_idx, values, functions)?;
-                        if condition_result {
-                            Ok(1.0)
-                        } else {
-                            Ok(0.0)
+                    Op::And | Op::Or | Op::Xor | Op::Not | Op::Implies => {
+                        match op {
+                            Op::Not => expect_operands(apply, &op, 1, head_idx)?,
+                            Op::Implies => expect_operands(apply, &op, 2, head_idx)?,
+                            _ => {}
+                        }
+                        let mut child_condition_results = Vec::new();
+                        for &operand_idx in &apply.operands {
+                            child_condition_results.push(boolean(operand_idx)?);
                         }
+                        let true_count = child_condition_results.iter().filter(|x| **x).count();
+                        let result = match op {
+                            Op::And => true_count == child_condition_results.len(),
+                            Op::Or => true_count > 0,
+                            Op::Xor => true_count % 2 == 1,
+                            // false only if the first argument is true and
+                            // the second one is false
+                            Op::Implies => {
+                                !child_condition_results[0] || child_condition_results[1]
+                            }
+                            _ => !child_condition_results[0],
+                        };
+                        Ok(Value::Boolean(result))
                     }
-                    _ => panic!("Evaluation not supported for operator."),
+                    _ => Err(EvalError::UnsupportedOperator { op, node: head_idx }),
                 }
+            } else if let MathNode::Csymbol(csymbol) = get_node(nodes, operator_idx)? {
+                evaluate_csymbol_call(nodes, apply, csymbol, env)
             } else {
-                // Evaluate as a lambda function
-                let mut res = None;
-                if let MathNode::Ci(ci) = &nodes[apply.operator.unwrap()] {
-                    let lambda_name = ci.name.as_ref().unwrap();
-                    if let Some(lambda) = functions.get(lambda_name) {
-                        let mut argument_values = Vec::new();
-                        for operand in apply.operands {
-                            argument_values.push(evaluate_node(nodes, operand, values, functions)?);
-                        }
-                        res = Some(evaluate_lambda(
-                            lambda,
-                            0,
-                            &argument_values,
-                            values,
-                            functions,
-                        )?);
-                        //dbg!(lambda_name, res);
+                // Evaluate as a call to a named or inline lambda function
+                let (lambda_nodes, lambda_idx) = match get_node(nodes, operator_idx)? {
+                    MathNode::Ci(ci) => {
+                        let lambda_name = ci.name.as_ref().ok_or(EvalError::MalformedNode {
+                            reason: "ci element with no content",
+                            node: operator_idx,
+                        })?;
+                        let lambda = env.function(lambda_name).ok_or_else(|| {
+                            EvalError::UnknownFunction {
+                                name: lambda_name.clone(),
+                                node: operator_idx,
+                            }
+                        })?;
+                        (lambda, 0)
                     }
+                    MathNode::Lambda(..) => (nodes, operator_idx),
+                    _ => {
+                        return Err(EvalError::MalformedNode {
+                            reason: "invalid operator",
+                            node: operator_idx,
+                        })
+                    }
+                };
+                let mut argument_values = Vec::new();
+                for &operand in &apply.operands {
+                    argument_values.push(eval(operand)?);
                 }
-                if let Some(value) = res {
-                    Ok(value)
-                } else {
-                    panic!("Invalid operator");
-                }
+                evaluate_lambda(lambda_nodes, lambda_idx, &argument_values, env)
             }
         }
-        MathNode::Cn(cn) => match &cn.r#type {
-            Some(NumType::Integer) => {
-                if let Some(Number::Integer(i)) = cn.value {
-                    let result = i.into();
-                    //println!("Returning {} from cn", result);
-                    Ok(result)
-                } else {
-                    panic!("Wrong type");
-                }
+        MathNode::Cn(cn) => match (&cn.r#type, &cn.value) {
+            (Some(NumType::Integer), Some(Number::Integer(i))) => Ok(Value::Integer(*i)),
+            (Some(NumType::Real), Some(Number::Real(r)))
+            | (Some(NumType::HexDouble), Some(Number::Real(r)))
+            | (None, Some(Number::Real(r))) => Ok(Value::Real(*r)),
+            (Some(NumType::Rational), Some(Number::Rational(x, y))) => Ok(Value::rational(*x, *y)),
+            (Some(NumType::ENotation), Some(Number::ENotation(x, y))) => {
+                Ok(Value::Real(x * 10.0_f64.powf(*y as f64)))
             }
-            Some(NumType::Real) | None => {
-                if let Some(Number::Real(r)) = cn.value {
-                    Ok(r)
-                } else {
-                    panic!("Wrong type");
-                }
-            }
-            Some(NumType::Rational) => {
-                if let Some(Number::Rational(x, y)) = cn.value {
-                    Ok((x as f64) / (y as f64))
-                } else {
-                    panic!("Wrong type");
-                }
+            (Some(NumType::ComplexCartesian), Some(Number::ComplexCartesian(re, im))) => {
+                Ok(Value::Complex(*re, *im))
             }
-            Some(NumType::ENotation) => {
-                if let Some(Number::ENotation(x, y)) = cn.value {
-                    Ok(x * 10.0_f64.powf(y as f64))
-                } else {
-                    panic!("Wrong type");
-                }
+            (Some(NumType::ComplexPolar), Some(Number::ComplexPolar(r, theta))) => {
+                Ok(Value::Complex(r * theta.cos(), r * theta.sin()))
             }
-            _ => panic!("Wrong type"),
+            (_, None) => Err(EvalError::MalformedNode {
+                reason: "cn element with no content",
+                node: head_idx,
+            }),
+            _ => Err(EvalError::TypeMismatch {
+                expected: "number",
+                node: head_idx,
+            }),
         },
         MathNode::Ci(ci) => {
-            let name = ci.name.expect("Ci element with no content!");
-            if values.contains_key(&name) {
-                let result = *values.get(&name).unwrap();
-                //println!("Returning {} from ci", result);
-                Ok(result)
-            } else {
-                let error = format!("No value found for Ci {}", name);
-                Err(error)
+            let name = ci.name.as_ref().ok_or(EvalError::MalformedNode {
+                reason: "ci element with no content",
+                node: head_idx,
+            })?;
+            match env.variable(name) {
+                Some(result) => Ok(result),
+                None => Err(EvalError::UnboundVariable {
+                    name: name.clone(),
+                    node: head_idx,
+                }),
             }
         }
-        MathNode::Piecewise(..) => Ok(evaluate_piecewise(nodes, head_idx, values, functions)?),
+        MathNode::Csymbol(csymbol) => match csymbol.sbml_symbol() {
+            Some(SbmlSymbol::Time) => {
+                env.time()
+                    .map(Value::Real)
+                    .ok_or(EvalError::UnavailableSymbol {
+                        name: SbmlSymbol::Time.to_string(),
+                        node: head_idx,
+                    })
+            }
+            Some(SbmlSymbol::Avogadro) => Ok(Value::Real(AVOGADRO)),
+            Some(..) => Err(EvalError::MalformedNode {
+                reason: "csymbol function used without arguments",
+                node: head_idx,
+            }),
+            None => Err(EvalError::UnavailableSymbol {
+                name: csymbol_name(csymbol),
+                node: head_idx,
+            }),
+        },
+        MathNode::Piecewise(..) => Ok(evaluate_piecewise(nodes, head_idx, env)?),
         MathNode::Constant(constant_node) => match constant_node.constant {
-            Some(Constant::Pi) => Ok(std::f64::consts::PI),
-            Some(Constant::ExponentialE) => Ok(std::f64::consts::E),
-            Some(Constant::True) => Ok(1.0),
-            Some(Constant::False) => Ok(0.0),
-            _ => Err("Invalid constant".to_string()),
+            Some(Constant::Pi) => Ok(Value::Real(std::f64::consts::PI)),
+            Some(Constant::ExponentialE) => Ok(Value::Real(std::f64::consts::E)),
+            Some(Constant::EulerGamma) => Ok(Value::Real(EULER_GAMMA)),
+            Some(Constant::Infinity) => Ok(Value::Real(f64::INFINITY)),
+            Some(Constant::NotANumber) => Ok(Value::Real(f64::NAN)),
+            Some(Constant::ImaginaryI) => Ok(Value::Complex(0.0, 1.0)),
+            Some(Constant::True) => Ok(Value::Boolean(true)),
+            Some(Constant::False) => Ok(Value::Boolean(false)),
+            None => Err(EvalError::MalformedNode {
+                reason: "constant element with no value",
+                node: head_idx,
+            }),
         },
+        // annotations do not change the value
+        MathNode::Semantics(semantics) => eval(semantics.expr.ok_or(EvalError::MalformedNode {
+            reason: "semantics element with no expression",
+            node: head_idx,
+        })?),
 
+        _ => Err(EvalError::MalformedNode {
+            reason: "node cannot be evaluated on its own",
+            node: head_idx,
+        }),
+    }
+}
+
+// Evaluates `delay(x, d)` and `rateOf(x)`, whose first argument must be a
+// variable, by asking the simulation state.
+fn evaluate_csymbol_call(
+    nodes: &[MathNode],
+    apply: &Apply,
+    csymbol: &Csymbol,
+    env: &dyn Environment,
+) -> Result<Value, EvalError> {
+    let operator_idx = apply.operator.unwrap_or_default();
+    let symbol = match csymbol.sbml_symbol() {
+        Some(symbol @ SbmlSymbol::Delay) | Some(symbol @ SbmlSymbol::RateOf) => symbol,
+        _ => {
+            return Err(EvalError::UnavailableSymbol {
+                name: csymbol_name(csymbol),
+                node: operator_idx,
+            })
+        }
+    };
+    let expected = if symbol == SbmlSymbol::Delay { 2 } else { 1 };
+    if apply.operands.len() != expected {
+        return Err(EvalError::ArgumentMismatch {
+            name: symbol.to_string(),
+            expected,
+            got: apply.operands.len(),
+            node: operator_idx,
+        });
+    }
+    let variable_idx = apply.operands[0];
+    let variable = match get_node(nodes, variable_idx)? {
+        MathNode::Ci(ci) => ci.name.as_deref().ok_or(EvalError::MalformedNode {
+            reason: "ci element with no content",
+            node: variable_idx,
+        })?,
         _ => {
-            panic!("Couldn't evaluate operator {}", head);
+            return Err(EvalError::TypeMismatch {
+                expected: "variable",
+                node: variable_idx,
+            })
         }
+    };
+    let unavailable = |name: String| EvalError::UnavailableSymbol {
+        name,
+        node: operator_idx,
+    };
+    if symbol == SbmlSymbol::RateOf {
+        env.rate_of(variable)
+            .map(Value::Real)
+            .ok_or_else(|| unavailable(format!("rateOf({})", variable)))
+    } else {
+        let delay_idx = apply.operands[1];
+        let delay = expect_real(evaluate(nodes, delay_idx, env)?, delay_idx)?;
+        env.delay(variable, delay)
+            .map(Value::Real)
+            .ok_or_else(|| unavailable(format!("delay({}, {})", variable, delay)))
     }
 }
 
+// Names a csymbol in error messages.
+/// The quotient or remainder of integer division. Only `i64::MIN / -1`
+/// overflows, and becomes a real.
+pub(crate) fn integer_division(quotient: bool, a: i64, b: i64) -> Value {
+    let (a_real, b_real) = (a as f64, b as f64);
+    if quotient {
+        a.checked_div(b)
+            .map_or(Value::Real((a_real / b_real).trunc()), Value::Integer)
+    } else {
+        a.checked_rem(b)
+            .map_or(Value::Real(a_real % b_real), Value::Integer)
+    }
+}
+
+pub(crate) fn csymbol_name(csymbol: &Csymbol) -> String {
+    csymbol
+        .text
+        .clone()
+        .or_else(|| csymbol.definition_url.clone())
+        .unwrap_or_default()
+}
+
 pub fn evaluate_lambda(
     nodes: &[MathNode],
     head_idx: NodeIndex,
-    argument_values: &[f64],
-    values: &HashMap<String, f64>,
-    functions: &HashMap<String, Vec<MathNode>>,
-) -> Result<f64, String> {
-    let head = nodes[head_idx].clone();
+    argument_values: &[Value],
+    env: &dyn Environment,
+) -> Result<Value, EvalError> {
+    let head = get_node(nodes, head_idx)?;
     match head {
         MathNode::Root(root) => {
             if root.children.len() != 1 {
-                return Err("Root with multiple/zero children!".to_string());
+                return Err(EvalError::MalformedNode {
+                    reason: "root must have exactly one child",
+                    node: head_idx,
+                });
             }
-            evaluate_lambda(nodes, root.children[0], argument_values, values, functions)
+            evaluate_lambda(nodes, root.children[0], argument_values, env)
+        }
+        // an annotated function definition
+        MathNode::Semantics(semantics) => {
+            let expr_idx = semantics.expr.ok_or(EvalError::MalformedNode {
+                reason: "semantics element with no expression",
+                node: head_idx,
+            })?;
+            evaluate_lambda(nodes, expr_idx, argument_values, env)
         }
         MathNode::Lambda(lambda) => {
             let mut argument_names = Vec::new();
-            for binding in lambda.bindings {
-                if let MathNode::BVar(bvar) = nodes[binding].clone() {
-                    for child in bvar.children {
-                        if let MathNode::Ci(ci) = nodes[child].clone() {
-                            argument_names.push(ci.name.unwrap());
+            for &binding in &lambda.bindings {
+                if let MathNode::BVar(bvar) = get_node(nodes, binding)? {
+                    for &child in &bvar.children {
+                        if let MathNode::Ci(ci) = get_node(nodes, child)? {
+                            let name = ci.name.clone().ok_or(EvalError::MalformedNode {
+                                reason: "ci element with no content",
+                                node: child,
+                            })?;
+                            argument_names.push(name);
                         }
                     }
                 }
             }
 
             if argument_values.len() != argument_names.len() {
-                Err("Argument names and values mismatch".to_string())
+                Err(EvalError::ArgumentMismatch {
+                    name: "lambda".to_string(),
+                    expected: argument_names.len(),
+                    got: argument_values.len(),
+                    node: head_idx,
+                })
             } else {
-                let mut assignments: HashMap<String, f64> = HashMap::new();
-                for i in 0..argument_values.len() {
-                    assignments.insert(argument_names[i].clone(), argument_values[i]);
-                }
-                let res = evaluate_node(nodes, lambda.expr.unwrap(), &assignments, functions)?;
-                Ok(res)
+                let arguments = Arguments {
+                    bindings: argument_names
+                        .into_iter()
+                        .zip(argument_values.iter().copied())
+                        .collect(),
+                    outer: env,
+                };
+                let expr_idx = lambda.expr.ok_or(EvalError::MalformedNode {
+                    reason: "lambda has no expression",
+                    node: head_idx,
+                })?;
+                evaluate(nodes, expr_idx, &arguments)
             }
         }
-        _ => evaluate_node(nodes, head_idx, values, functions),
+        _ => evaluate(nodes, head_idx, env),
     }
 }
 
 pub fn evaluate_piecewise(
     nodes: &[MathNode],
     head_idx: NodeIndex,
-    values: &HashMap<String, f64>,
-    functions: &HashMap<String, Vec<MathNode>>,
-) -> Result<f64, String> {
-    let head = nodes[head_idx].clone();
+    env: &dyn Environment,
+) -> Result<Value, EvalError> {
+    let head = get_node(nodes, head_idx)?;
     match head {
         MathNode::Piecewise(piecewise) => {
-            let pieces_idx = piecewise.pieces;
-            let otherwise_idx = piecewise.otherwise;
             let mut result = None;
-            for piece_idx in pieces_idx {
-                let (condition, value) = evaluate_piece(nodes, piece_idx, values, functions)?;
-                if condition {
-                    if let Some(..) = value {
-                        result = value;
-                        break;
-                    }
+            for &piece_idx in &piecewise.pieces {
+                let (condition, value) = evaluate_piece(nodes, piece_idx, env)?;
+                if condition && value.is_some() {
+                    result = value;
+                    break;
                 }
             }
             if let Some(value) = result {
                 Ok(value)
-            } else if let Some(otherwise_idx_value) = otherwise_idx {
-                Ok(evaluate_piecewise(
-                    nodes,
-                    otherwise_idx_value,
-                    values,
-                    functions,
-                )?)
+            } else if let Some(otherwise_idx_value) = piecewise.otherwise {
+                Ok(evaluate_piecewise(nodes, otherwise_idx_value, env)?)
             } else {
-                Err("All pieces evaluated to false and no otherwise branch found.".to_string())
+                Err(EvalError::NoPieceMatched { node: head_idx })
             }
         }
         MathNode::Otherwise(otherwise) => {
-            let expr_idx = otherwise.expr.expect("Otherwise branch is empty!");
-            Ok(evaluate_node(nodes, expr_idx, values, functions)?)
-        }
-        _ => {
-            //dbg!(head);
-            panic!("haha couldn't parse");
+            let expr_idx = otherwise.expr.ok_or(EvalError::MalformedNode {
+                reason: "otherwise branch is empty",
+                node: head_idx,
+            })?;
+            Ok(evaluate(nodes, expr_idx, env)?)
         }
+        _ => Err(EvalError::MalformedNode {
+            reason: "expected piecewise or otherwise",
+            node: head_idx,
+        }),
     }
 }
 
 pub fn evaluate_piece(
     nodes: &[MathNode],
     head_idx: NodeIndex,
-    values: &HashMap<String, f64>,
-    functions: &HashMap<String, Vec<MathNode>>,
-) -> Result<(bool, Option<f64>), String> {
-    let head = nodes[head_idx].clone();
+    env: &dyn Environment,
+) -> Result<(bool, Option<Value>), EvalError> {
+    let head = get_node(nodes, head_idx)?;
     match head {
         MathNode::Piece(piece) => {
-            let expr_idx = piece.expr.expect("Piece has no expression!");
-            let condition_idx = piece.condition.expect("Piece condition is empty!");
-            let condition_result = evaluate_condition(nodes, condition_idx, values, functions)?;
+            let expr_idx = piece.expr.ok_or(EvalError::MalformedNode {
+                reason: "piece has no expression",
+                node: head_idx,
+            })?;
+            let condition_idx = piece.condition.ok_or(EvalError::MalformedNode {
+                reason: "piece has no condition",
+                node: head_idx,
+            })?;
+            let condition_result = evaluate_condition(nodes, condition_idx, env)?;
             if condition_result {
-                let expr_result = evaluate_node(nodes, expr_idx, values, functions)?;
+                let expr_result = evaluate(nodes, expr_idx, env)?;
                 Ok((true, Some(expr_result)))
             } else {
                 Ok((false, None))
             }
         }
-        _ => {
-            panic!("haha couldn't parse");
-        }
+        _ => Err(EvalError::MalformedNode {
+            reason: "expected piece",
+            node: head_idx,
+        }),
     }
 }
 
 pub fn evaluate_condition(
     nodes: &[MathNode],
     head_idx: NodeIndex,
-    values: &HashMap<String, f64>,
-    functions: &HashMap<String, Vec<MathNode>>,
-) -> Result<bool, String> {
-    let head = nodes[head_idx].clone();
-    match head {
-        MathNode::Constant(constantnode) => {
-            if let Some(constant) = constantnode.constant {
-                match constant {
-                    Constant::False => Ok(false),
-                    Constant::True => Ok(true),
-                    _ => panic!("haha"),
-                }
-            } else {
-                panic!("hh");
-            }
+    env: &dyn Environment,
+) -> Result<bool, EvalError> {
+    let value = evaluate(nodes, head_idx, env)?;
+    expect_boolean(value, head_idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::convert::TryFrom;
+    #[test]
+    fn evaluation_errors() {
+        let values = std::collections::HashMap::new();
+        let functions = std::collections::HashMap::new();
+        let evaluate = |math: &str| {
+            let tree = parse_str(math).unwrap();
+            evaluate_node(tree.nodes(), tree.root(), &values, &functions)
+        };
+
+        assert_eq!(
+            evaluate("<math><apply><plus/><ci>k</ci><cn>1</cn></apply></math>"),
+            Err(EvalError::UnboundVariable {
+                name: "k".to_string(),
+                node: 3
+            })
+        );
+        assert_eq!(
+            evaluate("<math><apply><ci>f</ci><cn>1</cn></apply></math>"),
+            Err(EvalError::UnknownFunction {
+                name: "f".to_string(),
+                node: 2
+            })
+        );
+        assert_eq!(
+            evaluate("<math><apply><divide/><cn>1</cn></apply></math>"),
+            Err(EvalError::ArityMismatch {
+                op: Op::Divide,
+                expected: 2,
+                got: 1,
+                node: 1
+            })
+        );
+        assert_eq!(
+            evaluate("<math><apply><quotient/><cn>1</cn><cn>0</cn></apply></math>"),
+            Err(EvalError::DomainError {
+                op: Op::Quotient,
+                reason: "division by zero",
+                node: 1
+            })
+        );
+        assert_eq!(
+            evaluate("<math><piecewise><piece><cn>1</cn><false/></piece></piecewise></math>"),
+            Err(EvalError::NoPieceMatched { node: 1 })
+        );
+
+        // factorials too large for an integer become reals, then infinity
+        assert_eq!(
+            evaluate("<math><apply><factorial/><cn>21</cn></apply></math>"),
+            Ok(Value::Real(51090942171709440000.0))
+        );
+        assert_eq!(
+            evaluate("<math><apply><factorial/><cn type=\"integer\">21</cn></apply></math>"),
+            Ok(Value::Real(51090942171709440000.0))
+        );
+        assert_eq!(
+            evaluate("<math><apply><factorial/><cn>171</cn></apply></math>"),
+            Ok(Value::Real(f64::INFINITY))
+        );
+        assert_eq!(
+            evaluate("<math><apply><factorial/><cn>1e9</cn></apply></math>"),
+            Ok(Value::Real(f64::INFINITY))
+        );
+        assert_eq!(
+            evaluate("<math><piecewise><piece><cn>1</cn><pi/></piece></piecewise></math>"),
+            Err(EvalError::TypeMismatch {
+                expected: "boolean",
+                node: 4
+            })
+        );
+    }
+
+    #[test]
+    fn inverse_trigonometric_values() {
+        let values = std::collections::HashMap::new();
+        let functions = std::collections::HashMap::new();
+        let evaluate = |formula: &str| {
+            let tree = parse_formula(formula).unwrap();
+            eval_f64(tree.nodes(), tree.root(), &values, &functions).unwrap()
+        };
+        let cases = [
+            ("arccot(0)", std::f64::consts::FRAC_PI_2),
+            ("arccot(1)", std::f64::consts::FRAC_PI_4),
+            ("arccot(-1)", 3.0 * std::f64::consts::FRAC_PI_4),
+            ("arcsech(0.5)", 2.0_f64.acosh()),
+            ("arccsch(2)", 0.5_f64.asinh()),
+            ("arccoth(2)", 0.5_f64.atanh()),
+            ("arcsec(2)", 0.5_f64.acos()),
+            ("arccsc(2)", 0.5_f64.asin()),
+        ];
+        for (formula, expected) in cases.iter() {
+            let result = evaluate(formula);
+            assert!(
+                (result - expected).abs() < 1e-12,
+                "{} = {}",
+                formula,
+                result
+            );
         }
-        MathNode::Apply(apply) => {
-            let op_result = apply.get_op(nodes);
-            let mut result = None;
-            // If this is a regular mathematical operator, go ahead
-            if let Ok(op) = op_result.clone() {
-                let mut operand_results = Vec::<f64>::new();
-                let mut child_condition_results = Vec::<bool>::new();
-                match op {
-                    Op::Eq | Op::Neq | Op::Geq | Op::Leq | Op::Gt | Op::Lt => {
-                        if Op::Neq == op {
-                            if apply.operands.len() != 2 {
-                                return Err("Invalid number of operands.".to_string());
-                            }
-                        }
-                        for operand_location in &apply.operands {
-                            operand_results.push(evaluate_node(
-                                nodes,
-                                *operand_location,
-                                values,
-                                functions,
-                            )?);
-                        }
-                    }
-                    Op::And | Op::Or | Op::Xor => {
-                        for operand_location in &apply.operands {
-                            child_condition_results.push(evaluate_condition(
-                                nodes,
-                                *operand_location,
-                                values,
-                                functions,
-                            )?);
-                        }
-                    }
-                    Op::Not => {
-                        if apply.operands.len() != 1 {
-                            return Err("Invalid number of operands.".to_string());
-                        }
-                        for operand_location in &apply.operands {
-                            child_condition_results.push(evaluate_condition(
-                                nodes,
-                                *operand_location,
-                                values,
-                                functions,
-                            )?);
-                        }
-                    }
-                    Op::Implies => {
-                        if apply.operands.len() != 2 {
-                            return Err("Invalid number of operands.".to_string());
-                        }
-                        for operand_location in &apply.operands {
-                            child_condition_results.push(evaluate_condition(
-                                nodes,
-                                *operand_location,
-                                values,
-                                functions,
-                            )?);
-                        }
-                    }
-                    _ => {}
-                }
+    }
 
-                let condition_count = child_condition_results.len();
-                let true_count = child_condition_results.iter().filter(|x| **x).count();
-                match op {
-                    Op::Eq => {
-                        let mut x = true;
-                        for i in 1..operand_results.len() {
-                            let current =
-                                (operand_results[i - 1] - operand_results[i]).abs() <= f64::EPSILON;
-                            x = x && current;
-                            if !x {
-                                break;
-                            }
-                        }
-                        result = Some(x);
-                    }
-                    Op::Neq => {
-                        let current =
-                            (operand_results[0] - operand_results[1]).abs() > f64::EPSILON;
-                        result = Some(current);
-                    }
-                    Op::Gt => {
-                        let mut x = true;
-                        for i in 1..operand_results.len() {
-                            let current = operand_results[i - 1] > operand_results[i];
-                            x = x && current;
-                            if !x {
-                                break;
-                            }
-                        }
-                        result = Some(x);
-                    }
-                    Op::Lt => {
-                        let mut x = true;
-                        for i in 1..operand_results.len() {
-                            let current = operand_results[i - 1] < operand_results[i];
-                            x = x && current;
-                            if !x {
-                                break;
-                            }
-                        }
-                        result = Some(x);
-                    }
-                    Op::Geq => {
-                        let mut x = true;
-                        for i in 1..operand_results.len() {
-                            let current = operand_results[i - 1] >= operand_results[i];
-                            x = x && current;
-                            if !x {
-                                break;
-                            }
-                        }
-                        result = Some(x);
-                    }
-                    Op::Leq => {
-                        let mut x = true;
-                        for i in 1..operand_results.len() {
-                            let current = operand_results[i - 1] <= operand_results[i];
-                            x = x && current;
-                            if !x {
-                                break;
-                            }
-                        }
-                        result = Some(x);
-                    }
-                    Op::And => result = Some(condition_count == true_count),
-                    Op::Or => result = Some(true_count > 0),
-                    Op::Xor => {
-                        result = Some(true_count % 2 == 1);
-                    }
-                    Op::Implies => {
-                        // It evaluates to false if
-                        //      the first argument is true and
-                        //      the second argument is false,
-                        // otherwise it evaluates to true.
-                        result = Some(!(child_condition_results[0] && !child_condition_results[1]));
-                    }
-                    Op::Not => {
-                        result = Some(!(child_condition_results[0]));
-                    }
-                    Op::Times
-                    | Op::Plus
-                    | Op::Minus
-                    | Op::Divide
-                    | Op::Power
-                    | Op::Ceiling
-                    | Op::Floor
-                    | Op::Factorial => {
-                        let evaluation_result = evaluate_node(nodes, head_idx, values, functions)?;
-                        // return true if evaluation_result != 0
-                        result = Some((evaluation_result - 0.0).abs() > f64::EPSILON);
-                    }
-                    _ => {}
-                }
-            }
-            if let Some(value) = result {
-                Ok(value)
+    #[test]
+    fn log_and_root_evaluation() {
+        let mut values = std::collections::HashMap::new();
+        values.insert("x".to_string(), 8.0);
+        let functions = std::collections::HashMap::new();
+        let evaluate = |formula: &str| {
+            let tree = parse_formula(formula).unwrap();
+            eval_f64(tree.nodes(), tree.root(), &values, &functions)
+        };
+
+        approx::assert_abs_diff_eq!(evaluate("log(1000)").unwrap(), 3.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(evaluate("log(2, x)").unwrap(), 3.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(evaluate("sqrt(x*2)").unwrap(), 4.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(evaluate("root(3, x)").unwrap(), 2.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(evaluate("root(3, -x)").unwrap(), -2.0, epsilon = 1e-12);
+
+        let domain_error = |formula: &str| match evaluate(formula) {
+            Err(EvalError::DomainError { reason, .. }) => reason,
+            other => panic!("expected a domain error, got {:?}", other),
+        };
+        assert_eq!(
+            domain_error("log(1, x)"),
+            "logarithm base must be positive and not 1"
+        );
+        assert_eq!(
+            domain_error("log(-2, x)"),
+            "logarithm base must be positive and not 1"
+        );
+        assert_eq!(domain_error("log(0)"), "logarithm of a non-positive number");
+        assert_eq!(
+            domain_error("sqrt(-x)"),
+            "even or fractional root of a negative number"
+        );
+        assert_eq!(domain_error("root(0, x)"), "root of degree zero");
+    }
+
+    #[test]
+    fn sbml_csymbols() {
+        let math = "<math><apply><plus/>
+                <apply><times/>
+                    <csymbol encoding=\"text\" definitionURL=\"http://www.sbml.org/sbml/symbols/avogadro\">avogadro</csymbol>
+                    <csymbol encoding=\"text\" definitionURL=\"http://www.sbml.org/sbml/symbols/time\">t</csymbol>
+                </apply>
+                <apply>
+                    <csymbol encoding=\"text\" definitionURL=\"http://www.sbml.org/sbml/symbols/delay\">delay</csymbol>
+                    <ci>x</ci><cn>2</cn>
+                </apply>
+                <apply>
+                    <csymbol encoding=\"text\" definitionURL=\"http://www.sbml.org/sbml/symbols/rateOf\">rateOf</csymbol>
+                    <ci>x</ci>
+                </apply>
+            </apply></math>";
+        let tree = parse_str(math).unwrap();
+        let time = tree.operands_of(tree.operands_of(tree.expr().unwrap())[0])[1];
+        assert_eq!(tree.sbml_symbol(time), Some(SbmlSymbol::Time));
+        let csymbol = tree.as_csymbol(time).unwrap();
+        assert_eq!(csymbol.encoding.as_deref(), Some("text"));
+        assert_eq!(csymbol.text.as_deref(), Some("t"));
+
+        // the infix syntax names the symbols, and reads them back
+        let formula = "avogadro*time + delay(x, 2.0) + rateOf(x)";
+        assert_eq!(to_infix(&tree), formula);
+        assert_eq!(to_infix(&parse_formula(formula).unwrap()), formula);
+        assert_eq!(
+            parse_formula("time").unwrap().nodes()[1].node_type(),
+            MathNodeType::Csymbol
+        );
+        assert_eq!(
+            Expr::try_from(&parse_str(&to_mathml(&tree)).unwrap()).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+
+        let values = std::collections::HashMap::new();
+        let functions = std::collections::HashMap::new();
+        let rate_of = |name: &str| if name == "x" { Some(0.5) } else { None };
+        let delay = |name: &str, delay: f64| {
+            if name == "x" {
+                Some(10.0 - delay)
             } else {
-                panic!("Condition not supported");
+                None
             }
+        };
+        let context = SbmlContext::at_time(1e-23)
+            .with_rate_of(&rate_of)
+            .with_delay(&delay);
+        let result =
+            evaluate_node_with(tree.nodes(), tree.root(), &values, &functions, &context).unwrap();
+        approx::assert_abs_diff_eq!(
+            result.as_f64().unwrap(),
+            AVOGADRO * 1e-23 + 8.0 + 0.5,
+            epsilon = 1e-9
+        );
+
+        // without a simulation state only avogadro has a value
+        assert!(matches!(
+            evaluate_node(tree.nodes(), tree.root(), &values, &functions),
+            Err(EvalError::UnavailableSymbol { ref name, .. }) if name == "time"
+        ));
+        let missing_rate = parse_formula("rateOf(y)").unwrap();
+        assert!(matches!(
+            evaluate_node_with(
+                missing_rate.nodes(),
+                missing_rate.root(),
+                &values,
+                &functions,
+                &context
+            ),
+            Err(EvalError::UnavailableSymbol { ref name, .. }) if name == "rateOf(y)"
+        ));
+    }
+
+    #[test]
+    fn constants() {
+        let values = std::collections::HashMap::new();
+        let functions = std::collections::HashMap::new();
+        let evaluate = |math: &str| {
+            let tree = parse_str(math).unwrap();
+            eval_f64(tree.nodes(), tree.root(), &values, &functions)
+        };
+
+        assert_eq!(evaluate("<math><infinity/></math>"), Ok(f64::INFINITY));
+        assert!(evaluate("<math><notanumber/></math>").unwrap().is_nan());
+        approx::assert_abs_diff_eq!(
+            evaluate("<math><apply><times/><cn>2</cn><eulergamma/></apply></math>").unwrap(),
+            1.1544313298030657
+        );
+        assert_eq!(
+            evaluate(
+                "<math><apply><minus/><apply><divide/><cn>1</cn><infinity/></apply></apply></math>"
+            ),
+            Ok(-0.0)
+        );
+        assert_eq!(
+            evaluate("<math><piecewise><piece><cn>1</cn><false/></piece><otherwise><pi/></otherwise></piecewise></math>"),
+            Ok(std::f64::consts::PI)
+        );
+        assert!(matches!(
+            evaluate("<math><imaginaryi/></math>"),
+            Err(EvalError::TypeMismatch { .. })
+        ));
+
+        let tree = parse_formula("max(-INF, NaN, eulergamma, imaginaryi)").unwrap();
+        assert_eq!(
+            to_infix(&tree),
+            "max(-infinity, notanumber, eulergamma, imaginaryi)"
+        );
+        assert_eq!(
+            Expr::try_from(&parse_str(&to_mathml(&tree)).unwrap()).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+    }
+
+    #[test]
+    fn typed_values() {
+        let values = std::collections::HashMap::from([("x".to_string(), 0.5)]);
+        let functions = std::collections::HashMap::new();
+        let evaluate = |formula: &str| {
+            let tree = parse_formula(formula).unwrap();
+            evaluate_node(tree.nodes(), tree.root(), &values, &functions)
+        };
+
+        assert_eq!(evaluate("x > 0 && x < 1"), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("2^10 - 24"), Ok(Value::Integer(1000)));
+        assert_eq!(evaluate("1/2 + 1/3"), Ok(Value::Rational(5, 6)));
+        assert_eq!(evaluate("4/2"), Ok(Value::Integer(2)));
+        assert_eq!(evaluate("1/3 + x"), Ok(Value::Real(1.0 / 3.0 + 0.5)));
+        assert_eq!(
+            evaluate("9223372036854775807 + 1"),
+            Ok(Value::Real(9.223372036854776e18))
+        );
+        assert_eq!(
+            evaluate("quotient(-9223372036854775807 - 1, -1)"),
+            Ok(Value::Real(9.223372036854776e18))
+        );
+        assert_eq!(evaluate("rem(-7, 2)"), Ok(Value::Integer(-1)));
+
+        let evaluate = |math: &str| {
+            let tree = parse_str(math).unwrap();
+            evaluate_node(tree.nodes(), tree.root(), &values, &functions)
+        };
+        assert_eq!(
+            evaluate("<math><apply><times/><cn type=\"complex-cartesian\">1<sep/>2</cn><imaginaryi/></apply></math>"),
+            Ok(Value::Complex(-2.0, 1.0))
+        );
+        assert_eq!(
+            evaluate("<math><apply><plus/><true/><cn type=\"integer\">3</cn></apply></math>"),
+            Err(EvalError::TypeMismatch {
+                expected: "number",
+                node: 3
+            })
+        );
+        assert_eq!(
+            evaluate("<math><apply><and/><true/><cn>1</cn></apply></math>"),
+            Err(EvalError::TypeMismatch {
+                expected: "boolean",
+                node: 4
+            })
+        );
+        assert_eq!(
+            evaluate("<math><apply><eq/><true/><cn>1</cn></apply></math>"),
+            Err(EvalError::TypeMismatch {
+                expected: "boolean",
+                node: 4
+            })
+        );
+        assert_eq!(
+            evaluate("<math><apply><neq/><false/><apply><lt/><cn>1</cn><cn>2</cn></apply></apply></math>"),
+            Ok(Value::Boolean(true))
+        );
+
+        // the f64 wrapper rejects what is not a real number
+        let tree = parse_formula("x >= 0").unwrap();
+        assert_eq!(
+            eval_f64(tree.nodes(), tree.root(), &values, &functions),
+            Err(EvalError::TypeMismatch {
+                expected: "real number",
+                node: tree.root()
+            })
+        );
+        let tree = parse_formula("3 * x").unwrap();
+        assert_eq!(
+            eval_f64(tree.nodes(), tree.root(), &values, &functions),
+            Ok(1.5)
+        );
+    }
+
+    #[test]
+    fn environments() {
+        // a simulator's state vector, read without copying it into a map
+        struct State {
+            names: Vec<&'static str>,
+            values: Vec<f64>,
+            time: f64,
+            functions: std::collections::HashMap<String, Vec<MathNode>>,
         }
-        _ => {
-            let result = evaluate_node(nodes, head_idx, values, functions)?;
-            Ok(result != 0.0)
+        impl Environment for State {
+            fn variable(&self, name: &str) -> Option<Value> {
+                let index = self.names.iter().position(|&n| n == name)?;
+                Some(Value::Real(self.values[index]))
+            }
+            fn function(&self, name: &str) -> Option<&[MathNode]> {
+                self.functions.get(name).map(Vec::as_slice)
+            }
+            fn time(&self) -> Option<f64> {
+                Some(self.time)
+            }
         }
+
+        let pick = parse_formula("lambda(c, a, b, piecewise(a, c, b))").unwrap();
+        let mut state = State {
+            names: vec!["x", "y"],
+            values: vec![1.0, 2.0],
+            time: 0.0,
+            functions: std::collections::HashMap::from([("pick".to_string(), pick.into_nodes())]),
+        };
+        let tree = parse_formula("pick(time > 1, x, y) + x * y").unwrap();
+        assert_eq!(
+            evaluate(tree.nodes(), tree.root(), &state),
+            Ok(Value::Real(4.0))
+        );
+        state.time = 2.0;
+        state.values[0] = 3.0;
+        assert_eq!(
+            evaluate(tree.nodes(), tree.root(), &state),
+            Ok(Value::Real(9.0))
+        );
+
+        // function bodies only see their arguments
+        let tree = parse_formula("pick(true, z, y)").unwrap();
+        assert!(matches!(
+            evaluate(tree.nodes(), tree.root(), &state),
+            Err(EvalError::UnboundVariable { ref name, .. }) if name == "z"
+        ));
+
+        let values = std::collections::HashMap::from([("x".to_string(), 2.0)]);
+        let functions = std::collections::HashMap::new();
+        let tree = parse_formula("x * time").unwrap();
+        let env = MapEnvironment::new(&values, &functions).with_context(SbmlContext::at_time(3.0));
+        assert_eq!(
+            evaluate(tree.nodes(), tree.root(), &env),
+            Ok(Value::Real(6.0))
+        );
     }
 }
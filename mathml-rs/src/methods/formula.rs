@@ -0,0 +1,758 @@
+use crate::structs::attributes::Attributes;
+use crate::structs::constants::Constant;
+use crate::structs::csymbol::SbmlSymbol;
+use crate::structs::error::{FormulaError, Span};
+use crate::structs::expr::Expr;
+use crate::structs::math_tree::MathTree;
+use crate::structs::numbers::{NumType, Number};
+use crate::structs::op::Op;
+use crate::structs::qualifier::Qualifier;
+use std::str::FromStr;
+
+// Binding strength of the operators of the formula syntax, from loosest to
+// tightest, as in the SBML Level 3 formula grammar.
+pub(crate) const LOGICAL: u8 = 2;
+pub(crate) const RELATIONAL: u8 = 3;
+pub(crate) const ADDITIVE: u8 = 4;
+pub(crate) const MULTIPLICATIVE: u8 = 5;
+pub(crate) const UNARY: u8 = 6;
+pub(crate) const POWER: u8 = 7;
+
+/// Parses a formula in SBML Level 3 infix syntax, e.g. `k1 * A / (Km + A)`.
+///
+/// The resulting tree has exactly the node layout that parsing the
+/// equivalent Content MathML would give. Chains of `+`, `*`, `&&`, `||` and
+/// of the same relational operator become a single n-ary apply, and a minus
+/// sign directly in front of a number is folded into the literal.
+/// `log(b, x)` and `root(n, x)` become a log with a base and a root with a
+/// degree; `log(x)` is the base-10 logarithm. `time`, `avogadro`,
+/// `delay(x, d)` and `rateOf(x)` refer to the SBML csymbols.
+pub fn parse_formula(formula: &str) -> Result<MathTree, FormulaError> {
+    parse_formula_expr(formula).map(MathTree::from)
+}
+
+/// Parses a formula in SBML Level 3 infix syntax into an [`Expr`].
+pub fn parse_formula_expr(formula: &str) -> Result<Expr, FormulaError> {
+    let mut parser = Parser {
+        tokens: tokenize(formula)?,
+        next: 0,
+    };
+    let expr = parser.logical()?;
+    parser.expect(Token::End, "an operator or the end of the formula")?;
+    Ok(expr)
+}
+
+impl FromStr for Expr {
+    type Err = FormulaError;
+
+    fn from_str(formula: &str) -> Result<Self, FormulaError> {
+        parse_formula_expr(formula)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Bang,
+    AndAnd,
+    OrOr,
+    EqEq,
+    NotEq,
+    Lt,
+    Gt,
+    Leq,
+    Geq,
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+impl Token {
+    fn text(&self) -> String {
+        let text = match self {
+            Token::Number(text) | Token::Name(text) => return text.clone(),
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Caret => "^",
+            Token::Bang => "!",
+            Token::AndAnd => "&&",
+            Token::OrOr => "||",
+            Token::EqEq => "==",
+            Token::NotEq => "!=",
+            Token::Lt => "<",
+            Token::Gt => ">",
+            Token::Leq => "<=",
+            Token::Geq => ">=",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::Comma => ",",
+            Token::End => "",
+        };
+        text.to_string()
+    }
+
+    // The operator and precedence of a binary operator token.
+    fn binary(&self) -> Option<(Op, u8)> {
+        let binary = match self {
+            Token::AndAnd => (Op::And, LOGICAL),
+            Token::OrOr => (Op::Or, LOGICAL),
+            Token::EqEq => (Op::Eq, RELATIONAL),
+            Token::NotEq => (Op::Neq, RELATIONAL),
+            Token::Lt => (Op::Lt, RELATIONAL),
+            Token::Gt => (Op::Gt, RELATIONAL),
+            Token::Leq => (Op::Leq, RELATIONAL),
+            Token::Geq => (Op::Geq, RELATIONAL),
+            Token::Plus => (Op::Plus, ADDITIVE),
+            Token::Minus => (Op::Minus, ADDITIVE),
+            Token::Star => (Op::Times, MULTIPLICATIVE),
+            Token::Slash => (Op::Divide, MULTIPLICATIVE),
+            Token::Percent => (Op::Rem, MULTIPLICATIVE),
+            Token::Caret => (Op::Power, POWER),
+            _ => return None,
+        };
+        Some(binary)
+    }
+}
+
+fn tokenize(formula: &str) -> Result<Vec<(Token, Span)>, FormulaError> {
+    let bytes = formula.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let token = if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        } else if c.is_ascii_digit()
+            || (c == b'.' && matches!(bytes.get(i + 1), Some(c) if c.is_ascii_digit()))
+        {
+            i = scan_number(bytes, i);
+            Token::Number(formula[start..i].to_string())
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            Token::Name(formula[start..i].to_string())
+        } else {
+            let two = bytes.get(i..i + 2);
+            let (token, len) = match (c, two) {
+                (_, Some(b"&&")) => (Token::AndAnd, 2),
+                (_, Some(b"||")) => (Token::OrOr, 2),
+                (_, Some(b"==")) => (Token::EqEq, 2),
+                (_, Some(b"!=")) => (Token::NotEq, 2),
+                (_, Some(b"<=")) => (Token::Leq, 2),
+                (_, Some(b">=")) => (Token::Geq, 2),
+                (b'+', _) => (Token::Plus, 1),
+                (b'-', _) => (Token::Minus, 1),
+                (b'*', _) => (Token::Star, 1),
+                (b'/', _) => (Token::Slash, 1),
+                (b'%', _) => (Token::Percent, 1),
+                (b'^', _) => (Token::Caret, 1),
+                (b'!', _) => (Token::Bang, 1),
+                (b'<', _) => (Token::Lt, 1),
+                (b'>', _) => (Token::Gt, 1),
+                (b'(', _) => (Token::LParen, 1),
+                (b')', _) => (Token::RParen, 1),
+                (b',', _) => (Token::Comma, 1),
+                _ => {
+                    let character = formula[i..].chars().next().unwrap_or_default();
+                    return Err(FormulaError::UnexpectedCharacter {
+                        character,
+                        span: Span::new(i, i + character.len_utf8()),
+                    });
+                }
+            };
+            i += len;
+            token
+        };
+        tokens.push((token, Span::new(start, i)));
+    }
+    tokens.push((Token::End, Span::new(bytes.len(), bytes.len())));
+    Ok(tokens)
+}
+
+// Returns the end of the number starting at `i`: digits, an optional
+// fraction and an optional exponent.
+fn scan_number(bytes: &[u8], mut i: usize) -> usize {
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+    i = digits(i);
+    if bytes.get(i) == Some(&b'.') {
+        i = digits(i + 1);
+    }
+    if matches!(bytes.get(i), Some(b'e') | Some(b'E')) {
+        let mut exponent = i + 1;
+        if matches!(bytes.get(exponent), Some(b'+') | Some(b'-')) {
+            exponent += 1;
+        }
+        // an `e` without digits belongs to whatever follows
+        if matches!(bytes.get(exponent), Some(c) if c.is_ascii_digit()) {
+            i = digits(exponent);
+        }
+    }
+    i
+}
+
+fn number(text: &str, span: Span) -> Result<Expr, FormulaError> {
+    let invalid = || FormulaError::InvalidNumber {
+        text: text.to_string(),
+        span,
+    };
+    if let Some(e) = text.find(|c| c == 'e' || c == 'E') {
+        let mantissa = text[..e].parse::<f64>().map_err(|_| invalid())?;
+        let exponent = text[e + 1..].parse::<i64>().map_err(|_| invalid())?;
+        Ok(Expr::number(
+            Some(NumType::ENotation),
+            Number::ENotation(mantissa, exponent),
+        ))
+    } else if text.contains('.') {
+        text.parse::<f64>().map(Expr::real).map_err(|_| invalid())
+    } else {
+        // integers too large for the integer type are kept as reals
+        match text.parse::<i64>() {
+            Ok(value) => Ok(Expr::integer(value)),
+            Err(_) => text.parse::<f64>().map(Expr::real).map_err(|_| invalid()),
+        }
+    }
+}
+
+// Folds a sign into a numeric literal, or returns `None` if `expr` is not one.
+fn negate_literal(expr: &Expr) -> Option<Expr> {
+    if let Expr::Cn { r#type, value, .. } = expr {
+        let value = match value {
+            Number::Integer(i) => Number::Integer(i.checked_neg()?),
+            Number::Real(r) => Number::Real(-r),
+            Number::ENotation(m, e) => Number::ENotation(-m, *e),
+            _ => return None,
+        };
+        return Some(Expr::number(r#type.clone(), value));
+    }
+    None
+}
+
+fn constant(name: &str) -> Option<Constant> {
+    let constant = match name {
+        "pi" => Constant::Pi,
+        "exponentiale" => Constant::ExponentialE,
+        "true" => Constant::True,
+        "false" => Constant::False,
+        "infinity" | "INF" => Constant::Infinity,
+        "notanumber" | "NaN" => Constant::NotANumber,
+        "eulergamma" => Constant::EulerGamma,
+        "imaginaryi" => Constant::ImaginaryI,
+        _ => return None,
+    };
+    Some(constant)
+}
+
+// The SBML csymbols that are written as plain names.
+fn symbol(name: &str) -> Option<SbmlSymbol> {
+    match name {
+        "time" => Some(SbmlSymbol::Time),
+        "avogadro" => Some(SbmlSymbol::Avogadro),
+        _ => None,
+    }
+}
+
+// The operator of a built-in function and the number of arguments it takes.
+fn builtin(name: &str) -> Option<(Op, usize, Option<usize>)> {
+    let unary = |op| Some((op, 1, Some(1)));
+    let binary = |op| Some((op, 2, Some(2)));
+    let nary = |op| Some((op, 0, None));
+    match name {
+        "abs" => unary(Op::Abs),
+        "ceil" | "ceiling" => unary(Op::Ceiling),
+        "floor" => unary(Op::Floor),
+        "exp" => unary(Op::Exp),
+        "ln" => unary(Op::Ln),
+        "log" => Some((Op::Log, 1, Some(2))),
+        "log10" => unary(Op::Log),
+        "sqrt" => unary(Op::Root),
+        "root" => binary(Op::Root),
+        "factorial" => unary(Op::Factorial),
+        "not" => unary(Op::Not),
+        "sin" => unary(Op::Sin),
+        "cos" => unary(Op::Cos),
+        "tan" => unary(Op::Tan),
+        "sec" => unary(Op::Sec),
+        "csc" => unary(Op::Csc),
+        "cot" => unary(Op::Cot),
+        "sinh" => unary(Op::Sinh),
+        "cosh" => unary(Op::Cosh),
+        "tanh" => unary(Op::Tanh),
+        "sech" => unary(Op::Sech),
+        "csch" => unary(Op::Csch),
+        "coth" => unary(Op::Coth),
+        "arcsin" | "asin" => unary(Op::Arcsin),
+        "arccos" | "acos" => unary(Op::Arccos),
+        "arctan" | "atan" => unary(Op::Arctan),
+        "arcsec" | "asec" => unary(Op::Arcsec),
+        "arccsc" | "acsc" => unary(Op::Arccsc),
+        "arccot" | "acot" => unary(Op::Arccot),
+        "arcsinh" | "asinh" => unary(Op::Arcsinh),
+        "arccosh" | "acosh" => unary(Op::Arccosh),
+        "arctanh" | "atanh" => unary(Op::Arctanh),
+        "arcsech" | "asech" => unary(Op::Arcsech),
+        "arccsch" | "acsch" => unary(Op::Arccsch),
+        "arccoth" | "acoth" => unary(Op::Arccoth),
+        "pow" | "power" => binary(Op::Power),
+        "divide" => binary(Op::Divide),
+        "quotient" => binary(Op::Quotient),
+        "rem" => binary(Op::Rem),
+        "implies" => binary(Op::Implies),
+        "neq" => binary(Op::Neq),
+        "minus" => Some((Op::Minus, 1, Some(2))),
+        "plus" => nary(Op::Plus),
+        "times" => nary(Op::Times),
+        "and" => nary(Op::And),
+        "or" => nary(Op::Or),
+        "xor" => nary(Op::Xor),
+        "max" => Some((Op::Max, 1, None)),
+        "min" => Some((Op::Min, 1, None)),
+        "eq" => Some((Op::Eq, 2, None)),
+        "gt" => Some((Op::Gt, 2, None)),
+        "lt" => Some((Op::Lt, 2, None)),
+        "geq" => Some((Op::Geq, 2, None)),
+        "leq" => Some((Op::Leq, 2, None)),
+        _ => None,
+    }
+}
+
+fn describe_count(min: usize, max: Option<usize>) -> &'static str {
+    match (min, max) {
+        (1, Some(1)) => "1",
+        (2, Some(2)) => "2",
+        (1, Some(2)) => "1 or 2",
+        (1, None) => "at least 1",
+        (2, None) => "at least 2",
+        _ => "any number of",
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn advance(&mut self) -> (Token, Span) {
+        let token = self.tokens[self.next].clone();
+        if token.0 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &'static str) -> FormulaError {
+        let (token, span) = &self.tokens[self.next];
+        match token {
+            Token::End => FormulaError::UnexpectedEnd {
+                expected,
+                span: *span,
+            },
+            _ => FormulaError::UnexpectedToken {
+                found: token.text(),
+                expected,
+                span: *span,
+            },
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<Span, FormulaError> {
+        if *self.peek() == token {
+            Ok(self.advance().1)
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    // `&&` and `||` share a level and associate to the left.
+    fn logical(&mut self) -> Result<Expr, FormulaError> {
+        let mut operands = vec![self.relational()?];
+        let mut ops = Vec::new();
+        while let Some((op, LOGICAL)) = self.peek().binary() {
+            self.advance();
+            ops.push(op);
+            operands.push(self.relational()?);
+        }
+        Ok(fold_left(operands, ops, |op| {
+            *op == Op::And || *op == Op::Or
+        }))
+    }
+
+    // A chain of one relational operator becomes a single n-ary apply, a
+    // mixed chain like `a < b >= c` a conjunction of the pairwise comparisons.
+    fn relational(&mut self) -> Result<Expr, FormulaError> {
+        let mut operands = vec![self.additive()?];
+        let mut ops = Vec::new();
+        while let Some((op, RELATIONAL)) = self.peek().binary() {
+            self.advance();
+            ops.push(op);
+            operands.push(self.additive()?);
+        }
+        match ops.first() {
+            None => Ok(operands.remove(0)),
+            Some(first) if ops.iter().all(|op| op == first) && *first != Op::Neq => {
+                Ok(Expr::apply(first.clone(), operands))
+            }
+            Some(_) if ops.len() == 1 => Ok(Expr::apply(ops.remove(0), operands)),
+            Some(_) => Ok(Expr::apply(
+                Op::And,
+                ops.into_iter()
+                    .zip(operands.windows(2))
+                    .map(|(op, pair)| Expr::apply(op, pair.to_vec()))
+                    .collect(),
+            )),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, FormulaError> {
+        let mut operands = vec![self.multiplicative()?];
+        let mut ops = Vec::new();
+        while let Some((op, ADDITIVE)) = self.peek().binary() {
+            self.advance();
+            ops.push(op);
+            operands.push(self.multiplicative()?);
+        }
+        Ok(fold_left(operands, ops, |op| *op == Op::Plus))
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, FormulaError> {
+        let mut operands = vec![self.unary()?];
+        let mut ops = Vec::new();
+        while let Some((op, MULTIPLICATIVE)) = self.peek().binary() {
+            self.advance();
+            ops.push(op);
+            operands.push(self.unary()?);
+        }
+        Ok(fold_left(operands, ops, |op| *op == Op::Times))
+    }
+
+    // Unary operators bind looser than `^`, so `-a^2` is `-(a^2)`.
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        self.signed(Parser::unary, Parser::power)
+    }
+
+    // `^` associates to the left; its right operand may carry a sign, as in
+    // `x^-2`.
+    fn power(&mut self) -> Result<Expr, FormulaError> {
+        let mut base = self.primary()?;
+        while *self.peek() == Token::Caret {
+            self.advance();
+            let exponent = self.signed(Parser::signed_primary, Parser::primary)?;
+            base = Expr::apply(Op::Power, vec![base, exponent]);
+        }
+        Ok(base)
+    }
+
+    fn signed_primary(&mut self) -> Result<Expr, FormulaError> {
+        self.signed(Parser::signed_primary, Parser::primary)
+    }
+
+    // Applies a leading `-` or `!` to the operand parsed by `signed`, or
+    // parses an unsigned operand with `unsigned`.
+    fn signed(
+        &mut self,
+        signed: fn(&mut Parser) -> Result<Expr, FormulaError>,
+        unsigned: fn(&mut Parser) -> Result<Expr, FormulaError>,
+    ) -> Result<Expr, FormulaError> {
+        match self.peek() {
+            Token::Minus => {
+                self.advance();
+                let operand = signed(self)?;
+                Ok(negate_literal(&operand)
+                    .unwrap_or_else(|| Expr::apply(Op::Minus, vec![operand])))
+            }
+            Token::Bang => {
+                self.advance();
+                Ok(Expr::apply(Op::Not, vec![signed(self)?]))
+            }
+            _ => unsigned(self),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        match self.peek().clone() {
+            Token::Number(text) => {
+                let (_, span) = self.advance();
+                number(&text, span)
+            }
+            Token::LParen => {
+                self.advance();
+                let expr = self.logical()?;
+                self.expect(Token::RParen, "\")\"")?;
+                Ok(expr)
+            }
+            Token::Name(name) => {
+                let (_, span) = self.advance();
+                if *self.peek() == Token::LParen {
+                    self.call(name, span)
+                } else {
+                    Ok(match (constant(&name), symbol(&name)) {
+                        (Some(constant), _) => Expr::constant(constant),
+                        (None, Some(symbol)) => Expr::sbml(symbol),
+                        (None, None) => Expr::ci(&name),
+                    })
+                }
+            }
+            _ => Err(self.unexpected("a number, name or \"(\"")),
+        }
+    }
+
+    fn call(&mut self, name: String, start: Span) -> Result<Expr, FormulaError> {
+        self.expect(Token::LParen, "\"(\"")?;
+        let mut args = Vec::new();
+        if *self.peek() != Token::RParen {
+            loop {
+                args.push(self.logical()?);
+                if *self.peek() != Token::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        let end = self.expect(Token::RParen, "\",\" or \")\"")?;
+        let span = start.to(end);
+
+        let got = args.len();
+        let argument_count = |expected| FormulaError::ArgumentCount {
+            name: name.clone(),
+            expected,
+            got,
+            span,
+        };
+        match name.as_str() {
+            "piecewise" => {
+                if args.is_empty() {
+                    return Err(argument_count("at least 1"));
+                }
+                let otherwise = if args.len() % 2 == 1 {
+                    args.pop().map(Box::new)
+                } else {
+                    None
+                };
+                let mut args = args.into_iter();
+                let mut pieces = Vec::new();
+                while let (Some(value), Some(condition)) = (args.next(), args.next()) {
+                    pieces.push((value, condition));
+                }
+                Ok(Expr::Piecewise {
+                    pieces,
+                    otherwise,
+                    attributes: Attributes::default(),
+                })
+            }
+            "lambda" => {
+                let body = match args.pop() {
+                    Some(body) => Box::new(body),
+                    None => return Err(argument_count("at least 1")),
+                };
+                let params = args
+                    .into_iter()
+                    .map(|param| match param {
+                        Expr::Ci { name, .. } => Ok(name),
+                        _ => Err(FormulaError::UnexpectedToken {
+                            found: "expression".to_string(),
+                            expected: "a parameter name",
+                            span,
+                        }),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Expr::Lambda {
+                    params,
+                    body,
+                    attributes: Attributes::default(),
+                })
+            }
+            "delay" | "rateOf" => {
+                let (symbol, count) = match name.as_str() {
+                    "delay" => (SbmlSymbol::Delay, 2),
+                    _ => (SbmlSymbol::RateOf, 1),
+                };
+                if got != count {
+                    return Err(argument_count(describe_count(count, Some(count))));
+                }
+                Ok(Expr::sbml_call(symbol, args))
+            }
+            _ => match builtin(&name) {
+                Some((op, min, max)) => {
+                    if got < min || matches!(max, Some(max) if got > max) {
+                        return Err(argument_count(describe_count(min, max)));
+                    }
+                    // `log(b, x)` and `root(n, x)` lead with their qualifier
+                    match (op, got) {
+                        (Op::Log, 2) => {
+                            let base = args.remove(0);
+                            Ok(Expr::qualified(Op::Log, Qualifier::LogBase, base, args))
+                        }
+                        (Op::Root, 2) => {
+                            let degree = args.remove(0);
+                            Ok(Expr::qualified(Op::Root, Qualifier::Degree, degree, args))
+                        }
+                        (op, _) => Ok(Expr::apply(op, args)),
+                    }
+                }
+                None => Ok(Expr::call(&name, args)),
+            },
+        }
+    }
+}
+
+// Builds left-associative applies, merging runs of an n-ary operator into a
+// single apply.
+fn fold_left(operands: Vec<Expr>, ops: Vec<Op>, nary: impl Fn(&Op) -> bool) -> Expr {
+    let mut operands = operands.into_iter();
+    let mut acc = operands.next().expect("at least one operand");
+    let mut previous: Option<Op> = None;
+    for (op, operand) in ops.into_iter().zip(operands) {
+        match &mut acc {
+            Expr::Apply { args, .. } if nary(&op) && previous.as_ref() == Some(&op) => {
+                args.push(operand);
+            }
+            _ => acc = Expr::apply(op.clone(), vec![acc, operand]),
+        }
+        previous = Some(op);
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn formula_parsing() {
+        let tree = parse_formula("k1 * A * B / (Km + A)").unwrap();
+        let mathml = parse_str(
+            "<math><apply><divide/>
+                <apply><times/><ci>k1</ci><ci>A</ci><ci>B</ci></apply>
+                <apply><plus/><ci>Km</ci><ci>A</ci></apply>
+            </apply></math>",
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:?}", tree.nodes()),
+            format!("{:?}", mathml.nodes())
+        );
+
+        let parse = |formula: &str| formula.parse::<Expr>().unwrap();
+        assert_eq!(
+            parse("piecewise(1, t > 5, 0)"),
+            Expr::Piecewise {
+                pieces: vec![(
+                    Expr::integer(1),
+                    Expr::apply(Op::Gt, vec![Expr::ci("t"), Expr::integer(5)])
+                )],
+                otherwise: Some(Box::new(Expr::integer(0))),
+                attributes: Attributes::default(),
+            }
+        );
+        assert_eq!(
+            parse("-a^2"),
+            Expr::apply(
+                Op::Minus,
+                vec![Expr::apply(
+                    Op::Power,
+                    vec![Expr::ci("a"), Expr::integer(2)]
+                )]
+            )
+        );
+        assert_eq!(
+            parse("2^3^x"),
+            Expr::apply(
+                Op::Power,
+                vec![
+                    Expr::apply(Op::Power, vec![Expr::integer(2), Expr::integer(3)]),
+                    Expr::ci("x")
+                ]
+            )
+        );
+        assert_eq!(
+            parse("a - b - c"),
+            Expr::apply(
+                Op::Minus,
+                vec![
+                    Expr::apply(Op::Minus, vec![Expr::ci("a"), Expr::ci("b")]),
+                    Expr::ci("c")
+                ]
+            )
+        );
+        assert_eq!(
+            parse("0 < x <= 1 && !flag || true"),
+            Expr::apply(
+                Op::Or,
+                vec![
+                    Expr::apply(
+                        Op::And,
+                        vec![
+                            Expr::apply(
+                                Op::And,
+                                vec![
+                                    Expr::apply(Op::Lt, vec![Expr::integer(0), Expr::ci("x")]),
+                                    Expr::apply(Op::Leq, vec![Expr::ci("x"), Expr::integer(1)]),
+                                ]
+                            ),
+                            Expr::apply(Op::Not, vec![Expr::ci("flag")]),
+                        ]
+                    ),
+                    Expr::constant(Constant::True),
+                ]
+            )
+        );
+        assert_eq!(
+            parse("f(-1.5e-3, pi)"),
+            Expr::call(
+                "f",
+                vec![
+                    Expr::number(Some(NumType::ENotation), Number::ENotation(-1.5, -3)),
+                    Expr::constant(Constant::Pi),
+                ]
+            )
+        );
+
+        let values = std::collections::HashMap::from([("x".to_string(), 2.0)]);
+        let tree = parse_formula("x^-1 + exp(0) * 3 % 2").unwrap();
+        let result = eval_f64(tree.nodes(), tree.root(), &values, &Default::default());
+        assert_eq!(result, Ok(1.5));
+
+        assert_eq!(
+            parse_formula("a + * b").unwrap_err(),
+            FormulaError::UnexpectedToken {
+                found: "*".to_string(),
+                expected: "a number, name or \"(\"",
+                span: Span::new(4, 5),
+            }
+        );
+        assert!(matches!(
+            parse_formula("(a + b"),
+            Err(FormulaError::UnexpectedEnd { span, .. }) if span == Span::new(6, 6)
+        ));
+        assert!(matches!(
+            parse_formula("a $ b"),
+            Err(FormulaError::UnexpectedCharacter { character: '$', .. })
+        ));
+        assert!(matches!(
+            parse_formula("1 + sin(a, b)"),
+            Err(FormulaError::ArgumentCount { got: 2, span, .. }) if span == Span::new(4, 13)
+        ));
+    }
+}
@@ -0,0 +1,361 @@
+use super::formula::{ADDITIVE, LOGICAL, MULTIPLICATIVE, POWER, RELATIONAL, UNARY};
+use crate::structs::apply::Apply;
+use crate::structs::container::Container;
+use crate::structs::expr::Expr;
+use crate::structs::math_node::{MathNode, NodeIndex};
+use crate::structs::math_tree::MathTree;
+use crate::structs::numbers::Number;
+use crate::structs::op::Op;
+use crate::structs::qualifier::Qualifier;
+use std::fmt;
+
+// Names, literals, calls and anything in parentheses.
+const ATOM: u8 = POWER + 1;
+
+/// Renders a tree in the infix syntax read by [`parse_formula`](crate::parse_formula).
+///
+/// Operators are written with as few parentheses as the precedence and
+/// associativity of the formula syntax allow, so `k1*A*B/(Km + A)` comes
+/// out as written. Operators without an infix form, and infix operators
+/// with an unusual number of operands, use function-call syntax.
+pub fn to_infix(tree: &MathTree) -> String {
+    node_to_infix(tree, tree.root())
+}
+
+/// Renders the subtree of `tree` rooted at `idx` in infix syntax.
+pub fn node_to_infix(tree: &MathTree, idx: NodeIndex) -> String {
+    let mut writer = InfixWriter {
+        tree,
+        out: String::new(),
+    };
+    writer.write(idx);
+    writer.out
+}
+
+impl fmt::Display for MathTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&to_infix(self))
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&to_infix(&MathTree::from(self)))
+    }
+}
+
+/// Formats a real so that it reads back as a real rather than an integer
+/// or e-notation literal.
+pub(crate) fn format_real(r: f64) -> String {
+    if r.is_nan() {
+        "NaN".to_string()
+    } else if r.is_infinite() {
+        if r > 0.0 { "INF" } else { "-INF" }.to_string()
+    } else {
+        let text = r.to_string();
+        if text.contains('.') {
+            text
+        } else {
+            text + ".0"
+        }
+    }
+}
+
+// The symbol and precedence of an operator written infix with `n` operands.
+fn infix(op: &Op, n: usize) -> Option<(&'static str, u8)> {
+    let infix = match (op, n) {
+        (Op::And, 2..) => (" && ", LOGICAL),
+        (Op::Or, 2..) => (" || ", LOGICAL),
+        (Op::Eq, 2..) => (" == ", RELATIONAL),
+        (Op::Neq, 2) => (" != ", RELATIONAL),
+        (Op::Lt, 2..) => (" < ", RELATIONAL),
+        (Op::Gt, 2..) => (" > ", RELATIONAL),
+        (Op::Leq, 2..) => (" <= ", RELATIONAL),
+        (Op::Geq, 2..) => (" >= ", RELATIONAL),
+        (Op::Plus, 2..) => (" + ", ADDITIVE),
+        (Op::Minus, 2) => (" - ", ADDITIVE),
+        (Op::Times, 2..) => ("*", MULTIPLICATIVE),
+        (Op::Divide, 2) => ("/", MULTIPLICATIVE),
+        (Op::Rem, 2) => ("%", MULTIPLICATIVE),
+        (Op::Power, 2) => ("^", POWER),
+        (Op::Minus, 1) => ("-", UNARY),
+        (Op::Not, 1) => ("!", UNARY),
+        _ => return None,
+    };
+    Some(infix)
+}
+
+// Operators that the formula parser merges into one apply when chained.
+fn is_chained(op: &Op) -> bool {
+    matches!(op, Op::And | Op::Or | Op::Plus | Op::Times)
+}
+
+struct InfixWriter<'a> {
+    tree: &'a MathTree,
+    out: String,
+}
+
+impl<'a> InfixWriter<'a> {
+    fn precedence(&self, idx: NodeIndex) -> u8 {
+        match self.tree.get(idx) {
+            Some(MathNode::Apply(apply)) if is_plain(apply) => self
+                .tree
+                .op_of(idx)
+                .and_then(|op| infix(op, apply.operands.len()))
+                .map_or(ATOM, |(_, precedence)| precedence),
+            Some(MathNode::Cn(cn)) => match &cn.value {
+                Some(Number::Rational(..)) => MULTIPLICATIVE,
+                Some(value) if is_negative(value) => UNARY,
+                _ => ATOM,
+            },
+            // annotations are not printed, so the expression alone decides
+            Some(MathNode::Semantics(semantics)) => {
+                semantics.expr.map_or(ATOM, |expr| self.precedence(expr))
+            }
+            _ => ATOM,
+        }
+    }
+
+    fn write(&mut self, idx: NodeIndex) {
+        let node = match self.tree.get(idx) {
+            Some(node) => node,
+            None => return,
+        };
+        match node {
+            MathNode::Root(..) => self.write_list(node.children()),
+            MathNode::BVar(..) => self.write_call("bvar", node.children()),
+            MathNode::Qualifier(qualifiernode) => {
+                let name = qualifiernode.qualifier.as_ref().map_or("", Qualifier::name);
+                self.write_call(name, node.children());
+            }
+            MathNode::Apply(apply) => {
+                let operands = &apply.operands;
+                match self.tree.op_of(idx) {
+                    Some(op) if is_plain(apply) => match infix(op, operands.len()) {
+                        Some((symbol, precedence)) => {
+                            self.write_infix(op, symbol, precedence, operands)
+                        }
+                        None => self.write_call(op_name(op), operands),
+                    },
+                    // `log(b, x)` and `root(n, x)` put their qualifier first
+                    Some(op @ Op::Log) | Some(op @ Op::Root) if apply.bvars.is_empty() => {
+                        let qualifier = match op {
+                            Op::Log => Qualifier::LogBase,
+                            _ => Qualifier::Degree,
+                        };
+                        match (
+                            apply.qualifiers.len(),
+                            self.tree.qualifier_value_of(idx, &qualifier),
+                        ) {
+                            (1, Some(value)) => {
+                                let args: Vec<_> = std::iter::once(value)
+                                    .chain(operands.iter().copied())
+                                    .collect();
+                                self.write_call(op.name(), &args);
+                            }
+                            _ => self.write_call(op.name(), &apply.children[1..]),
+                        }
+                    }
+                    Some(op) => self.write_call(op.name(), &apply.children[1..]),
+                    None => {
+                        if let Some(operator) = apply.operator {
+                            self.write_operand(operator, ATOM);
+                        }
+                        self.out.push('(');
+                        self.write_list(operands);
+                        self.out.push(')');
+                    }
+                }
+            }
+            MathNode::Op(opnode) => {
+                if let Some(op) = &opnode.op {
+                    self.out.push_str(op_name(op));
+                }
+            }
+            MathNode::Constant(constantnode) => {
+                if let Some(constant) = &constantnode.constant {
+                    self.out.push_str(constant.name());
+                }
+            }
+            MathNode::Ci(ci) => {
+                self.out.push_str(ci.name.as_deref().unwrap_or_default());
+            }
+            MathNode::Cn(cn) => {
+                if let Some(value) = &cn.value {
+                    self.write_number(value);
+                }
+            }
+            MathNode::Csymbol(csymbol) => match csymbol.sbml_symbol() {
+                Some(symbol) => self.out.push_str(symbol.name()),
+                None => self
+                    .out
+                    .push_str(csymbol.text.as_deref().unwrap_or_default()),
+            },
+            MathNode::Lambda(..) => {
+                self.out.push_str("lambda(");
+                for param in self.tree.parameters_of(idx) {
+                    self.out.push_str(param);
+                    self.out.push_str(", ");
+                }
+                if let Some(body) = self.tree.body_of(idx) {
+                    self.write(body);
+                }
+                self.out.push(')');
+            }
+            MathNode::Piecewise(..) => {
+                let mut args = Vec::new();
+                for &piece in self.tree.pieces_of(idx) {
+                    if let Some((value, condition)) = self.tree.piece_parts(piece) {
+                        args.push(value);
+                        args.push(condition);
+                    }
+                }
+                args.extend(self.tree.otherwise_of(idx));
+                self.write_call("piecewise", &args);
+            }
+            MathNode::Piece(..) => self.write_call("piece", node.children()),
+            MathNode::Otherwise(..) => self.write_call("otherwise", node.children()),
+            MathNode::Semantics(semantics) => {
+                if let Some(expr) = semantics.expr {
+                    self.write(expr);
+                }
+            }
+            MathNode::Container(containernode) => {
+                let name = containernode.container.as_ref().map_or("", Container::name);
+                self.write_call(name, node.children());
+            }
+        }
+    }
+
+    fn write_infix(&mut self, op: &Op, symbol: &str, precedence: u8, operands: &[NodeIndex]) {
+        if precedence == UNARY {
+            self.out.push_str(symbol);
+            self.write_operand(operands[0], UNARY);
+            return;
+        }
+        for (i, &operand) in operands.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(symbol);
+            }
+            let required = if *op == Op::Power && i > 0 {
+                // a signed number may follow `^` directly, as in `x^-2`
+                match self.tree.cn_value(operand) {
+                    Some(value) if is_negative(value) => UNARY,
+                    _ => ATOM,
+                }
+            } else if i > 0 || precedence == RELATIONAL {
+                // relational operators do not associate, the rest do to the left
+                precedence + 1
+            } else if is_chained(op) && self.tree.op_of(operand) == Some(op) {
+                // `(a + b) + c` would read back as a single sum
+                ATOM
+            } else {
+                precedence
+            };
+            self.write_operand(operand, required);
+        }
+    }
+
+    fn write_operand(&mut self, idx: NodeIndex, required: u8) {
+        if self.precedence(idx) < required {
+            self.out.push('(');
+            self.write(idx);
+            self.out.push(')');
+        } else {
+            self.write(idx);
+        }
+    }
+
+    fn write_call(&mut self, name: &str, args: &[NodeIndex]) {
+        self.out.push_str(name);
+        self.out.push('(');
+        self.write_list(args);
+        self.out.push(')');
+    }
+
+    fn write_list(&mut self, items: &[NodeIndex]) {
+        for (i, &item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.write(item);
+        }
+    }
+
+    fn write_number(&mut self, value: &Number) {
+        let text = match value {
+            Number::Real(r) => format_real(*r),
+            Number::Integer(i) => i.to_string(),
+            Number::Rational(a, b) => format!("{}/{}", a, b),
+            Number::ENotation(m, e) => format!("{}e{}", m, e),
+            Number::ComplexCartesian(a, b) => format!("complex_cartesian({}, {})", a, b),
+            Number::ComplexPolar(a, b) => format!("complex_polar({}, {})", a, b),
+            Number::Constant(s) => s.clone(),
+        };
+        self.out.push_str(&text);
+    }
+}
+
+// The function name the formula parser reads back as `op`.
+fn op_name(op: &Op) -> &'static str {
+    match op {
+        Op::Root => "sqrt",
+        _ => op.name(),
+    }
+}
+
+// Whether an apply has neither bound variables nor qualifiers.
+fn is_plain(apply: &Apply) -> bool {
+    apply.bvars.is_empty() && apply.qualifiers.is_empty()
+}
+
+fn is_negative(value: &Number) -> bool {
+    match value {
+        Number::Real(r) => r.is_sign_negative() && !r.is_nan(),
+        Number::Integer(i) => *i < 0,
+        Number::ENotation(m, _) => *m < 0.0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::convert::TryFrom;
+    #[test]
+    fn infix_output() {
+        for formula in [
+            "k1*A*B/(Km + A)",
+            "a - (b - c) + (d + e)",
+            "(a + b)*c^2^x",
+            "-a^2 + (-2)^x*x^-1",
+            "x > 0 && (y < 1 || !z)",
+            "a < b < c && (a == b) != c",
+            "piecewise(1, t > 5.0, 0)",
+            "f(1.5e-3, pi, sin(x), max(a, b, c))",
+            "lambda(x, y, x*y%2)",
+        ] {
+            let tree = parse_formula(formula).unwrap();
+            assert_eq!(to_infix(&tree), formula);
+            assert_eq!(tree.to_string(), formula);
+        }
+
+        // trees that did not come from a formula still read back the same
+        let tree = parse_str(
+            "<math><apply><minus/>
+                <apply><plus/><apply><plus/><ci>a</ci><ci>b</ci></apply><cn>1</cn></apply>
+                <apply><divide/><ci>c</ci><apply><times/><cn type=\"integer\">2</cn><ci>d</ci></apply></apply>
+            </apply></math>",
+        )
+        .unwrap();
+        let infix = to_infix(&tree);
+        assert_eq!(infix, "(a + b) + 1.0 - c/(2*d)");
+        assert_eq!(
+            Expr::try_from(&parse_formula(&infix).unwrap()).unwrap(),
+            Expr::try_from(&tree).unwrap()
+        );
+
+        let expr = Expr::apply(Op::Plus, vec![Expr::ci("x")]);
+        assert_eq!(expr.to_string(), "plus(x)");
+    }
+}
@@ -0,0 +1,649 @@
+use super::formula::{ADDITIVE, LOGICAL, MULTIPLICATIVE, POWER, RELATIONAL, UNARY};
+use crate::structs::constants::Constant;
+use crate::structs::container::{Closure, Container};
+use crate::structs::csymbol::SbmlSymbol;
+use crate::structs::math_node::{MathNode, NodeIndex};
+use crate::structs::math_tree::MathTree;
+use crate::structs::numbers::Number;
+use crate::structs::op::Op;
+use crate::structs::qualifier::Qualifier;
+
+// Names, literals, calls and anything in parentheses.
+const ATOM: u8 = POWER + 1;
+// Fractions group their operands but still need parentheses as a base.
+const FRACTION: u8 = POWER;
+
+/// Controls how [`write_latex`] formats its output.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LatexOptions {
+    /// Written between the factors of a product, e.g. `\cdot` or `\times`.
+    /// An empty string writes the factors next to each other.
+    pub times: String,
+}
+
+impl Default for LatexOptions {
+    fn default() -> Self {
+        LatexOptions {
+            times: "\\cdot".to_string(),
+        }
+    }
+}
+
+/// Renders a tree as a LaTeX math-mode expression with default options.
+pub fn to_latex(tree: &MathTree) -> String {
+    write_latex(tree, &LatexOptions::default())
+}
+
+/// Renders a tree as a LaTeX math-mode expression.
+///
+/// Divisions become `\frac`, powers superscripts and piecewise expressions
+/// a `cases` environment. Identifiers named after Greek letters, like
+/// `alpha` or `Delta`, are written as the letter, and anything after the
+/// first underscore becomes a subscript.
+pub fn write_latex(tree: &MathTree, options: &LatexOptions) -> String {
+    let mut writer = LatexWriter {
+        tree,
+        options,
+        out: String::new(),
+    };
+    writer.write(tree.root());
+    writer.out
+}
+
+// The symbol and precedence of an operator written infix with `n` operands.
+fn infix(op: &Op, n: usize) -> Option<(&'static str, u8)> {
+    let infix = match (op, n) {
+        (Op::And, 2..) => (" \\land ", LOGICAL),
+        (Op::Or, 2..) => (" \\lor ", LOGICAL),
+        (Op::Xor, 2..) => (" \\oplus ", LOGICAL),
+        (Op::Implies, 2) => (" \\Rightarrow ", LOGICAL),
+        (Op::Eq, 2..) => (" = ", RELATIONAL),
+        (Op::Neq, 2) => (" \\neq ", RELATIONAL),
+        (Op::Lt, 2..) => (" < ", RELATIONAL),
+        (Op::Gt, 2..) => (" > ", RELATIONAL),
+        (Op::Leq, 2..) => (" \\leq ", RELATIONAL),
+        (Op::Geq, 2..) => (" \\geq ", RELATIONAL),
+        (Op::Plus, 2..) => (" + ", ADDITIVE),
+        (Op::Minus, 2) => (" - ", ADDITIVE),
+        (Op::Times, 2..) => ("", MULTIPLICATIVE),
+        (Op::Rem, 2) => (" \\bmod ", MULTIPLICATIVE),
+        (Op::Minus, 1) => ("-", UNARY),
+        (Op::Not, 1) => ("\\lnot ", UNARY),
+        _ => return None,
+    };
+    Some(infix)
+}
+
+// Functions with their own LaTeX command.
+fn function_command(op: &Op) -> Option<&'static str> {
+    let command = match op {
+        Op::Sin => "\\sin",
+        Op::Cos => "\\cos",
+        Op::Tan => "\\tan",
+        Op::Sec => "\\sec",
+        Op::Csc => "\\csc",
+        Op::Cot => "\\cot",
+        Op::Sinh => "\\sinh",
+        Op::Cosh => "\\cosh",
+        Op::Tanh => "\\tanh",
+        Op::Coth => "\\coth",
+        Op::Arcsin => "\\arcsin",
+        Op::Arccos => "\\arccos",
+        Op::Arctan => "\\arctan",
+        Op::Exp => "\\exp",
+        Op::Ln => "\\ln",
+        Op::Log => "\\log",
+        Op::Max => "\\max",
+        Op::Min => "\\min",
+        Op::Gcd => "\\gcd",
+        Op::Determinant => "\\det",
+        _ => return None,
+    };
+    Some(command)
+}
+
+const GREEK: [&str; 35] = [
+    "alpha",
+    "beta",
+    "gamma",
+    "delta",
+    "epsilon",
+    "zeta",
+    "eta",
+    "theta",
+    "iota",
+    "kappa",
+    "lambda",
+    "mu",
+    "nu",
+    "xi",
+    "pi",
+    "rho",
+    "sigma",
+    "tau",
+    "upsilon",
+    "phi",
+    "chi",
+    "psi",
+    "omega",
+    "Gamma",
+    "Delta",
+    "Theta",
+    "Lambda",
+    "Xi",
+    "Pi",
+    "Sigma",
+    "Upsilon",
+    "Phi",
+    "Psi",
+    "Omega",
+    "varepsilon",
+];
+
+// Writes an identifier, mapping Greek letter names and subscripts. The
+// subscript starts after the first underscore or, failing that, at a run of
+// trailing digits, so both `k_1` and `k1` give `k_{1}`.
+fn identifier(name: &str) -> String {
+    let digits = name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (base, subscript) = match name.find('_') {
+        Some(i) if i > 0 && i + 1 < name.len() => (&name[..i], Some(&name[i + 1..])),
+        None if digits > 0 && digits < name.len() => (&name[..digits], Some(&name[digits..])),
+        _ => (name, None),
+    };
+    let base = if GREEK.contains(&base) {
+        format!("\\{}", base)
+    } else if base.chars().count() == 1 {
+        base.to_string()
+    } else {
+        format!("\\mathrm{{{}}}", escape(base))
+    };
+    match subscript {
+        Some(subscript) => format!("{}_{{{}}}", base, identifier(subscript)),
+        None => base,
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '_' | '&' | '%' | '$' | '#' | '{' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn is_negative(value: &Number) -> bool {
+    match value {
+        Number::Real(r) => r.is_sign_negative() && !r.is_nan(),
+        Number::Integer(i) => *i < 0,
+        Number::Rational(a, b) => (*a < 0) != (*b < 0),
+        Number::ENotation(m, _) => *m < 0.0,
+        _ => false,
+    }
+}
+
+struct LatexWriter<'a> {
+    tree: &'a MathTree,
+    options: &'a LatexOptions,
+    out: String,
+}
+
+impl<'a> LatexWriter<'a> {
+    fn precedence(&self, idx: NodeIndex) -> u8 {
+        match self.tree.get(idx) {
+            Some(MathNode::Apply(apply)) => match self.tree.op_of(idx) {
+                Some(Op::Divide) if apply.operands.len() == 2 => FRACTION,
+                Some(Op::Power) if apply.operands.len() == 2 => POWER,
+                Some(Op::Factorial) if apply.operands.len() == 1 => POWER,
+                // big operators extend as far right as possible
+                Some(Op::Sum) | Some(Op::Product) | Some(Op::Int) if !apply.bvars.is_empty() => {
+                    ADDITIVE
+                }
+                Some(Op::Diff) | Some(Op::Partialdiff) if !apply.bvars.is_empty() => MULTIPLICATIVE,
+                Some(op) => infix(op, apply.operands.len()).map_or(ATOM, |(_, p)| p),
+                None => ATOM,
+            },
+            Some(MathNode::Cn(cn)) => match &cn.value {
+                Some(value) if is_negative(value) => UNARY,
+                Some(Number::Rational(..)) => FRACTION,
+                Some(Number::ENotation(..)) | Some(Number::ComplexPolar(..)) => MULTIPLICATIVE,
+                Some(Number::ComplexCartesian(..)) => ADDITIVE,
+                _ => ATOM,
+            },
+            // annotations are not printed, so the expression alone decides
+            Some(MathNode::Semantics(semantics)) => {
+                semantics.expr.map_or(ATOM, |expr| self.precedence(expr))
+            }
+            _ => ATOM,
+        }
+    }
+
+    fn write(&mut self, idx: NodeIndex) {
+        let node = match self.tree.get(idx) {
+            Some(node) => node,
+            None => return,
+        };
+        match node {
+            MathNode::Root(..) | MathNode::Qualifier(..) => self.write_list(node.children()),
+            MathNode::BVar(..) => self.write_list(node.children().get(..1).unwrap_or_default()),
+            MathNode::Apply(apply) => match self.tree.op_of(idx) {
+                Some(op) => self.write_apply(idx, op, &apply.operands),
+                None => {
+                    if let Some(operator) = apply.operator {
+                        self.write_operand(operator, ATOM);
+                    }
+                    self.write_arguments(&apply.operands);
+                }
+            },
+            MathNode::Op(opnode) => {
+                if let Some(op) = &opnode.op {
+                    match function_command(op) {
+                        Some(command) => self.out.push_str(command),
+                        None => self.push_operator_name(op.name()),
+                    }
+                }
+            }
+            MathNode::Constant(constantnode) => {
+                let text = match &constantnode.constant {
+                    Some(Constant::Pi) => "\\pi",
+                    Some(Constant::ExponentialE) => "e",
+                    Some(Constant::ImaginaryI) => "i",
+                    Some(Constant::EulerGamma) => "\\gamma",
+                    Some(Constant::Infinity) => "\\infty",
+                    Some(Constant::NotANumber) => "\\mathrm{NaN}",
+                    Some(Constant::True) => "\\mathrm{true}",
+                    Some(Constant::False) => "\\mathrm{false}",
+                    None => "",
+                };
+                self.out.push_str(text);
+            }
+            MathNode::Ci(ci) => {
+                let name = identifier(ci.name.as_deref().unwrap_or_default());
+                self.out.push_str(&name);
+            }
+            MathNode::Cn(cn) => {
+                if let Some(value) = &cn.value {
+                    self.write_number(value);
+                }
+            }
+            MathNode::Csymbol(csymbol) => match csymbol.sbml_symbol() {
+                Some(SbmlSymbol::Time) => self.out.push('t'),
+                Some(SbmlSymbol::Avogadro) => self.out.push_str("N_{A}"),
+                Some(symbol) => self.push_operator_name(symbol.name()),
+                None => {
+                    let name = identifier(csymbol.text.as_deref().unwrap_or_default());
+                    self.out.push_str(&name);
+                }
+            },
+            MathNode::Lambda(..) => {
+                let params: Vec<_> = self
+                    .tree
+                    .parameters_of(idx)
+                    .into_iter()
+                    .map(identifier)
+                    .collect();
+                match params.len() {
+                    1 => self.out.push_str(&params[0]),
+                    _ => self
+                        .out
+                        .push_str(&format!("\\left({}\\right)", params.join(", "))),
+                }
+                self.out.push_str(" \\mapsto ");
+                if let Some(body) = self.tree.body_of(idx) {
+                    self.write(body);
+                }
+            }
+            MathNode::Piecewise(..) => {
+                self.out.push_str("\\begin{cases}");
+                for &piece in self.tree.pieces_of(idx) {
+                    if let Some((value, condition)) = self.tree.piece_parts(piece) {
+                        self.out.push(' ');
+                        self.write(value);
+                        self.out.push_str(" & ");
+                        self.write(condition);
+                        self.out.push_str(" \\\\");
+                    }
+                }
+                if let Some(otherwise) = self.tree.otherwise_of(idx) {
+                    self.out.push(' ');
+                    self.write(otherwise);
+                    self.out.push_str(" & \\text{otherwise}");
+                }
+                self.out.push_str(" \\end{cases}");
+            }
+            MathNode::Piece(..) | MathNode::Otherwise(..) => {
+                for (i, &child) in node.children().iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(" & ");
+                    }
+                    self.write(child);
+                }
+            }
+            MathNode::Semantics(semantics) => {
+                if let Some(expr) = semantics.expr {
+                    self.write(expr);
+                }
+            }
+            MathNode::Container(containernode) => {
+                let items = node.children();
+                let (open, close) = match containernode.container {
+                    Some(Container::Matrix) => {
+                        self.out.push_str("\\begin{pmatrix}");
+                        for (i, &row) in items.iter().enumerate() {
+                            self.out.push_str(if i > 0 { " \\\\ " } else { " " });
+                            self.write(row);
+                        }
+                        self.out.push_str(" \\end{pmatrix}");
+                        return;
+                    }
+                    Some(Container::MatrixRow) => {
+                        for (i, &cell) in items.iter().enumerate() {
+                            if i > 0 {
+                                self.out.push_str(" & ");
+                            }
+                            self.write(cell);
+                        }
+                        return;
+                    }
+                    Some(Container::Vector) => ("\\left(", "\\right)"),
+                    Some(Container::Set) => ("\\left\\{", "\\right\\}"),
+                    Some(Container::Interval) => match containernode.closure {
+                        Some(Closure::Open) => ("\\left(", "\\right)"),
+                        Some(Closure::OpenClosed) => ("\\left(", "\\right]"),
+                        Some(Closure::ClosedOpen) => ("\\left[", "\\right)"),
+                        Some(Closure::Closed) | None => ("\\left[", "\\right]"),
+                    },
+                    Some(Container::List) | None => ("\\left[", "\\right]"),
+                };
+                self.out.push_str(open);
+                self.write_list(items);
+                self.out.push_str(close);
+            }
+        }
+    }
+
+    fn write_apply(&mut self, idx: NodeIndex, op: &Op, operands: &[NodeIndex]) {
+        let degree = self.tree.qualifier_value_of(idx, &Qualifier::Degree);
+        let bvar = self.tree.bvars_of(idx).first().copied();
+        match (op, operands) {
+            (Op::Divide, &[numerator, denominator]) => {
+                self.out.push_str("\\frac{");
+                self.write(numerator);
+                self.out.push_str("}{");
+                self.write(denominator);
+                self.out.push('}');
+            }
+            (Op::Power, &[base, exponent]) => {
+                self.write_operand(base, ATOM);
+                self.out.push_str("^{");
+                self.write(exponent);
+                self.out.push('}');
+            }
+            (Op::Root, &[radicand]) => {
+                self.out.push_str("\\sqrt");
+                if let Some(degree) = degree {
+                    self.out.push('[');
+                    self.write(degree);
+                    self.out.push(']');
+                }
+                self.out.push('{');
+                self.write(radicand);
+                self.out.push('}');
+            }
+            (Op::Abs, &[operand]) => self.write_delimited("\\left|", operand, "\\right|"),
+            (Op::Floor, &[operand]) => {
+                self.write_delimited("\\left\\lfloor ", operand, " \\right\\rfloor")
+            }
+            (Op::Ceiling, &[operand]) => {
+                self.write_delimited("\\left\\lceil ", operand, " \\right\\rceil")
+            }
+            (Op::Factorial, &[operand]) => {
+                self.write_operand(operand, ATOM);
+                self.out.push('!');
+            }
+            (Op::Log, &[operand]) => {
+                self.out.push_str("\\log");
+                if let Some(base) = self.tree.qualifier_value_of(idx, &Qualifier::LogBase) {
+                    self.out.push_str("_{");
+                    self.write(base);
+                    self.out.push('}');
+                }
+                self.write_arguments(&[operand]);
+            }
+            (Op::Sum, &[body]) | (Op::Product, &[body]) if bvar.is_some() => {
+                self.out.push_str(match op {
+                    Op::Sum => "\\sum",
+                    _ => "\\prod",
+                });
+                let (low, high) = self.limits(idx);
+                if let Some(low) = low {
+                    self.out.push_str("_{");
+                    self.write_variable(bvar);
+                    self.out.push_str(" = ");
+                    self.write(low);
+                    self.out.push('}');
+                } else if let Some(condition) =
+                    self.tree.qualifier_value_of(idx, &Qualifier::Condition)
+                {
+                    self.out.push_str("_{");
+                    self.write(condition);
+                    self.out.push('}');
+                }
+                self.write_superscript(high);
+                self.out.push(' ');
+                self.write_operand(body, MULTIPLICATIVE);
+            }
+            (Op::Int, &[body]) if bvar.is_some() => {
+                self.out.push_str("\\int");
+                let (low, high) = self.limits(idx);
+                if let Some(low) = low {
+                    self.out.push_str("_{");
+                    self.write(low);
+                    self.out.push('}');
+                }
+                self.write_superscript(high);
+                self.out.push(' ');
+                self.write_operand(body, MULTIPLICATIVE);
+                self.out.push_str(" \\, d");
+                self.write_variable(bvar);
+            }
+            (Op::Diff, &[body]) | (Op::Partialdiff, &[body]) if bvar.is_some() => {
+                let d = match op {
+                    Op::Diff => "d",
+                    _ => "\\partial ",
+                };
+                let order =
+                    bvar.and_then(|bvar| self.tree.qualifier_value_of(bvar, &Qualifier::Degree));
+                self.out.push_str("\\frac{");
+                self.out.push_str(d.trim_end());
+                self.write_superscript(order);
+                self.out.push_str("}{");
+                self.out.push_str(d);
+                self.write_variable(bvar);
+                self.write_superscript(order);
+                self.out.push_str("} ");
+                self.write_operand(body, POWER);
+            }
+            _ => match infix(op, operands.len()) {
+                Some((symbol, precedence)) => self.write_infix(op, symbol, precedence, operands),
+                None => {
+                    match function_command(op) {
+                        Some(command) => self.out.push_str(command),
+                        None => self.push_operator_name(op.name()),
+                    }
+                    self.write_arguments(operands);
+                }
+            },
+        }
+    }
+
+    // The lower and upper limit of a sum, product or integral, given either
+    // as limits or as an interval.
+    fn limits(&self, idx: NodeIndex) -> (Option<NodeIndex>, Option<NodeIndex>) {
+        match self.tree.interval_of(idx) {
+            Some((low, high)) => (Some(low), Some(high)),
+            None => (
+                self.tree.qualifier_value_of(idx, &Qualifier::LowLimit),
+                self.tree.qualifier_value_of(idx, &Qualifier::UpLimit),
+            ),
+        }
+    }
+
+    fn write_superscript(&mut self, exponent: Option<NodeIndex>) {
+        if let Some(exponent) = exponent {
+            self.out.push_str("^{");
+            self.write(exponent);
+            self.out.push('}');
+        }
+    }
+
+    fn write_variable(&mut self, bvar: Option<NodeIndex>) {
+        if let Some(bvar) = bvar {
+            self.write(bvar);
+        }
+    }
+
+    fn write_infix(&mut self, op: &Op, symbol: &str, precedence: u8, operands: &[NodeIndex]) {
+        if precedence == UNARY {
+            self.out.push_str(symbol);
+            self.write_operand(operands[0], UNARY);
+            return;
+        }
+        for (i, &operand) in operands.iter().enumerate() {
+            if i > 0 {
+                if *op == Op::Times {
+                    self.push_times();
+                } else {
+                    self.out.push_str(symbol);
+                }
+            }
+            // keep signs apart from the operator before them, as in `a - (-b)`
+            let required = if i > 0 && self.precedence(operand) == UNARY {
+                ATOM
+            } else if i > 0 || precedence == RELATIONAL {
+                precedence + 1
+            } else {
+                precedence
+            };
+            self.write_operand(operand, required);
+        }
+    }
+
+    fn push_times(&mut self) {
+        self.out.push(' ');
+        if !self.options.times.is_empty() {
+            self.out.push_str(&self.options.times);
+            self.out.push(' ');
+        }
+    }
+
+    fn push_operator_name(&mut self, name: &str) {
+        self.out
+            .push_str(&format!("\\operatorname{{{}}}", escape(name)));
+    }
+
+    fn write_operand(&mut self, idx: NodeIndex, required: u8) {
+        if self.precedence(idx) < required {
+            self.write_delimited("\\left(", idx, "\\right)");
+        } else {
+            self.write(idx);
+        }
+    }
+
+    fn write_delimited(&mut self, open: &str, idx: NodeIndex, close: &str) {
+        self.out.push_str(open);
+        self.write(idx);
+        self.out.push_str(close);
+    }
+
+    fn write_arguments(&mut self, args: &[NodeIndex]) {
+        self.out.push_str("\\left(");
+        self.write_list(args);
+        self.out.push_str("\\right)");
+    }
+
+    fn write_list(&mut self, items: &[NodeIndex]) {
+        for (i, &item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.write(item);
+        }
+    }
+
+    fn write_number(&mut self, value: &Number) {
+        let text = match value {
+            Number::Real(r) if r.is_infinite() => {
+                if *r > 0.0 { "\\infty" } else { "-\\infty" }.to_string()
+            }
+            Number::Real(r) if r.is_nan() => "\\mathrm{NaN}".to_string(),
+            Number::Real(r) => r.to_string(),
+            Number::Integer(i) => i.to_string(),
+            Number::Rational(a, b) if (*a < 0) != (*b < 0) => {
+                format!("-\\frac{{{}}}{{{}}}", a.unsigned_abs(), b.unsigned_abs())
+            }
+            Number::Rational(a, b) => {
+                format!("\\frac{{{}}}{{{}}}", a.unsigned_abs(), b.unsigned_abs())
+            }
+            Number::ENotation(m, e) => format!("{} \\times 10^{{{}}}", m, e),
+            Number::ComplexCartesian(a, b) if *b < 0.0 => format!("{} - {} i", a, -b),
+            Number::ComplexCartesian(a, b) => format!("{} + {} i", a, b),
+            Number::ComplexPolar(r, theta) => format!("{} e^{{{} i}}", r, theta),
+            Number::Constant(s) => format!("\\mathrm{{{}}}", escape(s)),
+        };
+        self.out.push_str(&text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn latex_output() {
+        let latex = |formula: &str| to_latex(&parse_formula(formula).unwrap());
+        assert_eq!(
+            latex("k1 * A * B / (Km + A)"),
+            "\\frac{k_{1} \\cdot A \\cdot B}{\\mathrm{Km} + A}"
+        );
+        assert_eq!(
+            latex("(a + 1)^(2*n) - sqrt(x_max) * -b"),
+            "\\left(a + 1\\right)^{2 \\cdot n} - \\sqrt{x_{\\mathrm{max}}} \\cdot \\left(-b\\right)"
+        );
+        assert_eq!(
+            latex("piecewise(alpha, t > tau, 0)"),
+            "\\begin{cases} \\alpha & t > \\tau \\\\ 0 & \\text{otherwise} \\end{cases}"
+        );
+        assert_eq!(
+            latex("exp(-Delta_G / (R*T)) + abs(x) + f(pi, 2.5e-3)"),
+            "\\exp\\left(\\frac{-\\Delta_{G}}{R \\cdot T}\\right) + \\left|x\\right| \
+             + f\\left(\\pi, 2.5 \\times 10^{-3}\\right)"
+        );
+
+        let options = LatexOptions {
+            times: String::new(),
+        };
+        let tree = parse_formula("2*x*y").unwrap();
+        assert_eq!(write_latex(&tree, &options), "2 x y");
+
+        // numbers are written with a single sign, whatever their magnitude
+        let number = |cn: &str| to_latex(&parse_str(&format!("<math>{}</math>", cn)).unwrap());
+        assert_eq!(
+            number("<cn type=\"rational\">-9223372036854775808<sep/>3</cn>"),
+            "-\\frac{9223372036854775808}{3}"
+        );
+        assert_eq!(
+            number("<cn type=\"rational\">1<sep/>-9223372036854775808</cn>"),
+            "-\\frac{1}{9223372036854775808}"
+        );
+        assert_eq!(
+            number("<cn type=\"complex-cartesian\">1.5<sep/>-2</cn>"),
+            "1.5 - 2 i"
+        );
+        assert_eq!(
+            number("<cn type=\"complex-cartesian\">1.5<sep/>2</cn>"),
+            "1.5 + 2 i"
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use super::evaluate::{evaluate, expect_operands, expect_real, get_node, Environment};
+use crate::structs::container::Container;
+use crate::structs::error::EvalError;
+use crate::structs::math_node::{MathNode, NodeIndex};
+use crate::structs::op::Op;
+use mathru::algebra::linear::matrix::Transpose;
+use mathru::algebra::linear::{Matrix, Vector};
+
+/// The value of an expression that may be a vector or a matrix.
+///
+/// Vectors are column vectors. Transposing one gives a matrix with a
+/// single row. Vectors and matrices without components can be built, but
+/// [`evaluate_array`] rejects them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayValue {
+    Scalar(f64),
+    Vector(Vector<f64>),
+    Matrix(Matrix<f64>),
+}
+
+impl ArrayValue {
+    /// A column vector with the given components.
+    pub fn vector(components: Vec<f64>) -> Self {
+        ArrayValue::Vector(Vector::new_column(components.len(), components))
+    }
+
+    /// A matrix from its rows, which all have `N` columns.
+    pub fn matrix<const N: usize>(rows: &[[f64; N]]) -> Self {
+        ArrayValue::from_entries(rows.len(), N, |i, j| rows[i][j])
+    }
+
+    // A matrix of the given shape with the entry in row `i` and column `j`
+    // given by `entry(i, j)`.
+    fn from_entries(rows: usize, columns: usize, entry: impl Fn(usize, usize) -> f64) -> Self {
+        let entry = &entry;
+        let data = (0..columns)
+            .flat_map(|j| (0..rows).map(move |i| entry(i, j)))
+            .collect();
+        ArrayValue::Matrix(Matrix::new(rows, columns, data))
+    }
+
+    pub fn as_scalar(&self) -> Option<f64> {
+        match self {
+            ArrayValue::Scalar(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Whether this is a vector or matrix without any components, which
+    // mathru cannot work with.
+    fn is_empty(&self) -> bool {
+        match self {
+            ArrayValue::Scalar(..) => false,
+            ArrayValue::Vector(v) => v.dim().0 == 0,
+            ArrayValue::Matrix(m) => m.dim().0 == 0 || m.dim().1 == 0,
+        }
+    }
+}
+
+impl From<f64> for ArrayValue {
+    fn from(value: f64) -> Self {
+        ArrayValue::Scalar(value)
+    }
+}
+
+/// Evaluates the subtree rooted at `head_idx`, where variables and results
+/// may be vectors or matrices.
+///
+/// Variables are looked up with [`Environment::array`] first. `vector` and
+/// `matrix` containers, `plus`, `minus` and `times` on arrays of matching
+/// shapes, and `determinant`, `transpose`, `vectorproduct`,
+/// `scalarproduct`, `outerproduct` and `selector` are evaluated here.
+/// Every other subexpression must be a real number and is handed to
+/// [`evaluate`] with the same environment.
+pub fn evaluate_array(
+    nodes: &[MathNode],
+    head_idx: NodeIndex,
+    env: &dyn Environment,
+) -> Result<ArrayValue, EvalError> {
+    ArrayEvaluator { nodes, env }.evaluate(head_idx)
+}
+
+struct ArrayEvaluator<'a> {
+    nodes: &'a [MathNode],
+    env: &'a dyn Environment,
+}
+
+impl<'a> ArrayEvaluator<'a> {
+    fn evaluate(&self, idx: NodeIndex) -> Result<ArrayValue, EvalError> {
+        match get_node(self.nodes, idx)? {
+            MathNode::Root(root) if root.children.len() == 1 => self.evaluate(root.children[0]),
+            MathNode::Semantics(semantics) => match semantics.expr {
+                Some(expr) => self.evaluate(expr),
+                None => self.scalar(idx),
+            },
+            MathNode::Ci(ci) => match ci.name.as_ref().and_then(|name| self.env.array(name)) {
+                Some(value) if value.is_empty() => Err(EvalError::MalformedNode {
+                    reason: "empty vector or matrix",
+                    node: idx,
+                }),
+                Some(value) => Ok(value),
+                None => self.scalar(idx),
+            },
+            MathNode::Container(containernode) => match containernode.container {
+                Some(Container::Vector) if containernode.children.is_empty() => {
+                    Err(EvalError::MalformedNode {
+                        reason: "empty vector",
+                        node: idx,
+                    })
+                }
+                Some(Container::Vector) => {
+                    let components = containernode
+                        .children
+                        .iter()
+                        .map(|&child| self.component(child))
+                        .collect::<Result<_, _>>()?;
+                    Ok(ArrayValue::vector(components))
+                }
+                Some(Container::Matrix) => {
+                    let rows = containernode
+                        .children
+                        .iter()
+                        .map(|&row| {
+                            get_node(self.nodes, row)?
+                                .children()
+                                .iter()
+                                .map(|&child| self.component(child))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let columns = rows.first().map_or(0, Vec::len);
+                    if columns == 0 {
+                        return Err(EvalError::MalformedNode {
+                            reason: "empty matrix",
+                            node: idx,
+                        });
+                    }
+                    if rows.iter().any(|row| row.len() != columns) {
+                        return Err(EvalError::MalformedNode {
+                            reason: "matrix rows of different lengths",
+                            node: idx,
+                        });
+                    }
+                    Ok(ArrayValue::from_entries(rows.len(), columns, |i, j| {
+                        rows[i][j]
+                    }))
+                }
+                _ => Err(EvalError::TypeMismatch {
+                    expected: "vector or matrix",
+                    node: idx,
+                }),
+            },
+            MathNode::Apply(apply) => {
+                let op = match apply.get_op(self.nodes) {
+                    Ok(op) => op,
+                    Err(..) => return self.scalar(idx),
+                };
+                let mut operands = apply.operands.iter().map(|&operand| self.evaluate(operand));
+                match op {
+                    Op::Plus => {
+                        let mut result = operands.next().unwrap_or(Ok(ArrayValue::Scalar(0.0)))?;
+                        for operand in operands {
+                            result = add(result, operand?, 1.0, &op, idx)?;
+                        }
+                        Ok(result)
+                    }
+                    Op::Minus => {
+                        let operands = operands.collect::<Result<Vec<_>, _>>()?;
+                        match operands.as_slice() {
+                            [a] => Ok(scale(a.clone(), -1.0)),
+                            [a, b] => add(a.clone(), b.clone(), -1.0, &op, idx),
+                            _ => Err(EvalError::ArityMismatch {
+                                op,
+                                expected: if operands.is_empty() { 1 } else { 2 },
+                                got: operands.len(),
+                                node: idx,
+                            }),
+                        }
+                    }
+                    Op::Times => {
+                        let mut result = operands.next().unwrap_or(Ok(ArrayValue::Scalar(1.0)))?;
+                        for operand in operands {
+                            result = multiply(result, operand?, &op, idx)?;
+                        }
+                        Ok(result)
+                    }
+                    Op::Determinant => {
+                        expect_operands(apply, &op, 1, idx)?;
+                        match self.evaluate(apply.operands[0])? {
+                            ArrayValue::Matrix(m) if is_square(&m) => {
+                                Ok(ArrayValue::Scalar(m.det()))
+                            }
+                            _ => Err(EvalError::TypeMismatch {
+                                expected: "square matrix",
+                                node: apply.operands[0],
+                            }),
+                        }
+                    }
+                    Op::Transpose => {
+                        expect_operands(apply, &op, 1, idx)?;
+                        match self.evaluate(apply.operands[0])? {
+                            ArrayValue::Matrix(m) => Ok(ArrayValue::Matrix(m.transpose())),
+                            ArrayValue::Vector(v) => {
+                                let components = v.convert_to_vec();
+                                Ok(ArrayValue::Matrix(Matrix::new(
+                                    1,
+                                    components.len(),
+                                    components,
+                                )))
+                            }
+                            scalar => Ok(scalar),
+                        }
+                    }
+                    Op::Vectorproduct | Op::Scalarproduct | Op::Outerproduct => {
+                        expect_operands(apply, &op, 2, idx)?;
+                        let a = self.vector(apply.operands[0])?;
+                        let b = self.vector(apply.operands[1])?;
+                        if a.len() != b.len() || (op == Op::Vectorproduct && a.len() != 3) {
+                            return Err(EvalError::ShapeMismatch { op, node: idx });
+                        }
+                        Ok(match op {
+                            Op::Vectorproduct => ArrayValue::vector(vec![
+                                a[1] * b[2] - a[2] * b[1],
+                                a[2] * b[0] - a[0] * b[2],
+                                a[0] * b[1] - a[1] * b[0],
+                            ]),
+                            Op::Scalarproduct => {
+                                ArrayValue::Scalar(a.iter().zip(&b).map(|(x, y)| x * y).sum())
+                            }
+                            _ => ArrayValue::from_entries(a.len(), b.len(), |i, j| a[i] * b[j]),
+                        })
+                    }
+                    Op::Selector => self.select(apply.operands.as_slice(), idx),
+                    _ => self.scalar(idx),
+                }
+            }
+            _ => self.scalar(idx),
+        }
+    }
+
+    // Evaluates a subexpression that does not involve arrays.
+    fn scalar(&self, idx: NodeIndex) -> Result<ArrayValue, EvalError> {
+        let value = evaluate(self.nodes, idx, self.env)?;
+        expect_real(value, idx).map(ArrayValue::Scalar)
+    }
+
+    fn component(&self, idx: NodeIndex) -> Result<f64, EvalError> {
+        self.evaluate(idx)?
+            .as_scalar()
+            .ok_or(EvalError::TypeMismatch {
+                expected: "scalar",
+                node: idx,
+            })
+    }
+
+    fn vector(&self, idx: NodeIndex) -> Result<Vec<f64>, EvalError> {
+        match self.evaluate(idx)? {
+            ArrayValue::Vector(v) => Ok(v.convert_to_vec()),
+            _ => Err(EvalError::TypeMismatch {
+                expected: "vector",
+                node: idx,
+            }),
+        }
+    }
+
+    // `selector(v, i)`, `selector(m, i)` for a row and `selector(m, i, j)`,
+    // with indices counted from 1.
+    fn select(&self, operands: &[NodeIndex], idx: NodeIndex) -> Result<ArrayValue, EvalError> {
+        let (&array, indices) = operands.split_first().ok_or(EvalError::ArityMismatch {
+            op: Op::Selector,
+            expected: 2,
+            got: 0,
+            node: idx,
+        })?;
+        let indices = indices
+            .iter()
+            .map(|&index| self.component(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let index = |i: f64, len: usize| {
+            if i.fract() == 0.0 && i >= 1.0 && i <= len as f64 {
+                Ok(i as usize - 1)
+            } else {
+                Err(EvalError::DomainError {
+                    op: Op::Selector,
+                    reason: "index out of range",
+                    node: idx,
+                })
+            }
+        };
+        match (self.evaluate(array)?, indices.as_slice()) {
+            (ArrayValue::Vector(v), &[i]) => {
+                let (len, _) = v.dim();
+                Ok(ArrayValue::Scalar(*v.get(index(i, len)?)))
+            }
+            (ArrayValue::Matrix(m), &[i]) => {
+                let (rows, columns) = m.dim();
+                let row = m.get_row(index(i, rows)?).convert_to_vec();
+                Ok(ArrayValue::vector(row[..columns].to_vec()))
+            }
+            (ArrayValue::Matrix(m), &[i, j]) => {
+                let (rows, columns) = m.dim();
+                Ok(ArrayValue::Scalar(
+                    *m.get(index(i, rows)?, index(j, columns)?),
+                ))
+            }
+            (ArrayValue::Scalar(..), _) => Err(EvalError::TypeMismatch {
+                expected: "vector or matrix",
+                node: array,
+            }),
+            _ => Err(EvalError::ArityMismatch {
+                op: Op::Selector,
+                expected: 3,
+                got: operands.len(),
+                node: idx,
+            }),
+        }
+    }
+}
+
+fn is_square(m: &Matrix<f64>) -> bool {
+    let (rows, columns) = m.dim();
+    rows == columns && rows > 0
+}
+
+fn scale(value: ArrayValue, factor: f64) -> ArrayValue {
+    match value {
+        ArrayValue::Scalar(a) => ArrayValue::Scalar(a * factor),
+        ArrayValue::Vector(v) => ArrayValue::Vector(v * factor),
+        ArrayValue::Matrix(m) => ArrayValue::Matrix(m * factor),
+    }
+}
+
+// Adds `factor` times `b` to `a`, elementwise for arrays of the same shape.
+fn add(
+    a: ArrayValue,
+    b: ArrayValue,
+    factor: f64,
+    op: &Op,
+    node: NodeIndex,
+) -> Result<ArrayValue, EvalError> {
+    match (a, scale(b, factor)) {
+        (ArrayValue::Scalar(a), ArrayValue::Scalar(b)) => Ok(ArrayValue::Scalar(a + b)),
+        (ArrayValue::Vector(a), ArrayValue::Vector(b)) if a.dim() == b.dim() => {
+            Ok(ArrayValue::Vector(a + b))
+        }
+        (ArrayValue::Matrix(a), ArrayValue::Matrix(b)) if a.dim() == b.dim() => {
+            Ok(ArrayValue::Matrix(a + b))
+        }
+        _ => Err(EvalError::ShapeMismatch {
+            op: op.clone(),
+            node,
+        }),
+    }
+}
+
+// Multiplies by scalars, or as matrices where the dimensions agree.
+fn multiply(
+    a: ArrayValue,
+    b: ArrayValue,
+    op: &Op,
+    node: NodeIndex,
+) -> Result<ArrayValue, EvalError> {
+    match (a, b) {
+        (ArrayValue::Scalar(a), b) => Ok(scale(b, a)),
+        (a, ArrayValue::Scalar(b)) => Ok(scale(a, b)),
+        (ArrayValue::Matrix(a), ArrayValue::Matrix(b)) if a.dim().1 == b.dim().0 => {
+            Ok(ArrayValue::Matrix(a * b))
+        }
+        (ArrayValue::Matrix(a), ArrayValue::Vector(b)) if a.dim().1 == b.dim().0 => {
+            Ok(ArrayValue::Vector(a * b))
+        }
+        _ => Err(EvalError::ShapeMismatch {
+            op: op.clone(),
+            node,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn linear_algebra() {
+        let scalars = std::collections::HashMap::new();
+        let functions = std::collections::HashMap::new();
+        let evaluate = |math: &str, values: &std::collections::HashMap<String, ArrayValue>| {
+            let tree = parse_str(math).unwrap();
+            let env = MapEnvironment::new(&scalars, &functions).with_arrays(values);
+            evaluate_array(tree.nodes(), tree.root(), &env)
+        };
+        let mut values = std::collections::HashMap::new();
+        values.insert(
+            "A".to_string(),
+            ArrayValue::matrix(&[[1.0, 2.0], [3.0, 4.0]]),
+        );
+        values.insert("v".to_string(), ArrayValue::vector(vec![1.0, 1.0]));
+        values.insert("k".to_string(), ArrayValue::Scalar(2.0));
+
+        let cases = [
+            (
+                "<math><apply><times/><ci>A</ci><ci>v</ci></apply></math>",
+                ArrayValue::vector(vec![3.0, 7.0]),
+            ),
+            (
+                "<math><apply><plus/><ci>v</ci>\
+                    <vector><ci>k</ci><apply><sin/><cn>0</cn></apply></vector></apply></math>",
+                ArrayValue::vector(vec![3.0, 1.0]),
+            ),
+            (
+                "<math><apply><minus/><ci>A</ci><apply><times/><ci>k</ci><ci>A</ci></apply>\
+                    </apply></math>",
+                ArrayValue::matrix(&[[-1.0, -2.0], [-3.0, -4.0]]),
+            ),
+            (
+                "<math><apply><determinant/><ci>A</ci></apply></math>",
+                ArrayValue::Scalar(-2.0),
+            ),
+            (
+                "<math><apply><transpose/><matrix>\
+                    <matrixrow><cn>1</cn><cn>2</cn><cn>3</cn></matrixrow></matrix></apply></math>",
+                ArrayValue::matrix(&[[1.0], [2.0], [3.0]]),
+            ),
+            (
+                "<math><apply><vectorproduct/>\
+                    <vector><cn>1</cn><cn>0</cn><cn>0</cn></vector>\
+                    <vector><cn>0</cn><cn>1</cn><cn>0</cn></vector></apply></math>",
+                ArrayValue::vector(vec![0.0, 0.0, 1.0]),
+            ),
+            (
+                "<math><apply><scalarproduct/><ci>v</ci>\
+                    <vector><cn>2</cn><cn>5</cn></vector></apply></math>",
+                ArrayValue::Scalar(7.0),
+            ),
+            (
+                "<math><apply><outerproduct/><ci>v</ci>\
+                    <vector><cn>2</cn><cn>5</cn></vector></apply></math>",
+                ArrayValue::matrix(&[[2.0, 5.0], [2.0, 5.0]]),
+            ),
+            (
+                "<math><apply><selector/><ci>A</ci><cn>2</cn><cn>1</cn></apply></math>",
+                ArrayValue::Scalar(3.0),
+            ),
+            (
+                "<math><apply><selector/><ci>A</ci><cn>2</cn></apply></math>",
+                ArrayValue::vector(vec![3.0, 4.0]),
+            ),
+            (
+                "<math><apply><plus/><ci>k</ci>\
+                    <apply><determinant/><ci>A</ci></apply></apply></math>",
+                ArrayValue::Scalar(0.0),
+            ),
+            (
+                "<math><apply><times/><ci>v</ci>\
+                    <apply><power/><ci>k</ci><cn>2</cn></apply></apply></math>",
+                ArrayValue::vector(vec![4.0, 4.0]),
+            ),
+        ];
+        for (math, expected) in cases.iter() {
+            assert_eq!(evaluate(math, &values).as_ref(), Ok(expected), "{}", math);
+        }
+
+        assert!(matches!(
+            evaluate(
+                "<math><apply><plus/><ci>A</ci><ci>v</ci></apply></math>",
+                &values
+            ),
+            Err(EvalError::ShapeMismatch { op: Op::Plus, .. })
+        ));
+        assert!(matches!(
+            evaluate(
+                "<math><apply><times/><ci>v</ci><ci>A</ci></apply></math>",
+                &values
+            ),
+            Err(EvalError::ShapeMismatch { op: Op::Times, .. })
+        ));
+        assert!(matches!(
+            evaluate(
+                "<math><apply><selector/><ci>v</ci><cn>3</cn></apply></math>",
+                &values
+            ),
+            Err(EvalError::DomainError {
+                op: Op::Selector,
+                ..
+            })
+        ));
+        assert!(matches!(
+            evaluate(
+                "<math><apply><determinant/><ci>v</ci></apply></math>",
+                &values
+            ),
+            Err(EvalError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            evaluate("<math><apply><minus/></apply></math>", &values),
+            Err(EvalError::ArityMismatch {
+                expected: 1,
+                got: 0,
+                ..
+            })
+        ));
+        assert!(matches!(
+            evaluate(
+                "<math><apply><minus/><ci>v</ci><ci>v</ci><ci>v</ci></apply></math>",
+                &values
+            ),
+            Err(EvalError::ArityMismatch {
+                expected: 2,
+                got: 3,
+                ..
+            })
+        ));
+
+        // empty arrays are errors rather than reaching mathru
+        values.insert("e".to_string(), ArrayValue::matrix::<0>(&[]));
+        for math in [
+            "<math><apply><transpose/><matrix/></apply></math>",
+            "<math><apply><transpose/><matrix><matrixrow/></matrix></apply></math>",
+            "<math><apply><scalarproduct/><vector/><vector/></apply></math>",
+            "<math><apply><transpose/><ci>e</ci></apply></math>",
+        ] {
+            assert!(
+                matches!(
+                    evaluate(math, &values),
+                    Err(EvalError::MalformedNode { .. })
+                ),
+                "{}",
+                math
+            );
+        }
+    }
+}
@@ -1 +1,10 @@
+pub mod batch;
+pub mod compile;
 pub mod evaluate;
+pub mod formula;
+pub mod infix;
+pub mod latex;
+pub mod linear_algebra;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod serialize;
@@ -0,0 +1,82 @@
+use super::batch::CHUNK;
+use super::compile::CompiledExpr;
+use super::evaluate::{evaluate, Environment};
+use crate::structs::error::EvalError;
+use crate::structs::math_tree::MathTree;
+use crate::structs::value::Value;
+use rayon::prelude::*;
+
+// Each thread takes this many chunks of rows of a batch at a time.
+const CHUNKS_PER_TASK: usize = 16;
+
+/// Evaluates independent expressions, like the rates of all reactions of a
+/// model, on the threads of rayon's global pool.
+pub fn par_evaluate(
+    trees: &[MathTree],
+    env: &(dyn Environment + Sync),
+) -> Vec<Result<Value, EvalError>> {
+    trees
+        .par_iter()
+        .map(|tree| evaluate(tree.nodes(), tree.root(), env))
+        .collect()
+}
+
+/// Evaluates independent compiled expressions on the same inputs on the
+/// threads of rayon's global pool.
+pub fn par_eval(exprs: &[CompiledExpr], inputs: &[f64]) -> Vec<f64> {
+    exprs.par_iter().map(|expr| expr.eval(inputs)).collect()
+}
+
+impl CompiledExpr {
+    /// Like [`CompiledExpr::eval_batch`], with the rows split between the
+    /// threads of rayon's global pool.
+    pub fn par_eval_batch(&self, columns: &[&[f64]], output: &mut [f64]) {
+        let rows_per_task = CHUNK * CHUNKS_PER_TASK;
+        output
+            .par_chunks_mut(rows_per_task)
+            .enumerate()
+            .for_each(|(i, output)| {
+                let start = i * rows_per_task;
+                // columns the expression does not use may be short
+                let columns: Vec<&[f64]> = columns
+                    .iter()
+                    .map(|column| &column[start.min(column.len())..])
+                    .collect();
+                self.eval_batch(&columns, output);
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn parallel_evaluation() {
+        let values = std::collections::HashMap::from([("x".to_string(), 2.0)]);
+        let functions = std::collections::HashMap::new();
+        let env = MapEnvironment::new(&values, &functions);
+        let trees: Vec<MathTree> = (0..100)
+            .map(|i| parse_formula(&format!("x * {}", i)).unwrap())
+            .collect();
+        let results = par_evaluate(&trees, &env);
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(
+                result.map(|value| value.as_f64().unwrap()),
+                Ok(2.0 * i as f64)
+            );
+        }
+
+        let symbols = SymbolTable::from_variables(&["x"]);
+        let compiled: Vec<CompiledExpr> = trees
+            .iter()
+            .map(|tree| compile(tree, &symbols).unwrap())
+            .collect();
+        assert_eq!(par_eval(&compiled, &[3.0])[10], 30.0);
+
+        let rows = 10_000;
+        let x: Vec<f64> = (0..rows).map(|i| i as f64).collect();
+        let mut output = vec![0.0; rows];
+        compiled[3].par_eval_batch(&[&x], &mut output);
+        assert!(output.iter().enumerate().all(|(i, &y)| y == 3.0 * i as f64));
+    }
+}
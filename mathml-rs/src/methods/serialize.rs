@@ -0,0 +1,383 @@
+use crate::structs::attributes::Attributes;
+use crate::structs::math_node::{MathNode, NodeIndex};
+use crate::structs::math_tree::MathTree;
+use crate::structs::numbers::{NumType, Number};
+use quick_xml::escape::escape;
+
+/// The namespace of MathML 2 and 3.
+pub const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Controls how [`write_mathml`] formats its output.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WriteOptions {
+    /// Prefix for every element name, e.g. `Some("m")` gives `<m:apply>`.
+    pub prefix: Option<String>,
+    /// Whether to declare the MathML namespace on the `math` element.
+    pub declare_namespace: bool,
+    /// Spaces per nesting level, or `None` to write everything on one line.
+    pub indent: Option<usize>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            prefix: None,
+            declare_namespace: true,
+            indent: Some(2),
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Options that write the whole expression without any whitespace.
+    pub fn compact() -> Self {
+        WriteOptions {
+            indent: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+}
+
+/// Writes a tree as a Content MathML `math` element with default options.
+pub fn to_mathml(tree: &MathTree) -> String {
+    write_mathml(tree, &WriteOptions::default())
+}
+
+/// Writes a tree as a Content MathML `math` element.
+///
+/// Anything produced by the parser is written back so that parsing the
+/// output again yields the same expression.
+pub fn write_mathml(tree: &MathTree, options: &WriteOptions) -> String {
+    let mut writer = Writer {
+        tree,
+        options,
+        out: String::new(),
+    };
+    writer.write_node(tree.root(), 0);
+    if options.indent.is_some() {
+        writer.out.push('\n');
+    }
+    writer.out
+}
+
+struct Writer<'a> {
+    tree: &'a MathTree,
+    options: &'a WriteOptions,
+    out: String,
+}
+
+impl<'a> Writer<'a> {
+    fn write_node(&mut self, idx: NodeIndex, depth: usize) {
+        let node = match self.tree.get(idx) {
+            Some(node) => node,
+            None => return,
+        };
+        self.newline(depth);
+        let attributes = self.attribute_list(node.attributes());
+        let children = node.children();
+        match node {
+            MathNode::Root(..) => {
+                let mut namespace = if self.options.declare_namespace {
+                    match &self.options.prefix {
+                        Some(prefix) => attribute(&format!("xmlns:{}", prefix), MATHML_NAMESPACE),
+                        None => attribute("xmlns", MATHML_NAMESPACE),
+                    }
+                } else {
+                    String::new()
+                };
+                // prefixes of units that were declared outside of the math
+                // element, like on an enclosing SBML document
+                for (prefix, uri) in undeclared_prefixes(self.tree) {
+                    namespace.push_str(&attribute(&format!("xmlns:{}", prefix), &uri));
+                }
+                self.write_parent("math", &(namespace + &attributes), children, depth);
+            }
+            MathNode::Apply(..) => self.write_parent("apply", &attributes, children, depth),
+            MathNode::Lambda(..) => self.write_parent("lambda", &attributes, children, depth),
+            MathNode::BVar(..) => self.write_parent("bvar", &attributes, children, depth),
+            MathNode::Piecewise(..) => self.write_parent("piecewise", &attributes, children, depth),
+            MathNode::Piece(..) => self.write_parent("piece", &attributes, children, depth),
+            MathNode::Otherwise(..) => self.write_parent("otherwise", &attributes, children, depth),
+            MathNode::Qualifier(qualifiernode) => {
+                if let Some(qualifier) = &qualifiernode.qualifier {
+                    self.write_parent(qualifier.name(), &attributes, children, depth);
+                }
+            }
+            MathNode::Op(opnode) => {
+                if let Some(op) = &opnode.op {
+                    self.empty_tag(op.name(), &attributes);
+                }
+            }
+            MathNode::Constant(constantnode) => {
+                if let Some(constant) = &constantnode.constant {
+                    self.empty_tag(constant.name(), &attributes);
+                }
+            }
+            MathNode::Ci(ci) => {
+                self.start_tag("ci", &attributes);
+                self.text(ci.name.as_deref().unwrap_or_default());
+                self.end_tag("ci");
+            }
+            MathNode::Csymbol(csymbol) => {
+                let mut symbol = String::new();
+                if let Some(encoding) = &csymbol.encoding {
+                    symbol.push_str(&attribute("encoding", encoding));
+                }
+                if let Some(url) = &csymbol.definition_url {
+                    symbol.push_str(&attribute("definitionURL", url));
+                }
+                self.start_tag("csymbol", &(symbol + &attributes));
+                self.text(csymbol.text.as_deref().unwrap_or_default());
+                self.end_tag("csymbol");
+            }
+            MathNode::Cn(cn) => {
+                // plain reals are the default and need no type attribute
+                let r#type = match (&cn.r#type, &cn.value) {
+                    (Some(r#type), _) => Some(r#type.clone()),
+                    (None, Some(value)) if value.num_type() != NumType::Real => {
+                        Some(value.num_type())
+                    }
+                    _ => None,
+                };
+                let r#type = r#type.map_or_else(String::new, |t| attribute("type", &t.to_string()));
+                self.start_tag("cn", &(r#type + &attributes));
+                match (&cn.r#type, &cn.value) {
+                    (Some(NumType::HexDouble), Some(Number::Real(r))) => {
+                        self.out.push_str(&format!("{:016X}", r.to_bits()))
+                    }
+                    (_, Some(value)) => self.write_number(value),
+                    (_, None) => {}
+                }
+                self.end_tag("cn");
+            }
+            MathNode::Container(containernode) => {
+                if let Some(container) = &containernode.container {
+                    let closure = containernode
+                        .closure
+                        .map_or_else(String::new, |closure| attribute("closure", closure.name()));
+                    self.write_parent(container.name(), &(closure + &attributes), children, depth);
+                }
+            }
+            MathNode::Semantics(semantics) => {
+                self.start_tag("semantics", &attributes);
+                for &child in children {
+                    self.write_node(child, depth + 1);
+                }
+                // annotations are written back exactly as they were read
+                for annotation in &semantics.annotations {
+                    self.newline(depth + 1);
+                    let encoding = annotation
+                        .encoding
+                        .as_ref()
+                        .map_or_else(String::new, |encoding| attribute("encoding", encoding));
+                    let attributes = encoding + &self.attribute_list(&annotation.attributes);
+                    self.start_tag(annotation.name(), &attributes);
+                    self.out.push_str(&annotation.content);
+                    self.end_tag(annotation.name());
+                }
+                self.newline(depth);
+                self.end_tag("semantics");
+            }
+        }
+    }
+
+    fn write_parent(&mut self, name: &str, attributes: &str, children: &[NodeIndex], depth: usize) {
+        self.start_tag(name, attributes);
+        for &child in children {
+            self.write_node(child, depth + 1);
+        }
+        self.newline(depth);
+        self.end_tag(name);
+    }
+
+    fn write_number(&mut self, value: &Number) {
+        match value {
+            Number::Real(r) => self.out.push_str(&r.to_string()),
+            Number::Integer(i) => self.out.push_str(&i.to_string()),
+            Number::Rational(a, b) => self.separated(a, b),
+            Number::ComplexCartesian(a, b) | Number::ComplexPolar(a, b) => self.separated(a, b),
+            Number::ENotation(a, b) => self.separated(a, b),
+            Number::Constant(s) => self.text(s),
+        }
+    }
+
+    fn separated<A: ToString, B: ToString>(&mut self, a: A, b: B) {
+        self.out.push_str(&a.to_string());
+        self.empty_tag("sep", "");
+        self.out.push_str(&b.to_string());
+    }
+
+    fn text(&mut self, text: &str) {
+        self.out.push_str(&escape_str(text));
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: &str) {
+        self.out.push('<');
+        self.qualified_name(name);
+        self.out.push_str(attributes);
+        self.out.push('>');
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        self.out.push_str("</");
+        self.qualified_name(name);
+        self.out.push('>');
+    }
+
+    fn empty_tag(&mut self, name: &str, attributes: &str) {
+        self.out.push('<');
+        self.qualified_name(name);
+        self.out.push_str(attributes);
+        self.out.push_str("/>");
+    }
+
+    fn qualified_name(&mut self, name: &str) {
+        if let Some(prefix) = &self.options.prefix {
+            self.out.push_str(prefix);
+            self.out.push(':');
+        }
+        self.out.push_str(name);
+    }
+
+    // Starts a new line at the given nesting level when pretty printing.
+    fn newline(&mut self, depth: usize) {
+        if let Some(indent) = self.options.indent {
+            if !self.out.is_empty() {
+                self.out.push('\n');
+            }
+            self.out.push_str(&" ".repeat(indent * depth));
+        }
+    }
+
+    // Writes the attributes every element may carry, after the namespaces
+    // declared on the element. The prefix of the elements themselves is
+    // never declared again.
+    fn attribute_list(&self, attributes: &Attributes) -> String {
+        let mut list = String::new();
+        for (prefix, namespace) in &attributes.namespaces {
+            if self.options.prefix.as_ref() != Some(prefix) {
+                list.push_str(&attribute(&format!("xmlns:{}", prefix), namespace));
+            }
+        }
+        let typed = [
+            ("id", &attributes.id),
+            ("class", &attributes.class),
+            ("style", &attributes.style),
+        ];
+        for (name, value) in typed.iter() {
+            if let Some(value) = value {
+                list.push_str(&attribute(name, value));
+            }
+        }
+        if let Some(units) = &attributes.units {
+            list.push_str(&attribute(&units.name, &units.value));
+        }
+        for (name, value) in &attributes.others {
+            list.push_str(&attribute(name, value));
+        }
+        list
+    }
+}
+
+// Escapes text and attribute values.
+fn escape_str(text: &str) -> String {
+    String::from_utf8_lossy(&escape(text.as_bytes())).into_owned()
+}
+
+fn attribute(name: &str, value: &str) -> String {
+    format!(" {}=\"{}\"", name, escape_str(value))
+}
+
+// The prefixes of units, with their namespaces, that neither the element
+// nor any of its ancestors declare. Prefixes the parser could not resolve
+// are left alone.
+fn undeclared_prefixes(tree: &MathTree) -> Vec<(String, String)> {
+    let declares = |attributes: &Attributes, prefix: &str| {
+        attributes.namespaces.iter().any(|(p, _)| p == prefix)
+    };
+    let mut undeclared: Vec<(String, String)> = Vec::new();
+    for (idx, node) in tree.nodes().iter().enumerate() {
+        let annotations = match node {
+            MathNode::Semantics(semantics) => &semantics.annotations[..],
+            _ => &[],
+        };
+        let own = std::iter::once(node.attributes())
+            .chain(annotations.iter().map(|annotation| &annotation.attributes));
+        for attributes in own {
+            let units = match &attributes.units {
+                Some(units) => units,
+                None => continue,
+            };
+            let (prefix, namespace) = match (units.prefix(), &units.namespace) {
+                (Some(prefix), Some(namespace)) => (prefix, namespace),
+                _ => continue,
+            };
+            let mut ancestor = Some(idx);
+            let mut declared = declares(attributes, prefix);
+            while let (false, Some(current)) = (declared, ancestor) {
+                declared = declares(tree.nodes()[current].attributes(), prefix);
+                ancestor = tree.nodes()[current].parent();
+            }
+            if !declared && !undeclared.iter().any(|(p, _)| p == prefix) {
+                undeclared.push((prefix.to_string(), namespace.clone()));
+            }
+        }
+    }
+    undeclared
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::convert::TryFrom;
+    #[test]
+    fn write_round_trip() {
+        let math = "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">
+                <lambda>
+                    <bvar><ci>x</ci></bvar>
+                    <piecewise>
+                        <piece>
+                            <apply><times/><cn type=\"e-notation\">1.5<sep/>3</cn><ci>x</ci></apply>
+                            <apply><and/><apply><gt/><ci>x</ci><cn>0.25</cn></apply><true/></apply>
+                        </piece>
+                        <otherwise><cn type=\"rational\">-1<sep/>2</cn></otherwise>
+                    </piecewise>
+                </lambda>
+            </math>";
+        let tree = parse_str(math).unwrap();
+        let expr = Expr::try_from(&tree).unwrap();
+
+        for options in [WriteOptions::default(), WriteOptions::compact()] {
+            let written = write_mathml(&tree, &options);
+            let reparsed = parse_str(&written).unwrap();
+            assert_eq!(Expr::try_from(&reparsed).unwrap(), expr, "{}", written);
+        }
+
+        let tree = MathTree::from(Expr::apply(
+            Op::Plus,
+            vec![Expr::ci("a<b"), Expr::integer(2)],
+        ));
+        assert_eq!(
+            write_mathml(&tree, &WriteOptions::compact().with_prefix("m")),
+            "<m:math xmlns:m=\"http://www.w3.org/1998/Math/MathML\">\
+             <m:apply><m:plus/><m:ci>a&lt;b</m:ci><m:cn type=\"integer\">2</m:cn></m:apply>\
+             </m:math>"
+        );
+        assert_eq!(
+            to_mathml(&tree),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">
+  <apply>
+    <plus/>
+    <ci>a&lt;b</ci>
+    <cn type=\"integer\">2</cn>
+  </apply>
+</math>
+"
+        );
+    }
+}
@@ -1,36 +1,71 @@
+use super::attributes::Attributes;
 use super::lambda::Lambda;
 use super::math_node::{MathNode, MathNodeType, NodeIndex};
 use super::op::Op;
 use std::fmt;
 
+/// Application of an operator to its operands.
+///
+/// The children come in document order: the operator, any bound variables,
+/// any qualifiers and then the operands.
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct Apply {
     pub children: Vec<NodeIndex>,
     pub operator: Option<NodeIndex>,
+    pub bvars: Vec<NodeIndex>,
+    pub qualifiers: Vec<NodeIndex>,
     pub operands: Vec<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl Apply {
-    pub fn index(&mut self, _tag_type: MathNodeType, location: NodeIndex) {
-        if self.children.len() == 1 {
-            self.operator = Some(location);
-        } else {
-            self.operands.push(location);
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        location: NodeIndex,
+    ) -> Result<(), &'static str> {
+        if self.operator.is_none() {
+            return match tag_type {
+                MathNodeType::BVar | MathNodeType::Qualifier => {
+                    Err("An apply must start with its operator!")
+                }
+                _ => {
+                    self.operator = Some(location);
+                    Ok(())
+                }
+            };
+        }
+        match tag_type {
+            MathNodeType::BVar if self.qualifiers.is_empty() && self.operands.is_empty() => {
+                self.bvars.push(location);
+                Ok(())
+            }
+            MathNodeType::Qualifier if self.operands.is_empty() => {
+                self.qualifiers.push(location);
+                Ok(())
+            }
+            MathNodeType::BVar | MathNodeType::Qualifier => {
+                Err("Bound variables and qualifiers must precede the operands!")
+            }
+            _ => {
+                self.operands.push(location);
+                Ok(())
+            }
         }
     }
 
     pub fn get_op(&self, nodes: &[MathNode]) -> Result<Op, &'static str> {
-        let operator_idx = self.operator.expect("No operator found!");
-        if let MathNode::Op(opnode) = &nodes[operator_idx] {
-            return Ok(opnode.op.clone().unwrap());
+        let operator_idx = self.operator.ok_or("No operator found!")?;
+        if let Some(MathNode::Op(opnode)) = nodes.get(operator_idx) {
+            return opnode.op.clone().ok_or("Operator node is empty.");
         }
         Err("Not a regular mathematical operator.")
     }
 
     pub fn get_lambda(&self, nodes: &[MathNode]) -> Result<Lambda, &'static str> {
-        let operator_idx = self.operator.expect("No operator found!");
-        if let MathNode::Lambda(lambda) = &nodes[operator_idx] {
+        let operator_idx = self.operator.ok_or("No operator found!")?;
+        if let Some(MathNode::Lambda(lambda)) = nodes.get(operator_idx) {
             return Ok(lambda.clone());
         }
         Err("Not a lambda function.")
@@ -41,8 +76,8 @@ impl fmt::Display for Apply {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Operator: {:?}, Operands: {:?}, Children: {:?}, Parent: {:?}",
-            self.operator, self.operands, self.children, self.parent
+            "Operator: {:?}, BVars: {:?}, Qualifiers: {:?}, Operands: {:?}, Children: {:?}, Parent: {:?}",
+            self.operator, self.bvars, self.qualifiers, self.operands, self.children, self.parent
         )
     }
 }
@@ -0,0 +1,107 @@
+use crate::methods::serialize::MATHML_NAMESPACE;
+use std::fmt;
+
+// The namespaces of every SBML Level 3 package and version start with this.
+const SBML_NAMESPACES: &str = "http://www.sbml.org/sbml/level3/";
+
+/// The attributes of an element that do not change its meaning as an
+/// expression, like `id` or SBML's `sbml:units`.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct Attributes {
+    pub id: Option<String>,
+    pub class: Option<String>,
+    pub style: Option<String>,
+    /// The units of a number, from SBML's `sbml:units` under whatever
+    /// prefix the document bound to an SBML namespace.
+    pub units: Option<QualifiedAttribute>,
+    /// Every other attribute, by its name as written, in document order.
+    pub others: Vec<(String, String)>,
+    /// The prefixes declared on the element, with their namespaces, which
+    /// prefixed attributes may need. Declarations of MathML's own namespace
+    /// and of the default namespace are not kept.
+    pub namespaces: Vec<(String, String)>,
+}
+
+/// A prefixed attribute with its name as written, like `sbml:units`, and
+/// the namespace its prefix was bound to, if the parser could resolve it.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct QualifiedAttribute {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub value: String,
+}
+
+impl QualifiedAttribute {
+    pub fn prefix(&self) -> Option<&str> {
+        self.name.split_once(':').map(|(prefix, _)| prefix)
+    }
+}
+
+impl Attributes {
+    /// Stores an attribute under its typed field or among the others.
+    ///
+    /// A prefixed `units` attribute is taken to be SBML's, as its namespace
+    /// is unknown; see [`Attributes::insert_namespaced`].
+    pub fn insert(&mut self, name: &str, value: String) {
+        self.insert_namespaced(name, None, value)
+    }
+
+    /// Stores an attribute whose prefix, if it has one, was bound to
+    /// `namespace`. Units from a namespace other than SBML's are kept among
+    /// the others.
+    pub fn insert_namespaced(&mut self, name: &str, namespace: Option<&str>, value: String) {
+        let local_name = name.rsplit(':').next().unwrap_or(name);
+        let sbml = namespace.map_or(true, |namespace| namespace.starts_with(SBML_NAMESPACES));
+        match name {
+            "xmlns" => {}
+            _ if name.starts_with("xmlns:") => {
+                if value != MATHML_NAMESPACE {
+                    self.namespaces.push((local_name.to_string(), value));
+                }
+            }
+            "id" => self.id = Some(value),
+            "class" => self.class = Some(value),
+            "style" => self.style = Some(value),
+            _ if local_name == "units" && name != local_name && sbml => {
+                self.units = Some(QualifiedAttribute {
+                    name: name.to_string(),
+                    namespace: namespace.map(String::from),
+                    value,
+                })
+            }
+            _ => self.others.push((name.to_string(), value)),
+        }
+    }
+
+    /// The value of the units attribute.
+    pub fn units(&self) -> Option<&str> {
+        self.units.as_ref().map(|units| units.value.as_str())
+    }
+
+    /// Looks up one of the other attributes by its name as written.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.others
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none()
+            && self.class.is_none()
+            && self.style.is_none()
+            && self.units.is_none()
+            && self.others.is_empty()
+            && self.namespaces.is_empty()
+    }
+}
+
+impl fmt::Display for Attributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "id: {:?}, class: {:?}, style: {:?}, units: {:?}, others: {:?}, namespaces: {:?}",
+            self.id, self.class, self.style, self.units, self.others, self.namespaces
+        )
+    }
+}
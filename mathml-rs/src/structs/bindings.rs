@@ -1,10 +1,29 @@
-use super::math_node::NodeIndex;
+use super::attributes::Attributes;
+use super::math_node::{MathNodeType, NodeIndex};
 use std::fmt;
 
 #[derive(Default, Debug, Clone)]
 pub struct BVar {
     pub children: Vec<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
+}
+
+impl BVar {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        _location: NodeIndex,
+    ) -> Result<(), &'static str> {
+        // the variable comes first and may be followed by its degree
+        match tag_type {
+            MathNodeType::Ci if self.children.len() == 1 => Ok(()),
+            MathNodeType::Ci => Err("A bvar can bind only one variable!"),
+            MathNodeType::Qualifier if self.children.len() == 2 => Ok(()),
+            MathNodeType::Qualifier => Err("A bvar can have one degree after its variable!"),
+            _ => Err("Can't have this element in a bvar!"),
+        }
+    }
 }
 
 impl fmt::Display for BVar {
@@ -1,3 +1,4 @@
+use super::attributes::Attributes;
 use super::math_node::NodeIndex;
 use std::fmt;
 
@@ -5,6 +6,7 @@ use std::fmt;
 pub struct Ci {
     pub name: Option<String>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl Ci {
@@ -12,6 +14,7 @@ impl Ci {
         Ci {
             name: Some(s),
             parent: None,
+            attributes: Default::default(),
         }
     }
 }
@@ -1,3 +1,4 @@
+use super::attributes::Attributes;
 use super::math_node::NodeIndex;
 use super::numbers::{NumType, Number};
 use std::fmt;
@@ -5,8 +6,12 @@ use std::fmt;
 #[derive(Default, Debug, Clone)]
 pub struct Cn {
     pub r#type: Option<NumType>,
+    /// The radix the number was written in. The value itself is always
+    /// stored, and written back, in base 10.
+    pub base: Option<u32>,
     pub value: Option<Number>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl fmt::Display for Cn {
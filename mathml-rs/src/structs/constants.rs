@@ -1,3 +1,4 @@
+use super::attributes::Attributes;
 use super::math_node::NodeIndex;
 use std::fmt;
 
@@ -5,6 +6,7 @@ use std::fmt;
 pub struct ConstantNode {
     pub constant: Option<Constant>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl fmt::Display for ConstantNode {
@@ -26,4 +28,27 @@ pub enum Constant {
     ExponentialE,
     EulerGamma,
     Infinity,
+    NotANumber,
+}
+
+impl Constant {
+    /// The name of the MathML element for this constant.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Constant::ImaginaryI => "imaginaryi",
+            Constant::True => "true",
+            Constant::False => "false",
+            Constant::Pi => "pi",
+            Constant::ExponentialE => "exponentiale",
+            Constant::EulerGamma => "eulergamma",
+            Constant::Infinity => "infinity",
+            Constant::NotANumber => "notanumber",
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
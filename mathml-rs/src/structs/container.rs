@@ -0,0 +1,134 @@
+use super::attributes::Attributes;
+use super::math_node::{MathNodeType, NodeIndex};
+use std::fmt;
+use std::str::FromStr;
+
+/// A container node, which groups its children into a compound value like
+/// a vector or a set.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct ContainerNode {
+    pub container: Option<Container>,
+    /// Which ends of an interval belong to it. Only intervals have one, and
+    /// `None` means closed.
+    pub closure: Option<Closure>,
+    pub children: Vec<NodeIndex>,
+    pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
+}
+
+impl ContainerNode {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        _location: NodeIndex,
+    ) -> Result<(), &'static str> {
+        match (&self.container, tag_type) {
+            (Some(Container::Matrix), MathNodeType::Container) => Ok(()),
+            (Some(Container::Matrix), _) => Err("A matrix can only hold matrix rows!"),
+            (Some(Container::Interval), _) if self.children.len() > 2 => {
+                Err("An interval has only two bounds!")
+            }
+            (
+                _,
+                MathNodeType::Apply
+                | MathNodeType::Ci
+                | MathNodeType::Csymbol
+                | MathNodeType::Cn
+                | MathNodeType::Constant
+                | MathNodeType::Lambda
+                | MathNodeType::Piecewise
+                | MathNodeType::Semantics
+                | MathNodeType::Container,
+            ) => Ok(()),
+            (
+                _,
+                MathNodeType::Root
+                | MathNodeType::Op
+                | MathNodeType::BVar
+                | MathNodeType::Piece
+                | MathNodeType::Otherwise
+                | MathNodeType::Qualifier,
+            ) => Err("Can't have this element in a container!"),
+        }
+    }
+}
+
+impl fmt::Display for ContainerNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "container: {:?}, closure: {:?}, children: {:?}, parent: {:?}",
+            self.container, self.closure, self.children, self.parent
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Container {
+    Vector,
+    Matrix,
+    MatrixRow,
+    List,
+    Set,
+    Interval,
+}
+
+impl Container {
+    /// The name of the MathML element for this container.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Container::Vector => "vector",
+            Container::Matrix => "matrix",
+            Container::MatrixRow => "matrixrow",
+            Container::List => "list",
+            Container::Set => "set",
+            Container::Interval => "interval",
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The `closure` of an interval.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Closure {
+    Open,
+    Closed,
+    OpenClosed,
+    ClosedOpen,
+}
+
+impl Closure {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Closure::Open => "open",
+            Closure::Closed => "closed",
+            Closure::OpenClosed => "open-closed",
+            Closure::ClosedOpen => "closed-open",
+        }
+    }
+}
+
+impl FromStr for Closure {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "open" => Ok(Closure::Open),
+            "closed" => Ok(Closure::Closed),
+            "open-closed" => Ok(Closure::OpenClosed),
+            "closed-open" => Ok(Closure::ClosedOpen),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
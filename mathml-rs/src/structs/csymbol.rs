@@ -0,0 +1,97 @@
+use super::attributes::Attributes;
+use super::math_node::NodeIndex;
+use std::fmt;
+
+/// Avogadro's number as fixed by the 2019 SI, the value of SBML's
+/// `avogadro` csymbol.
+pub const AVOGADRO: f64 = 6.02214076e23;
+
+/// A symbol defined outside of MathML, identified by its `definitionURL`.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct Csymbol {
+    pub definition_url: Option<String>,
+    pub encoding: Option<String>,
+    pub text: Option<String>,
+    pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
+}
+
+impl Csymbol {
+    /// A csymbol for one of the SBML symbols, written the way SBML tools
+    /// write them.
+    pub fn sbml(symbol: SbmlSymbol) -> Self {
+        Csymbol {
+            definition_url: Some(symbol.url().to_string()),
+            encoding: Some("text".to_string()),
+            text: Some(symbol.name().to_string()),
+            parent: None,
+            attributes: Default::default(),
+        }
+    }
+
+    /// The SBML symbol this csymbol refers to, if any.
+    pub fn sbml_symbol(&self) -> Option<SbmlSymbol> {
+        self.definition_url
+            .as_deref()
+            .and_then(SbmlSymbol::from_url)
+    }
+}
+
+impl fmt::Display for Csymbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "definitionURL: {:?}, encoding: {:?}, text: {:?}, parent: {:?}",
+            self.definition_url, self.encoding, self.text, self.parent
+        )
+    }
+}
+
+/// The csymbols defined by SBML Level 3.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SbmlSymbol {
+    Time,
+    Delay,
+    Avogadro,
+    RateOf,
+}
+
+impl SbmlSymbol {
+    /// The `definitionURL` that identifies this symbol.
+    pub fn url(&self) -> &'static str {
+        match self {
+            SbmlSymbol::Time => "http://www.sbml.org/sbml/symbols/time",
+            SbmlSymbol::Delay => "http://www.sbml.org/sbml/symbols/delay",
+            SbmlSymbol::Avogadro => "http://www.sbml.org/sbml/symbols/avogadro",
+            SbmlSymbol::RateOf => "http://www.sbml.org/sbml/symbols/rateOf",
+        }
+    }
+
+    pub fn from_url(url: &str) -> Option<Self> {
+        [
+            SbmlSymbol::Time,
+            SbmlSymbol::Delay,
+            SbmlSymbol::Avogadro,
+            SbmlSymbol::RateOf,
+        ]
+        .iter()
+        .copied()
+        .find(|symbol| symbol.url() == url.trim())
+    }
+
+    /// The name of the symbol in SBML's infix syntax.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SbmlSymbol::Time => "time",
+            SbmlSymbol::Delay => "delay",
+            SbmlSymbol::Avogadro => "avogadro",
+            SbmlSymbol::RateOf => "rateOf",
+        }
+    }
+}
+
+impl fmt::Display for SbmlSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,467 @@
+use super::math_node::NodeIndex;
+use super::op::Op;
+use std::error::Error;
+use std::fmt;
+
+/// Where in the input a parse error occurred.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Position {
+    /// Byte offset of the offending markup from the start of the input.
+    pub offset: usize,
+    /// 1-based line number, known only when the parser had the source text.
+    pub line: Option<usize>,
+    /// 1-based column in bytes, known only when the parser had the source text.
+    pub column: Option<usize>,
+}
+
+impl Position {
+    pub fn new(offset: usize) -> Self {
+        Position {
+            offset,
+            line: None,
+            column: None,
+        }
+    }
+
+    /// Fills in the line and column from the text the offset refers to.
+    ///
+    /// The XML reader reports positions before any whitespace that precedes
+    /// a tag, so that whitespace is skipped to point at the markup itself.
+    pub fn locate(&mut self, source: &[u8]) {
+        let mut offset = self.offset.min(source.len());
+        while offset < source.len() && source[offset].is_ascii_whitespace() {
+            offset += 1;
+        }
+        let preceding = &source[..offset];
+        let line_start = preceding
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+
+        self.offset = offset;
+        self.line = Some(preceding.iter().filter(|&&b| b == b'\n').count() + 1);
+        self.column = Some(offset - line_start + 1);
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}", line, column),
+            _ => write!(f, "byte {}", self.offset),
+        }
+    }
+}
+
+/// Errors produced while parsing MathML.
+#[derive(Debug)]
+pub enum ParseError {
+    /// An element the parser does not know about.
+    UnknownElement { name: String, position: Position },
+    /// A MathML element the parser knows about but does not accept, with a
+    /// hint at what to write instead.
+    UnsupportedElement {
+        name: String,
+        hint: &'static str,
+        position: Position,
+    },
+    /// An element from a namespace other than MathML.
+    ForeignElement {
+        name: String,
+        namespace: String,
+        position: Position,
+    },
+    /// A known element in a place where it is not allowed.
+    UnexpectedChild {
+        parent: String,
+        child: String,
+        position: Position,
+    },
+    /// A closing tag that does not match the element being parsed.
+    UnexpectedEnd { name: String, position: Position },
+    /// An element closed without a child or content it requires.
+    IncompleteElement {
+        element: String,
+        missing: &'static str,
+        position: Position,
+    },
+    /// Text content inside an element that does not take any.
+    UnexpectedText {
+        parent: String,
+        text: String,
+        position: Position,
+    },
+    /// The content of a `cn` element is not a valid number of its type.
+    InvalidNumber { text: String, position: Position },
+    /// A supported attribute with a value that could not be parsed.
+    InvalidAttribute {
+        element: String,
+        name: String,
+        value: String,
+        position: Position,
+    },
+    /// The underlying XML is malformed.
+    XmlError {
+        error: quick_xml::Error,
+        position: Position,
+    },
+    /// The input ended before the `math` element was closed.
+    UnexpectedEof { position: Position },
+}
+
+impl ParseError {
+    pub fn position(&self) -> Position {
+        match self {
+            ParseError::UnknownElement { position, .. }
+            | ParseError::UnsupportedElement { position, .. }
+            | ParseError::ForeignElement { position, .. }
+            | ParseError::UnexpectedChild { position, .. }
+            | ParseError::UnexpectedEnd { position, .. }
+            | ParseError::IncompleteElement { position, .. }
+            | ParseError::UnexpectedText { position, .. }
+            | ParseError::InvalidNumber { position, .. }
+            | ParseError::InvalidAttribute { position, .. }
+            | ParseError::XmlError { position, .. }
+            | ParseError::UnexpectedEof { position } => *position,
+        }
+    }
+
+    /// Resolves line and column of the error against the source text.
+    pub fn locate(mut self, source: &[u8]) -> Self {
+        match &mut self {
+            ParseError::UnknownElement { position, .. }
+            | ParseError::UnsupportedElement { position, .. }
+            | ParseError::ForeignElement { position, .. }
+            | ParseError::UnexpectedChild { position, .. }
+            | ParseError::UnexpectedEnd { position, .. }
+            | ParseError::IncompleteElement { position, .. }
+            | ParseError::UnexpectedText { position, .. }
+            | ParseError::InvalidNumber { position, .. }
+            | ParseError::InvalidAttribute { position, .. }
+            | ParseError::XmlError { position, .. }
+            | ParseError::UnexpectedEof { position } => position.locate(source),
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownElement { name, position } => {
+                write!(f, "unknown element <{}> at {}", name, position)
+            }
+            ParseError::UnsupportedElement {
+                name,
+                hint,
+                position,
+            } => write!(
+                f,
+                "unsupported element <{}> at {}: {}",
+                name, position, hint
+            ),
+            ParseError::ForeignElement {
+                name,
+                namespace,
+                position,
+            } => write!(
+                f,
+                "element <{}> from foreign namespace {:?} at {}",
+                name, namespace, position
+            ),
+            ParseError::UnexpectedChild {
+                parent,
+                child,
+                position,
+            } => write!(f, "unexpected <{}> in {} at {}", child, parent, position),
+            ParseError::UnexpectedEnd { name, position } => {
+                write!(f, "unexpected </{}> at {}", name, position)
+            }
+            ParseError::IncompleteElement {
+                element,
+                missing,
+                position,
+            } => write!(f, "<{}> without {} at {}", element, missing, position),
+            ParseError::UnexpectedText {
+                parent,
+                text,
+                position,
+            } => write!(
+                f,
+                "unexpected text {:?} in {} at {}",
+                text, parent, position
+            ),
+            ParseError::InvalidNumber { text, position } => {
+                write!(f, "invalid number {:?} at {}", text, position)
+            }
+            ParseError::InvalidAttribute {
+                element,
+                name,
+                value,
+                position,
+            } => write!(
+                f,
+                "invalid value {:?} for attribute {:?} on <{}> at {}",
+                value, name, element, position
+            ),
+            ParseError::XmlError { error, position } => {
+                write!(f, "malformed XML at {}: {}", position, error)
+            }
+            ParseError::UnexpectedEof { position } => {
+                write!(f, "unexpected end of input at {}", position)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::XmlError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// A range of bytes in a text formula.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Self {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Syntax errors in a text formula.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FormulaError {
+    /// A character that does not start any token.
+    UnexpectedCharacter { character: char, span: Span },
+    /// A token that cannot appear at this point of the formula.
+    UnexpectedToken {
+        found: String,
+        expected: &'static str,
+        span: Span,
+    },
+    /// The formula ended in the middle of an expression.
+    UnexpectedEnd { expected: &'static str, span: Span },
+    /// A numeric literal that does not fit its type.
+    InvalidNumber { text: String, span: Span },
+    /// A built-in function called with the wrong number of arguments.
+    ArgumentCount {
+        name: String,
+        expected: &'static str,
+        got: usize,
+        span: Span,
+    },
+}
+
+impl FormulaError {
+    pub fn span(&self) -> Span {
+        match self {
+            FormulaError::UnexpectedCharacter { span, .. }
+            | FormulaError::UnexpectedToken { span, .. }
+            | FormulaError::UnexpectedEnd { span, .. }
+            | FormulaError::InvalidNumber { span, .. }
+            | FormulaError::ArgumentCount { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormulaError::UnexpectedCharacter { character, span } => {
+                write!(f, "unexpected character {:?} at {}", character, span)
+            }
+            FormulaError::UnexpectedToken {
+                found,
+                expected,
+                span,
+            } => write!(f, "expected {} but found {:?} at {}", expected, found, span),
+            FormulaError::UnexpectedEnd { expected, span } => {
+                write!(f, "expected {} at end of formula ({})", expected, span)
+            }
+            FormulaError::InvalidNumber { text, span } => {
+                write!(f, "invalid number {:?} at {}", text, span)
+            }
+            FormulaError::ArgumentCount {
+                name,
+                expected,
+                got,
+                span,
+            } => write!(
+                f,
+                "{} takes {} arguments but got {} at {}",
+                name, expected, got, span
+            ),
+        }
+    }
+}
+
+impl Error for FormulaError {}
+
+/// Errors produced while evaluating a parsed expression.
+///
+/// Every variant records the index of the node at which evaluation failed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EvalError {
+    /// A `ci` with no value bound to its name.
+    UnboundVariable { name: String, node: NodeIndex },
+    /// A call to a function with no definition.
+    UnknownFunction { name: String, node: NodeIndex },
+    /// An operator applied to the wrong number of operands.
+    ArityMismatch {
+        op: Op,
+        expected: usize,
+        got: usize,
+        node: NodeIndex,
+    },
+    /// A function called with the wrong number of arguments.
+    ArgumentMismatch {
+        name: String,
+        expected: usize,
+        got: usize,
+        node: NodeIndex,
+    },
+    /// An operator the evaluator does not implement.
+    UnsupportedOperator { op: Op, node: NodeIndex },
+    /// A value of the wrong kind, e.g. a number where a boolean was expected.
+    TypeMismatch {
+        expected: &'static str,
+        node: NodeIndex,
+    },
+    /// No piece of a piecewise expression held and there is no otherwise.
+    NoPieceMatched { node: NodeIndex },
+    /// An operand outside the domain of its operator.
+    DomainError {
+        op: Op,
+        reason: &'static str,
+        node: NodeIndex,
+    },
+    /// Arrays whose shapes do not fit the operator, e.g. the sum of a
+    /// vector and a matrix.
+    ShapeMismatch { op: Op, node: NodeIndex },
+    /// A node that is missing a required child or cannot be evaluated.
+    MalformedNode {
+        reason: &'static str,
+        node: NodeIndex,
+    },
+    /// A csymbol whose value was not supplied by the simulation state.
+    UnavailableSymbol { name: String, node: NodeIndex },
+}
+
+impl EvalError {
+    pub fn node(&self) -> NodeIndex {
+        match self {
+            EvalError::UnboundVariable { node, .. }
+            | EvalError::UnknownFunction { node, .. }
+            | EvalError::ArityMismatch { node, .. }
+            | EvalError::ArgumentMismatch { node, .. }
+            | EvalError::UnsupportedOperator { node, .. }
+            | EvalError::TypeMismatch { node, .. }
+            | EvalError::NoPieceMatched { node }
+            | EvalError::DomainError { node, .. }
+            | EvalError::ShapeMismatch { node, .. }
+            | EvalError::MalformedNode { node, .. }
+            | EvalError::UnavailableSymbol { node, .. } => *node,
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::UnboundVariable { name, node } => {
+                write!(f, "no value for variable {} at node {}", name, node)
+            }
+            EvalError::UnknownFunction { name, node } => {
+                write!(f, "unknown function {} at node {}", name, node)
+            }
+            EvalError::ArityMismatch {
+                op,
+                expected,
+                got,
+                node,
+            } => write!(
+                f,
+                "{:?} expects {} operands but got {} at node {}",
+                op, expected, got, node
+            ),
+            EvalError::ArgumentMismatch {
+                name,
+                expected,
+                got,
+                node,
+            } => write!(
+                f,
+                "{} expects {} arguments but got {} at node {}",
+                name, expected, got, node
+            ),
+            EvalError::UnsupportedOperator { op, node } => {
+                write!(
+                    f,
+                    "evaluation of {:?} is not supported at node {}",
+                    op, node
+                )
+            }
+            EvalError::TypeMismatch { expected, node } => {
+                write!(f, "expected {} at node {}", expected, node)
+            }
+            EvalError::NoPieceMatched { node } => write!(
+                f,
+                "no piece matched and no otherwise branch found at node {}",
+                node
+            ),
+            EvalError::DomainError { op, reason, node } => {
+                write!(f, "{:?}: {} at node {}", op, reason, node)
+            }
+            EvalError::ShapeMismatch { op, node } => {
+                write!(
+                    f,
+                    "{:?}: operands of incompatible shapes at node {}",
+                    op, node
+                )
+            }
+            EvalError::MalformedNode { reason, node } => {
+                write!(f, "malformed node {}: {}", node, reason)
+            }
+            EvalError::UnavailableSymbol { name, node } => {
+                write!(f, "no value for csymbol {} at node {}", name, node)
+            }
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+/// A node arena that does not form a well-formed tree, or a node that is
+/// not an expression where one was needed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TreeError {
+    pub reason: &'static str,
+    pub node: NodeIndex,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at node {}", self.reason, self.node)
+    }
+}
+
+impl Error for TreeError {}
@@ -0,0 +1,822 @@
+use super::apply::Apply;
+use super::attributes::Attributes;
+use super::bindings::BVar;
+use super::ci::Ci;
+use super::cn::Cn;
+use super::constants::{Constant, ConstantNode};
+use super::container::{Closure, Container, ContainerNode};
+use super::csymbol::{Csymbol, SbmlSymbol};
+use super::error::TreeError;
+use super::lambda::Lambda;
+use super::math_node::{MathNode, NodeIndex};
+use super::math_tree::MathTree;
+use super::numbers::{NumType, Number};
+use super::op::{Op, OpNode};
+use super::piecewise::{Otherwise, Piece, Piecewise};
+use super::qualifier::{Qualifier, QualifierNode};
+use super::root::Root;
+use super::semantics::{Annotation, Semantics};
+use std::convert::TryFrom;
+
+/// An owned, recursive MathML expression.
+///
+/// This is an alternative to the flat [`MathTree`] arena that is easier to
+/// build and transform by hand. Converting a tree to an `Expr` and back
+/// gives the same nodes, in the same order, as `tree`. Every expression
+/// keeps its [`Attributes`], like the `sbml:units` of a number; those of
+/// `bvar`, `piece`, `otherwise` and qualifier elements are dropped. The
+/// attributes of the `math` element itself are kept by [`Math`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Application of an operator, a function name or a lambda to arguments.
+    ///
+    /// Bound variables and qualifiers refine the operator, e.g. the variable
+    /// and limits of an integral or the degree of a root.
+    Apply {
+        op: Box<Expr>,
+        bvars: Vec<BoundVariable>,
+        qualifiers: Vec<(Qualifier, Vec<Expr>)>,
+        args: Vec<Expr>,
+        attributes: Attributes,
+    },
+    /// A built-in operator, usually in the operator position of an apply.
+    Op { op: Op, attributes: Attributes },
+    Ci {
+        name: String,
+        attributes: Attributes,
+    },
+    /// A number, with the radix it was written in if that was not 10.
+    Cn {
+        r#type: Option<NumType>,
+        base: Option<u32>,
+        value: Number,
+        attributes: Attributes,
+    },
+    Constant {
+        constant: Constant,
+        attributes: Attributes,
+    },
+    /// A symbol defined outside of MathML, such as SBML's `time`.
+    Csymbol {
+        definition_url: Option<String>,
+        encoding: Option<String>,
+        text: Option<String>,
+        attributes: Attributes,
+    },
+    Lambda {
+        params: Vec<String>,
+        body: Box<Expr>,
+        attributes: Attributes,
+    },
+    /// Pairs of value and condition, and the optional otherwise value.
+    Piecewise {
+        pieces: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+        attributes: Attributes,
+    },
+    /// A vector, matrix, matrix row, list, set or interval of expressions.
+    ///
+    /// Only intervals have a closure, where `None` means closed.
+    Container {
+        container: Container,
+        closure: Option<Closure>,
+        items: Vec<Expr>,
+        attributes: Attributes,
+    },
+    /// An expression with its annotations, which evaluate and print as the
+    /// expression alone.
+    Semantics {
+        expr: Box<Expr>,
+        annotations: Vec<Annotation>,
+        attributes: Attributes,
+    },
+}
+
+/// A whole `math` element: the top-level expression and the attributes of
+/// the element itself, such as its namespace declarations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Math {
+    pub expr: Expr,
+    pub attributes: Attributes,
+}
+
+/// A variable bound by an apply, with its degree if it has one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundVariable {
+    pub name: String,
+    pub degree: Option<Expr>,
+}
+
+impl Expr {
+    /// Applies a built-in operator to the given arguments.
+    pub fn apply(op: Op, args: Vec<Expr>) -> Self {
+        Expr::Apply {
+            op: Box::new(Expr::op(op)),
+            bvars: Vec::new(),
+            qualifiers: Vec::new(),
+            args,
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// Applies a built-in operator with a single-valued qualifier, like the
+    /// degree of a root or the base of a logarithm.
+    pub fn qualified(op: Op, qualifier: Qualifier, value: Expr, args: Vec<Expr>) -> Self {
+        Expr::Apply {
+            op: Box::new(Expr::op(op)),
+            bvars: Vec::new(),
+            qualifiers: vec![(qualifier, vec![value])],
+            args,
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// Calls a function by name with the given arguments.
+    pub fn call(name: &str, args: Vec<Expr>) -> Self {
+        Expr::Apply {
+            op: Box::new(Expr::ci(name)),
+            bvars: Vec::new(),
+            qualifiers: Vec::new(),
+            args,
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// One of the SBML csymbols, like `time` or `avogadro`.
+    pub fn sbml(symbol: SbmlSymbol) -> Self {
+        Expr::Csymbol {
+            definition_url: Some(symbol.url().to_string()),
+            encoding: Some("text".to_string()),
+            text: Some(symbol.name().to_string()),
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// Applies one of the SBML csymbol functions, `delay` or `rateOf`.
+    pub fn sbml_call(symbol: SbmlSymbol, args: Vec<Expr>) -> Self {
+        Expr::Apply {
+            op: Box::new(Expr::sbml(symbol)),
+            bvars: Vec::new(),
+            qualifiers: Vec::new(),
+            args,
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// The SBML symbol this expression refers to, if it is a csymbol for one.
+    pub fn sbml_symbol(&self) -> Option<SbmlSymbol> {
+        match self {
+            Expr::Csymbol { definition_url, .. } => {
+                definition_url.as_deref().and_then(SbmlSymbol::from_url)
+            }
+            _ => None,
+        }
+    }
+
+    /// The expressions of the first qualifier of the given kind on an apply.
+    pub fn qualifier(&self, kind: &Qualifier) -> Option<&[Expr]> {
+        match self {
+            Expr::Apply { qualifiers, .. } => qualifiers
+                .iter()
+                .find(|(qualifier, _)| qualifier == kind)
+                .map(|(_, values)| &values[..]),
+            _ => None,
+        }
+    }
+
+    /// A container of the given kind, e.g. a vector or a set.
+    pub fn container(container: Container, items: Vec<Expr>) -> Self {
+        Expr::Container {
+            container,
+            closure: None,
+            items,
+            attributes: Attributes::default(),
+        }
+    }
+
+    pub fn op(op: Op) -> Self {
+        Expr::Op {
+            op,
+            attributes: Attributes::default(),
+        }
+    }
+
+    pub fn constant(constant: Constant) -> Self {
+        Expr::Constant {
+            constant,
+            attributes: Attributes::default(),
+        }
+    }
+
+    pub fn ci(name: &str) -> Self {
+        Expr::Ci {
+            name: name.to_string(),
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// A number of the given type, written in base 10.
+    pub fn number(r#type: Option<NumType>, value: Number) -> Self {
+        Expr::Cn {
+            r#type,
+            base: None,
+            value,
+            attributes: Attributes::default(),
+        }
+    }
+
+    pub fn real(value: f64) -> Self {
+        Expr::number(None, Number::Real(value))
+    }
+
+    pub fn integer(value: i64) -> Self {
+        Expr::number(Some(NumType::Integer), Number::Integer(value))
+    }
+
+    pub fn attributes(&self) -> &Attributes {
+        match self {
+            Expr::Apply { attributes, .. }
+            | Expr::Op { attributes, .. }
+            | Expr::Ci { attributes, .. }
+            | Expr::Cn { attributes, .. }
+            | Expr::Constant { attributes, .. }
+            | Expr::Csymbol { attributes, .. }
+            | Expr::Lambda { attributes, .. }
+            | Expr::Piecewise { attributes, .. }
+            | Expr::Container { attributes, .. }
+            | Expr::Semantics { attributes, .. } => attributes,
+        }
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut Attributes {
+        match self {
+            Expr::Apply { attributes, .. }
+            | Expr::Op { attributes, .. }
+            | Expr::Ci { attributes, .. }
+            | Expr::Cn { attributes, .. }
+            | Expr::Constant { attributes, .. }
+            | Expr::Csymbol { attributes, .. }
+            | Expr::Lambda { attributes, .. }
+            | Expr::Piecewise { attributes, .. }
+            | Expr::Container { attributes, .. }
+            | Expr::Semantics { attributes, .. } => attributes,
+        }
+    }
+
+    /// Converts the subtree of `tree` rooted at `idx`.
+    ///
+    /// Fails if `idx` does not refer to an expression, e.g. if it is out of
+    /// range or points at a `bvar` or `piece`, or if an element in the
+    /// subtree is missing a part, which is never the case for trees from
+    /// the parser.
+    pub fn from_node(tree: &MathTree, idx: NodeIndex) -> Result<Self, TreeError> {
+        let incomplete = || TreeError {
+            reason: "incomplete element",
+            node: idx,
+        };
+        let node = tree.get(idx).ok_or(TreeError {
+            reason: "node out of range",
+            node: idx,
+        })?;
+        let attributes = node.attributes().clone();
+        let expr = match node {
+            MathNode::Apply(apply) => Expr::Apply {
+                op: Box::new(Expr::from_node(
+                    tree,
+                    apply.operator.ok_or_else(incomplete)?,
+                )?),
+                bvars: apply
+                    .bvars
+                    .iter()
+                    .map(|&bvar| {
+                        let ci = *tree.children(bvar).first().ok_or_else(incomplete)?;
+                        Ok(BoundVariable {
+                            name: tree.ci_name(ci).ok_or_else(incomplete)?.to_string(),
+                            degree: match tree.qualifier_value_of(bvar, &Qualifier::Degree) {
+                                Some(degree) => Some(Expr::from_node(tree, degree)?),
+                                None => None,
+                            },
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                qualifiers: apply
+                    .qualifiers
+                    .iter()
+                    .map(|&qualifier| {
+                        Ok((
+                            tree.qualifier(qualifier).ok_or_else(incomplete)?.clone(),
+                            tree.children(qualifier)
+                                .iter()
+                                .map(|&child| Expr::from_node(tree, child))
+                                .collect::<Result<_, _>>()?,
+                        ))
+                    })
+                    .collect::<Result<_, _>>()?,
+                args: apply
+                    .operands
+                    .iter()
+                    .map(|&operand| Expr::from_node(tree, operand))
+                    .collect::<Result<_, _>>()?,
+                attributes,
+            },
+            MathNode::Op(opnode) => Expr::Op {
+                op: opnode.op.clone().ok_or_else(incomplete)?,
+                attributes,
+            },
+            MathNode::Constant(constantnode) => Expr::Constant {
+                constant: constantnode.constant.clone().ok_or_else(incomplete)?,
+                attributes,
+            },
+            MathNode::Ci(ci) => Expr::Ci {
+                name: ci.name.clone().ok_or_else(incomplete)?,
+                attributes,
+            },
+            MathNode::Csymbol(csymbol) => Expr::Csymbol {
+                definition_url: csymbol.definition_url.clone(),
+                encoding: csymbol.encoding.clone(),
+                text: csymbol.text.clone(),
+                attributes,
+            },
+            MathNode::Cn(cn) => Expr::Cn {
+                r#type: cn.r#type.clone(),
+                base: cn.base,
+                value: cn.value.clone().ok_or_else(incomplete)?,
+                attributes,
+            },
+            MathNode::Lambda(..) => Expr::Lambda {
+                params: tree
+                    .parameters_of(idx)
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                body: Box::new(Expr::from_node(
+                    tree,
+                    tree.body_of(idx).ok_or_else(incomplete)?,
+                )?),
+                attributes,
+            },
+            MathNode::Piecewise(..) => Expr::Piecewise {
+                pieces: tree
+                    .pieces_of(idx)
+                    .iter()
+                    .map(|&piece| {
+                        let (value, condition) = tree.piece_parts(piece).ok_or_else(incomplete)?;
+                        Ok((
+                            Expr::from_node(tree, value)?,
+                            Expr::from_node(tree, condition)?,
+                        ))
+                    })
+                    .collect::<Result<_, _>>()?,
+                otherwise: match tree.otherwise_of(idx) {
+                    Some(otherwise) => Some(Box::new(Expr::from_node(tree, otherwise)?)),
+                    None => None,
+                },
+                attributes,
+            },
+            MathNode::Container(containernode) => Expr::Container {
+                container: containernode.container.clone().ok_or_else(incomplete)?,
+                closure: containernode.closure,
+                items: containernode
+                    .children
+                    .iter()
+                    .map(|&child| Expr::from_node(tree, child))
+                    .collect::<Result<_, _>>()?,
+                attributes,
+            },
+            MathNode::Semantics(semantics) => Expr::Semantics {
+                expr: Box::new(Expr::from_node(
+                    tree,
+                    semantics.expr.ok_or_else(incomplete)?,
+                )?),
+                annotations: semantics.annotations.clone(),
+                attributes,
+            },
+            MathNode::Root(..)
+            | MathNode::BVar(..)
+            | MathNode::Piece(..)
+            | MathNode::Otherwise(..)
+            | MathNode::Qualifier(..) => {
+                return Err(TreeError {
+                    reason: "not an expression",
+                    node: idx,
+                })
+            }
+        };
+        Ok(expr)
+    }
+}
+
+/// Converts the top-level expression of a tree.
+///
+/// Fails if the tree has no expression or is not well-formed, which is
+/// never the case for trees from the parser.
+impl TryFrom<&MathTree> for Expr {
+    type Error = TreeError;
+
+    fn try_from(tree: &MathTree) -> Result<Self, Self::Error> {
+        let expr = tree.expr().ok_or(TreeError {
+            reason: "no expression",
+            node: tree.root(),
+        })?;
+        Expr::from_node(tree, expr)
+    }
+}
+
+impl TryFrom<&MathTree> for Math {
+    type Error = TreeError;
+
+    fn try_from(tree: &MathTree) -> Result<Self, Self::Error> {
+        Ok(Math {
+            expr: Expr::try_from(tree)?,
+            attributes: tree.nodes()[tree.root()].attributes().clone(),
+        })
+    }
+}
+
+impl From<&Math> for MathTree {
+    fn from(math: &Math) -> Self {
+        let mut nodes = vec![MathNode::Root(Root {
+            attributes: math.attributes.clone(),
+            ..Default::default()
+        })];
+        let child = push_expr(&mut nodes, &math.expr, 0);
+        if let MathNode::Root(root) = &mut nodes[0] {
+            root.children.push(child);
+        }
+        MathTree::from_nodes(nodes)
+    }
+}
+
+impl From<Math> for MathTree {
+    fn from(math: Math) -> Self {
+        MathTree::from(&math)
+    }
+}
+
+impl From<&Expr> for MathTree {
+    fn from(expr: &Expr) -> Self {
+        let mut nodes = vec![MathNode::default()];
+        let child = push_expr(&mut nodes, expr, 0);
+        if let MathNode::Root(root) = &mut nodes[0] {
+            root.children.push(child);
+        }
+        MathTree::from_nodes(nodes)
+    }
+}
+
+impl From<Expr> for MathTree {
+    fn from(expr: Expr) -> Self {
+        MathTree::from(&expr)
+    }
+}
+
+// Appends `expr` and its descendants in document order, the same order the
+// parser creates them in, and returns the index of the new node.
+fn push_expr(nodes: &mut Vec<MathNode>, expr: &Expr, parent: NodeIndex) -> NodeIndex {
+    let idx = nodes.len();
+    match expr {
+        Expr::Apply {
+            op,
+            bvars,
+            qualifiers,
+            args,
+            attributes,
+        } => {
+            nodes.push(MathNode::Apply(Apply::default()));
+            let operator = push_expr(nodes, op, idx);
+            let bvars: Vec<_> = bvars
+                .iter()
+                .map(|bvar| {
+                    let bvar_idx = nodes.len();
+                    nodes.push(MathNode::BVar(BVar::default()));
+                    let mut children = vec![push_expr(nodes, &Expr::ci(&bvar.name), bvar_idx)];
+                    if let Some(degree) = &bvar.degree {
+                        children.push(push_qualifier(
+                            nodes,
+                            &Qualifier::Degree,
+                            std::slice::from_ref(degree),
+                            bvar_idx,
+                        ));
+                    }
+                    nodes[bvar_idx] = MathNode::BVar(BVar {
+                        children,
+                        parent: Some(idx),
+                        ..Default::default()
+                    });
+                    bvar_idx
+                })
+                .collect();
+            let qualifiers: Vec<_> = qualifiers
+                .iter()
+                .map(|(qualifier, values)| push_qualifier(nodes, qualifier, values, idx))
+                .collect();
+            let operands: Vec<_> = args.iter().map(|arg| push_expr(nodes, arg, idx)).collect();
+            nodes[idx] = MathNode::Apply(Apply {
+                children: std::iter::once(operator)
+                    .chain(bvars.iter().copied())
+                    .chain(qualifiers.iter().copied())
+                    .chain(operands.iter().copied())
+                    .collect(),
+                operator: Some(operator),
+                bvars,
+                qualifiers,
+                operands,
+                parent: Some(parent),
+                attributes: attributes.clone(),
+            });
+        }
+        Expr::Op { op, attributes } => nodes.push(MathNode::Op(OpNode {
+            op: Some(op.clone()),
+            parent: Some(parent),
+            attributes: attributes.clone(),
+        })),
+        Expr::Constant {
+            constant,
+            attributes,
+        } => nodes.push(MathNode::Constant(ConstantNode {
+            constant: Some(constant.clone()),
+            parent: Some(parent),
+            attributes: attributes.clone(),
+        })),
+        Expr::Ci { name, attributes } => nodes.push(MathNode::Ci(Ci {
+            name: Some(name.clone()),
+            parent: Some(parent),
+            attributes: attributes.clone(),
+        })),
+        Expr::Csymbol {
+            definition_url,
+            encoding,
+            text,
+            attributes,
+        } => nodes.push(MathNode::Csymbol(Csymbol {
+            definition_url: definition_url.clone(),
+            encoding: encoding.clone(),
+            text: text.clone(),
+            parent: Some(parent),
+            attributes: attributes.clone(),
+        })),
+        Expr::Cn {
+            r#type,
+            base,
+            value,
+            attributes,
+        } => nodes.push(MathNode::Cn(Cn {
+            r#type: r#type.clone(),
+            base: *base,
+            value: Some(value.clone()),
+            parent: Some(parent),
+            attributes: attributes.clone(),
+        })),
+        Expr::Lambda {
+            params,
+            body,
+            attributes,
+        } => {
+            nodes.push(MathNode::Lambda(Lambda::default()));
+            let mut bindings = Vec::new();
+            for param in params {
+                let bvar = nodes.len();
+                nodes.push(MathNode::BVar(BVar::default()));
+                let ci = push_expr(nodes, &Expr::ci(param), bvar);
+                nodes[bvar] = MathNode::BVar(BVar {
+                    children: vec![ci],
+                    parent: Some(idx),
+                    ..Default::default()
+                });
+                bindings.push(bvar);
+            }
+            let body = push_expr(nodes, body, idx);
+            nodes[idx] = MathNode::Lambda(Lambda {
+                children: bindings.iter().copied().chain(Some(body)).collect(),
+                bindings,
+                expr: Some(body),
+                parent: Some(parent),
+                attributes: attributes.clone(),
+            });
+        }
+        Expr::Piecewise {
+            pieces,
+            otherwise,
+            attributes,
+        } => {
+            nodes.push(MathNode::Piecewise(Piecewise::default()));
+            let mut piece_indices = Vec::new();
+            for (value, condition) in pieces {
+                let piece = nodes.len();
+                nodes.push(MathNode::Piece(Piece::default()));
+                let value = push_expr(nodes, value, piece);
+                let condition = push_expr(nodes, condition, piece);
+                nodes[piece] = MathNode::Piece(Piece {
+                    children: vec![value, condition],
+                    expr: Some(value),
+                    condition: Some(condition),
+                    parent: Some(idx),
+                    ..Default::default()
+                });
+                piece_indices.push(piece);
+            }
+            let otherwise_idx = otherwise.as_ref().map(|otherwise| {
+                let otherwise_idx = nodes.len();
+                nodes.push(MathNode::Otherwise(Otherwise::default()));
+                let value = push_expr(nodes, otherwise, otherwise_idx);
+                nodes[otherwise_idx] = MathNode::Otherwise(Otherwise {
+                    children: vec![value],
+                    expr: Some(value),
+                    parent: Some(idx),
+                    ..Default::default()
+                });
+                otherwise_idx
+            });
+            nodes[idx] = MathNode::Piecewise(Piecewise {
+                children: piece_indices.iter().copied().chain(otherwise_idx).collect(),
+                pieces: piece_indices,
+                otherwise: otherwise_idx,
+                parent: Some(parent),
+                attributes: attributes.clone(),
+            });
+        }
+        Expr::Container {
+            container,
+            closure,
+            items,
+            attributes,
+        } => {
+            nodes.push(MathNode::Container(ContainerNode::default()));
+            let children = items
+                .iter()
+                .map(|item| push_expr(nodes, item, idx))
+                .collect();
+            nodes[idx] = MathNode::Container(ContainerNode {
+                container: Some(container.clone()),
+                closure: *closure,
+                children,
+                parent: Some(parent),
+                attributes: attributes.clone(),
+            });
+        }
+        Expr::Semantics {
+            expr,
+            annotations,
+            attributes,
+        } => {
+            nodes.push(MathNode::Semantics(Semantics::default()));
+            let child = push_expr(nodes, expr, idx);
+            nodes[idx] = MathNode::Semantics(Semantics {
+                children: vec![child],
+                expr: Some(child),
+                annotations: annotations.clone(),
+                parent: Some(parent),
+                attributes: attributes.clone(),
+            });
+        }
+    }
+    idx
+}
+
+fn push_qualifier(
+    nodes: &mut Vec<MathNode>,
+    qualifier: &Qualifier,
+    values: &[Expr],
+    parent: NodeIndex,
+) -> NodeIndex {
+    let idx = nodes.len();
+    nodes.push(MathNode::default());
+    let children = values
+        .iter()
+        .map(|value| push_expr(nodes, value, idx))
+        .collect();
+    nodes[idx] = MathNode::Qualifier(QualifierNode {
+        qualifier: Some(qualifier.clone()),
+        children,
+        parent: Some(parent),
+        ..Default::default()
+    });
+    idx
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::convert::TryFrom;
+    #[test]
+    fn expr_round_trip() {
+        let math = "<math>
+                <apply>
+                    <ci>f</ci>
+                    <piecewise>
+                        <piece><cn type=\"integer\">1</cn><apply><lt/><ci>x</ci><pi/></apply></piece>
+                        <otherwise><cn type=\"rational\">1<sep/>2</cn></otherwise>
+                    </piecewise>
+                    <apply><minus/><ci>y</ci></apply>
+                </apply>
+            </math>";
+        let tree = parse_str(math).unwrap();
+        let expr = Expr::try_from(&tree).unwrap();
+
+        let expected = Expr::call(
+            "f",
+            vec![
+                Expr::Piecewise {
+                    pieces: vec![(
+                        Expr::integer(1),
+                        Expr::apply(Op::Lt, vec![Expr::ci("x"), Expr::constant(Constant::Pi)]),
+                    )],
+                    otherwise: Some(Box::new(Expr::number(
+                        Some(NumType::Rational),
+                        Number::Rational(1, 2),
+                    ))),
+                    attributes: Attributes::default(),
+                },
+                Expr::apply(Op::Minus, vec![Expr::ci("y")]),
+            ],
+        );
+        assert_eq!(expr, expected);
+
+        // converting back reproduces the parsed arena node for node
+        let rebuilt = MathTree::from(&expr);
+        assert_eq!(
+            format!("{:?}", rebuilt.nodes()),
+            format!("{:?}", tree.nodes())
+        );
+
+        let lambda = Expr::Lambda {
+            params: vec!["a".to_string(), "b".to_string()],
+            body: Box::new(Expr::apply(Op::Times, vec![Expr::ci("a"), Expr::ci("b")])),
+            attributes: Attributes::default(),
+        };
+        let functions = std::collections::HashMap::from([(
+            "f".to_string(),
+            MathTree::from(lambda).into_nodes(),
+        )]);
+        let call = MathTree::from(Expr::call("f", vec![Expr::real(3.0), Expr::integer(4)]));
+        let result = eval_f64(call.nodes(), call.root(), &Default::default(), &functions);
+        assert_eq!(result, Ok(12.0));
+
+        // attributes and the base of numbers survive the round trip too
+        let tree = parse_str(
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML"
+                xmlns:sbml="http://www.sbml.org/sbml/level3/version2/core">
+                <apply id="a">
+                    <plus/>
+                    <cn sbml:units="mole" type="integer" base="16">FF</cn>
+                    <ci class="c">x</ci>
+                    <csymbol encoding="text" definitionURL="http://www.sbml.org/sbml/symbols/time" style="s">t</csymbol>
+                </apply>
+            </math>"#,
+        )
+        .unwrap();
+        let expr = Expr::try_from(&tree).unwrap();
+        match &expr {
+            Expr::Apply { args, .. } => {
+                assert!(matches!(args[0], Expr::Cn { base: Some(16), .. }));
+                let units = args[0].attributes().units();
+                assert_eq!(units, Some("mole"));
+            }
+            other => panic!("expected an apply, got {:?}", other),
+        }
+        let rebuilt = MathTree::from(&expr);
+        assert_eq!(
+            format!("{:?}", &rebuilt.nodes()[1..]),
+            format!("{:?}", &tree.nodes()[1..])
+        );
+
+        // so do those of every other element, including the math element
+        let tree = parse_str(
+            r#"<math id="m" class="top">
+                <lambda id="l">
+                    <bvar><ci>x</ci></bvar>
+                    <semantics id="s">
+                        <piecewise class="p">
+                            <piece>
+                                <vector style="v"><ci>x</ci></vector>
+                                <apply><lt/><ci>x</ci><pi id="pi"/></apply>
+                            </piece>
+                            <otherwise><apply><minus id="minus"/><ci>x</ci></apply></otherwise>
+                        </piecewise>
+                        <annotation encoding="text">f</annotation>
+                    </semantics>
+                </lambda>
+            </math>"#,
+        )
+        .unwrap();
+        let math = Math::try_from(&tree).unwrap();
+        assert_eq!(math.attributes.id.as_deref(), Some("m"));
+        assert_eq!(math.expr.attributes().id.as_deref(), Some("l"));
+        let rebuilt = MathTree::from(&math);
+        assert_eq!(
+            format!("{:?}", rebuilt.nodes()),
+            format!("{:?}", tree.nodes())
+        );
+        assert_eq!(to_mathml(&rebuilt), to_mathml(&tree));
+
+        // a tree without an expression has no Expr
+        let empty = MathTree::from_nodes(vec![MathNode::default()]);
+        assert_eq!(
+            Expr::try_from(&empty),
+            Err(TreeError {
+                reason: "no expression",
+                node: 0
+            })
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use super::attributes::Attributes;
 use super::math_node::{MathNodeType, NodeIndex};
 use std::fmt;
 
@@ -7,30 +8,41 @@ pub struct Lambda {
     pub bindings: Vec<NodeIndex>,
     pub expr: Option<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl Lambda {
-    pub fn index(&mut self, tag_type: MathNodeType, location: NodeIndex) {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        location: NodeIndex,
+    ) -> Result<(), &'static str> {
         match tag_type {
             MathNodeType::Op
             | MathNodeType::Apply
             | MathNodeType::Lambda
             | MathNodeType::Piecewise
             | MathNodeType::Ci
+            | MathNodeType::Csymbol
             | MathNodeType::Cn
-            | MathNodeType::Constant => {
-                if self.expr == None {
+            | MathNodeType::Constant
+            | MathNodeType::Semantics
+            | MathNodeType::Container => {
+                if self.expr.is_none() {
                     self.expr = Some(location);
+                    Ok(())
                 } else {
-                    panic!("Can't have two expressions in a lambda function!");
+                    Err("Can't have two expressions in a lambda function!")
                 }
             }
             MathNodeType::BVar => {
                 self.bindings.push(location);
+                Ok(())
             }
-            MathNodeType::Root | MathNodeType::Piece | MathNodeType::Otherwise => {
-                panic!("Can't have {} in a lambda function!", tag_type);
-            }
+            MathNodeType::Root
+            | MathNodeType::Piece
+            | MathNodeType::Otherwise
+            | MathNodeType::Qualifier => Err("Can't have this element in a lambda function!"),
         }
     }
 }
@@ -1,7 +1,7 @@
 pub type NodeIndex = usize;
 use crate::{
-    Apply, BVar, Ci, Cn, Constant, ConstantNode, Lambda, Op, OpNode, Otherwise, Piece, Piecewise,
-    Root,
+    Apply, Attributes, BVar, Ci, Cn, Constant, ConstantNode, Container, ContainerNode, Csymbol,
+    Lambda, Op, OpNode, Otherwise, Piece, Piecewise, Qualifier, QualifierNode, Root, Semantics,
 };
 
 use std::fmt;
@@ -19,6 +19,10 @@ pub enum MathNode {
     Piecewise(Piecewise),
     Piece(Piece),
     Otherwise(Otherwise),
+    Qualifier(QualifierNode),
+    Csymbol(Csymbol),
+    Semantics(Semantics),
+    Container(ContainerNode),
 }
 
 impl MathNode {
@@ -26,14 +30,149 @@ impl MathNode {
         MathNode::Op(OpNode {
             op: Some(op),
             parent: None,
+            attributes: Default::default(),
         })
     }
     pub fn new_constant(constant: Constant) -> Self {
         MathNode::Constant(ConstantNode {
             constant: Some(constant),
             parent: None,
+            attributes: Default::default(),
+        })
+    }
+    pub fn new_qualifier(qualifier: Qualifier) -> Self {
+        MathNode::Qualifier(QualifierNode {
+            qualifier: Some(qualifier),
+            ..Default::default()
+        })
+    }
+    pub fn new_container(container: Container) -> Self {
+        MathNode::Container(ContainerNode {
+            container: Some(container),
+            ..Default::default()
         })
     }
+    pub fn node_type(&self) -> MathNodeType {
+        match self {
+            MathNode::Apply(..) => MathNodeType::Apply,
+            MathNode::Op(..) => MathNodeType::Op,
+            MathNode::Constant(..) => MathNodeType::Constant,
+            MathNode::Root(..) => MathNodeType::Root,
+            MathNode::Ci(..) => MathNodeType::Ci,
+            MathNode::Cn(..) => MathNodeType::Cn,
+            MathNode::Lambda(..) => MathNodeType::Lambda,
+            MathNode::BVar(..) => MathNodeType::BVar,
+            MathNode::Piecewise(..) => MathNodeType::Piecewise,
+            MathNode::Piece(..) => MathNodeType::Piece,
+            MathNode::Otherwise(..) => MathNodeType::Otherwise,
+            MathNode::Qualifier(..) => MathNodeType::Qualifier,
+            MathNode::Csymbol(..) => MathNodeType::Csymbol,
+            MathNode::Semantics(..) => MathNodeType::Semantics,
+            MathNode::Container(..) => MathNodeType::Container,
+        }
+    }
+    pub fn children(&self) -> &[NodeIndex] {
+        match self {
+            MathNode::Root(root) => &root.children,
+            MathNode::Apply(apply) => &apply.children,
+            MathNode::Lambda(lambda) => &lambda.children,
+            MathNode::BVar(bvar) => &bvar.children,
+            MathNode::Piecewise(piecewise) => &piecewise.children,
+            MathNode::Piece(piece) => &piece.children,
+            MathNode::Otherwise(otherwise) => &otherwise.children,
+            MathNode::Qualifier(qualifiernode) => &qualifiernode.children,
+            MathNode::Semantics(semantics) => &semantics.children,
+            MathNode::Container(containernode) => &containernode.children,
+            MathNode::Op(..)
+            | MathNode::Constant(..)
+            | MathNode::Ci(..)
+            | MathNode::Cn(..)
+            | MathNode::Csymbol(..) => &[],
+        }
+    }
+    pub fn parent(&self) -> Option<NodeIndex> {
+        match self {
+            MathNode::Root(root) => root.parent,
+            MathNode::Apply(apply) => apply.parent,
+            MathNode::Op(opnode) => opnode.parent,
+            MathNode::Constant(constantnode) => constantnode.parent,
+            MathNode::Ci(ci) => ci.parent,
+            MathNode::Cn(cn) => cn.parent,
+            MathNode::Lambda(lambda) => lambda.parent,
+            MathNode::BVar(bvar) => bvar.parent,
+            MathNode::Piecewise(piecewise) => piecewise.parent,
+            MathNode::Piece(piece) => piece.parent,
+            MathNode::Otherwise(otherwise) => otherwise.parent,
+            MathNode::Qualifier(qualifiernode) => qualifiernode.parent,
+            MathNode::Csymbol(csymbol) => csymbol.parent,
+            MathNode::Semantics(semantics) => semantics.parent,
+            MathNode::Container(containernode) => containernode.parent,
+        }
+    }
+    pub fn attributes(&self) -> &Attributes {
+        match self {
+            MathNode::Root(root) => &root.attributes,
+            MathNode::Apply(apply) => &apply.attributes,
+            MathNode::Op(opnode) => &opnode.attributes,
+            MathNode::Constant(constantnode) => &constantnode.attributes,
+            MathNode::Ci(ci) => &ci.attributes,
+            MathNode::Cn(cn) => &cn.attributes,
+            MathNode::Lambda(lambda) => &lambda.attributes,
+            MathNode::BVar(bvar) => &bvar.attributes,
+            MathNode::Piecewise(piecewise) => &piecewise.attributes,
+            MathNode::Piece(piece) => &piece.attributes,
+            MathNode::Otherwise(otherwise) => &otherwise.attributes,
+            MathNode::Qualifier(qualifiernode) => &qualifiernode.attributes,
+            MathNode::Csymbol(csymbol) => &csymbol.attributes,
+            MathNode::Semantics(semantics) => &semantics.attributes,
+            MathNode::Container(containernode) => &containernode.attributes,
+        }
+    }
+    pub fn attributes_mut(&mut self) -> &mut Attributes {
+        match self {
+            MathNode::Root(root) => &mut root.attributes,
+            MathNode::Apply(apply) => &mut apply.attributes,
+            MathNode::Op(opnode) => &mut opnode.attributes,
+            MathNode::Constant(constantnode) => &mut constantnode.attributes,
+            MathNode::Ci(ci) => &mut ci.attributes,
+            MathNode::Cn(cn) => &mut cn.attributes,
+            MathNode::Lambda(lambda) => &mut lambda.attributes,
+            MathNode::BVar(bvar) => &mut bvar.attributes,
+            MathNode::Piecewise(piecewise) => &mut piecewise.attributes,
+            MathNode::Piece(piece) => &mut piece.attributes,
+            MathNode::Otherwise(otherwise) => &mut otherwise.attributes,
+            MathNode::Qualifier(qualifiernode) => &mut qualifiernode.attributes,
+            MathNode::Csymbol(csymbol) => &mut csymbol.attributes,
+            MathNode::Semantics(semantics) => &mut semantics.attributes,
+            MathNode::Container(containernode) => &mut containernode.attributes,
+        }
+    }
+    /// Describes the required child or content that this node is missing,
+    /// if any.
+    pub fn missing_child(&self) -> Option<&'static str> {
+        match self {
+            MathNode::Root(root) if root.children.is_empty() => Some("expression"),
+            MathNode::Apply(apply) if apply.operator.is_none() => Some("operator"),
+            MathNode::Lambda(lambda) if lambda.expr.is_none() => Some("expression"),
+            MathNode::BVar(bvar) if bvar.children.is_empty() => Some("variable"),
+            MathNode::Piece(piece) if piece.expr.is_none() => Some("expression"),
+            MathNode::Piece(piece) if piece.condition.is_none() => Some("condition"),
+            MathNode::Otherwise(otherwise) if otherwise.expr.is_none() => Some("expression"),
+            MathNode::Semantics(semantics) if semantics.expr.is_none() => Some("expression"),
+            MathNode::Ci(ci) if ci.name.is_none() => Some("name"),
+            MathNode::Cn(cn) if cn.value.is_none() => Some("value"),
+            MathNode::Qualifier(qualifiernode) => match qualifiernode.qualifier {
+                Some(Qualifier::Interval) if qualifiernode.children.len() < 2 => Some("bounds"),
+                _ if qualifiernode.children.is_empty() => Some("value"),
+                _ => None,
+            },
+            MathNode::Container(containernode) => match containernode.container {
+                Some(Container::Interval) if containernode.children.len() < 2 => Some("bounds"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
     pub fn shift_indices(&mut self, shift: i32) -> &Self {
         match self {
             MathNode::Root(root) => {
@@ -54,6 +193,12 @@ impl MathNode {
                 for i in 0..apply.operands.len() {
                     apply.operands[i] = ((apply.operands[i] as i32) + shift) as usize;
                 }
+                for i in 0..apply.bvars.len() {
+                    apply.bvars[i] = ((apply.bvars[i] as i32) + shift) as usize;
+                }
+                for i in 0..apply.qualifiers.len() {
+                    apply.qualifiers[i] = ((apply.qualifiers[i] as i32) + shift) as usize;
+                }
                 if let Some(parent) = apply.parent {
                     apply.parent = Some(((parent as i32) + shift) as usize);
                 }
@@ -68,6 +213,11 @@ impl MathNode {
                     cn.parent = Some(((parent as i32) + shift) as usize);
                 }
             }
+            MathNode::Csymbol(csymbol) => {
+                if let Some(parent) = csymbol.parent {
+                    csymbol.parent = Some(((parent as i32) + shift) as usize);
+                }
+            }
             MathNode::Op(op) => {
                 if let Some(parent) = op.parent {
                     op.parent = Some(((parent as i32) + shift) as usize);
@@ -139,6 +289,35 @@ impl MathNode {
                     otherwise.parent = Some(((parent as i32) + shift) as usize);
                 }
             }
+            MathNode::Qualifier(qualifiernode) => {
+                for i in 0..qualifiernode.children.len() {
+                    qualifiernode.children[i] =
+                        ((qualifiernode.children[i] as i32) + shift) as usize;
+                }
+                if let Some(parent) = qualifiernode.parent {
+                    qualifiernode.parent = Some(((parent as i32) + shift) as usize);
+                }
+            }
+            MathNode::Semantics(semantics) => {
+                for i in 0..semantics.children.len() {
+                    semantics.children[i] = ((semantics.children[i] as i32) + shift) as usize;
+                }
+                if let Some(expr) = semantics.expr {
+                    semantics.expr = Some(((expr as i32) + shift) as usize);
+                }
+                if let Some(parent) = semantics.parent {
+                    semantics.parent = Some(((parent as i32) + shift) as usize);
+                }
+            }
+            MathNode::Container(containernode) => {
+                for i in 0..containernode.children.len() {
+                    containernode.children[i] =
+                        ((containernode.children[i] as i32) + shift) as usize;
+                }
+                if let Some(parent) = containernode.parent {
+                    containernode.parent = Some(((parent as i32) + shift) as usize);
+                }
+            }
         }
         self
     }
@@ -164,10 +343,15 @@ impl fmt::Display for MathNode {
             MathNode::Piece(piece) => write!(f, "Piece: {}", piece),
             MathNode::Otherwise(otherwise) => write!(f, "Otherwise: {}", otherwise),
             MathNode::Constant(constantnode) => write!(f, "Constant: {}", constantnode),
+            MathNode::Qualifier(qualifiernode) => write!(f, "Qualifier: {}", qualifiernode),
+            MathNode::Csymbol(csymbol) => write!(f, "Csymbol: {}", csymbol),
+            MathNode::Semantics(semantics) => write!(f, "Semantics: {}", semantics),
+            MathNode::Container(containernode) => write!(f, "Container: {}", containernode),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum MathNodeType {
     Apply,
     Op,
@@ -180,6 +364,10 @@ pub enum MathNodeType {
     Piece,
     Otherwise,
     Constant,
+    Qualifier,
+    Csymbol,
+    Semantics,
+    Container,
 }
 
 impl fmt::Display for MathNodeType {
@@ -196,6 +384,10 @@ impl fmt::Display for MathNodeType {
             MathNodeType::Piecewise => write!(f, "Piecewise"),
             MathNodeType::Piece => write!(f, "Piece"),
             MathNodeType::Otherwise => write!(f, "Otherwise"),
+            MathNodeType::Qualifier => write!(f, "Qualifier"),
+            MathNodeType::Csymbol => write!(f, "Csymbol"),
+            MathNodeType::Semantics => write!(f, "Semantics"),
+            MathNodeType::Container => write!(f, "Container"),
         }
     }
 }
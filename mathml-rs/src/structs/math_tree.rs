@@ -0,0 +1,479 @@
+use super::apply::Apply;
+use super::attributes::Attributes;
+use super::bindings::BVar;
+use super::ci::Ci;
+use super::cn::Cn;
+use super::constants::Constant;
+use super::container::ContainerNode;
+use super::csymbol::{Csymbol, SbmlSymbol};
+use super::error::TreeError;
+use super::lambda::Lambda;
+use super::math_node::{MathNode, NodeIndex};
+use super::numbers::Number;
+use super::op::Op;
+use super::piecewise::{Otherwise, Piece, Piecewise};
+use super::qualifier::{Qualifier, QualifierNode};
+use super::semantics::Semantics;
+use std::collections::VecDeque;
+
+/// A parsed MathML expression.
+///
+/// The nodes live in a flat arena and refer to each other by [`NodeIndex`].
+/// The node at [`MathTree::root`] is always a [`MathNode::Root`] whose single
+/// child is the top-level expression. All accessors take arbitrary indices
+/// and return `None` or an empty slice instead of panicking when the index
+/// does not refer to a node of the expected kind.
+#[derive(Debug, Clone)]
+pub struct MathTree {
+    nodes: Vec<MathNode>,
+}
+
+impl MathTree {
+    pub(crate) fn from_nodes(nodes: Vec<MathNode>) -> Self {
+        MathTree { nodes }
+    }
+
+    /// Builds a tree from a node arena, e.g. one taken apart with
+    /// [`MathTree::into_nodes`].
+    ///
+    /// The node at index 0 must be the root, and every other node must be
+    /// the child of exactly one node, which is also its parent. Indices in
+    /// the typed fields of nodes, like the operands of an apply, are not
+    /// checked; the accessors treat them like any other index.
+    pub fn try_from_nodes(nodes: Vec<MathNode>) -> Result<Self, TreeError> {
+        let error = |reason, node| Err(TreeError { reason, node });
+        match nodes.first() {
+            Some(MathNode::Root(root)) if root.parent.is_none() => {}
+            Some(..) => return error("not a root", 0),
+            None => return error("no root", 0),
+        }
+        let mut seen = vec![false; nodes.len()];
+        seen[0] = true;
+        for (idx, node) in nodes.iter().enumerate() {
+            for &child in node.children() {
+                match nodes.get(child) {
+                    None => return error("child out of range", idx),
+                    Some(..) if seen[child] => return error("node with two parents", child),
+                    Some(child_node) if child_node.parent() != Some(idx) => {
+                        return error("parent does not match", child)
+                    }
+                    Some(..) => seen[child] = true,
+                }
+            }
+        }
+        match seen.iter().position(|&seen| !seen) {
+            Some(orphan) => error("node without a parent", orphan),
+            None => Ok(MathTree { nodes }),
+        }
+    }
+
+    /// The raw node arena, e.g. for use with [`evaluate_node`](crate::evaluate_node).
+    pub fn nodes(&self) -> &[MathNode] {
+        &self.nodes
+    }
+
+    pub fn into_nodes(self) -> Vec<MathNode> {
+        self.nodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn root(&self) -> NodeIndex {
+        0
+    }
+
+    /// The top-level expression, i.e. the child of the root.
+    pub fn expr(&self) -> Option<NodeIndex> {
+        self.children(self.root()).first().copied()
+    }
+
+    pub fn get(&self, idx: NodeIndex) -> Option<&MathNode> {
+        self.nodes.get(idx)
+    }
+
+    pub fn children(&self, idx: NodeIndex) -> &[NodeIndex] {
+        self.get(idx).map_or(&[], MathNode::children)
+    }
+
+    pub fn parent(&self, idx: NodeIndex) -> Option<NodeIndex> {
+        self.get(idx).and_then(MathNode::parent)
+    }
+
+    /// The operator node of an apply.
+    pub fn operator_of(&self, idx: NodeIndex) -> Option<NodeIndex> {
+        self.as_apply(idx).and_then(|apply| apply.operator)
+    }
+
+    /// The operand nodes of an apply.
+    pub fn operands_of(&self, idx: NodeIndex) -> &[NodeIndex] {
+        self.as_apply(idx).map_or(&[], |apply| &apply.operands)
+    }
+
+    /// The built-in operator of an apply, if it is not a function call.
+    pub fn op_of(&self, idx: NodeIndex) -> Option<&Op> {
+        self.operator_of(idx).and_then(|operator| self.op(operator))
+    }
+
+    /// The name of the function called by an apply, if it is not a built-in operator.
+    pub fn function_name_of(&self, idx: NodeIndex) -> Option<&str> {
+        self.operator_of(idx)
+            .and_then(|operator| self.ci_name(operator))
+    }
+
+    /// The bvar nodes of an apply, e.g. the variable of an integral.
+    pub fn bvars_of(&self, idx: NodeIndex) -> &[NodeIndex] {
+        self.as_apply(idx).map_or(&[], |apply| &apply.bvars)
+    }
+
+    /// The names bound by the bvars of an apply, in order.
+    pub fn bound_variables_of(&self, idx: NodeIndex) -> Vec<&str> {
+        self.bvars_of(idx)
+            .iter()
+            .filter_map(|&bvar| self.children(bvar).first())
+            .filter_map(|&child| self.ci_name(child))
+            .collect()
+    }
+
+    /// The qualifier nodes of an apply, in order.
+    pub fn qualifiers_of(&self, idx: NodeIndex) -> &[NodeIndex] {
+        self.as_apply(idx).map_or(&[], |apply| &apply.qualifiers)
+    }
+
+    /// The first qualifier node of the given kind on an apply or bvar.
+    pub fn qualifier_of(&self, idx: NodeIndex, kind: &Qualifier) -> Option<NodeIndex> {
+        let candidates = match self.get(idx) {
+            Some(MathNode::Apply(apply)) => &apply.qualifiers[..],
+            Some(MathNode::BVar(bvar)) => bvar.children.get(1..).unwrap_or_default(),
+            _ => &[],
+        };
+        candidates
+            .iter()
+            .copied()
+            .find(|&candidate| self.qualifier(candidate) == Some(kind))
+    }
+
+    /// The value of a single-valued qualifier, like the degree of a root,
+    /// the base of a log or the lower limit of a sum.
+    pub fn qualifier_value_of(&self, idx: NodeIndex, kind: &Qualifier) -> Option<NodeIndex> {
+        self.qualifier_of(idx, kind)
+            .and_then(|qualifier| self.children(qualifier).first().copied())
+    }
+
+    /// The lower and upper bound of an interval qualifier.
+    pub fn interval_of(&self, idx: NodeIndex) -> Option<(NodeIndex, NodeIndex)> {
+        match self.children(self.qualifier_of(idx, &Qualifier::Interval)?) {
+            &[low, high] => Some((low, high)),
+            _ => None,
+        }
+    }
+
+    /// The names bound by a lambda, in order.
+    pub fn parameters_of(&self, idx: NodeIndex) -> Vec<&str> {
+        let bindings = self
+            .as_lambda(idx)
+            .map_or(&[][..], |lambda| &lambda.bindings);
+        bindings
+            .iter()
+            .flat_map(|&bvar| self.children(bvar))
+            .filter_map(|&child| self.ci_name(child))
+            .collect()
+    }
+
+    /// The body of a lambda.
+    pub fn body_of(&self, idx: NodeIndex) -> Option<NodeIndex> {
+        self.as_lambda(idx).and_then(|lambda| lambda.expr)
+    }
+
+    /// The pieces of a piecewise, in order.
+    pub fn pieces_of(&self, idx: NodeIndex) -> &[NodeIndex] {
+        self.as_piecewise(idx)
+            .map_or(&[], |piecewise| &piecewise.pieces)
+    }
+
+    /// The expression of the otherwise branch of a piecewise.
+    pub fn otherwise_of(&self, idx: NodeIndex) -> Option<NodeIndex> {
+        self.as_piecewise(idx)
+            .and_then(|piecewise| piecewise.otherwise)
+            .and_then(|otherwise| self.as_otherwise(otherwise))
+            .and_then(|otherwise| otherwise.expr)
+    }
+
+    /// The value and condition of a piece.
+    pub fn piece_parts(&self, idx: NodeIndex) -> Option<(NodeIndex, NodeIndex)> {
+        let piece = self.as_piece(idx)?;
+        Some((piece.expr?, piece.condition?))
+    }
+
+    pub fn op(&self, idx: NodeIndex) -> Option<&Op> {
+        match self.get(idx) {
+            Some(MathNode::Op(opnode)) => opnode.op.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn constant(&self, idx: NodeIndex) -> Option<&Constant> {
+        match self.get(idx) {
+            Some(MathNode::Constant(constantnode)) => constantnode.constant.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn qualifier(&self, idx: NodeIndex) -> Option<&Qualifier> {
+        self.as_qualifier(idx)
+            .and_then(|qualifiernode| qualifiernode.qualifier.as_ref())
+    }
+
+    pub fn ci_name(&self, idx: NodeIndex) -> Option<&str> {
+        self.as_ci(idx).and_then(|ci| ci.name.as_deref())
+    }
+
+    pub fn cn_value(&self, idx: NodeIndex) -> Option<&Number> {
+        self.as_cn(idx).and_then(|cn| cn.value.as_ref())
+    }
+
+    pub fn sbml_symbol(&self, idx: NodeIndex) -> Option<SbmlSymbol> {
+        self.as_csymbol(idx).and_then(Csymbol::sbml_symbol)
+    }
+
+    pub fn attributes(&self, idx: NodeIndex) -> Option<&Attributes> {
+        self.get(idx).map(MathNode::attributes)
+    }
+
+    pub fn as_apply(&self, idx: NodeIndex) -> Option<&Apply> {
+        match self.get(idx) {
+            Some(MathNode::Apply(apply)) => Some(apply),
+            _ => None,
+        }
+    }
+
+    pub fn as_ci(&self, idx: NodeIndex) -> Option<&Ci> {
+        match self.get(idx) {
+            Some(MathNode::Ci(ci)) => Some(ci),
+            _ => None,
+        }
+    }
+
+    pub fn as_cn(&self, idx: NodeIndex) -> Option<&Cn> {
+        match self.get(idx) {
+            Some(MathNode::Cn(cn)) => Some(cn),
+            _ => None,
+        }
+    }
+
+    pub fn as_csymbol(&self, idx: NodeIndex) -> Option<&Csymbol> {
+        match self.get(idx) {
+            Some(MathNode::Csymbol(csymbol)) => Some(csymbol),
+            _ => None,
+        }
+    }
+
+    pub fn as_lambda(&self, idx: NodeIndex) -> Option<&Lambda> {
+        match self.get(idx) {
+            Some(MathNode::Lambda(lambda)) => Some(lambda),
+            _ => None,
+        }
+    }
+
+    pub fn as_bvar(&self, idx: NodeIndex) -> Option<&BVar> {
+        match self.get(idx) {
+            Some(MathNode::BVar(bvar)) => Some(bvar),
+            _ => None,
+        }
+    }
+
+    pub fn as_piecewise(&self, idx: NodeIndex) -> Option<&Piecewise> {
+        match self.get(idx) {
+            Some(MathNode::Piecewise(piecewise)) => Some(piecewise),
+            _ => None,
+        }
+    }
+
+    pub fn as_piece(&self, idx: NodeIndex) -> Option<&Piece> {
+        match self.get(idx) {
+            Some(MathNode::Piece(piece)) => Some(piece),
+            _ => None,
+        }
+    }
+
+    pub fn as_otherwise(&self, idx: NodeIndex) -> Option<&Otherwise> {
+        match self.get(idx) {
+            Some(MathNode::Otherwise(otherwise)) => Some(otherwise),
+            _ => None,
+        }
+    }
+
+    pub fn as_container(&self, idx: NodeIndex) -> Option<&ContainerNode> {
+        match self.get(idx) {
+            Some(MathNode::Container(containernode)) => Some(containernode),
+            _ => None,
+        }
+    }
+
+    pub fn as_semantics(&self, idx: NodeIndex) -> Option<&Semantics> {
+        match self.get(idx) {
+            Some(MathNode::Semantics(semantics)) => Some(semantics),
+            _ => None,
+        }
+    }
+
+    pub fn as_qualifier(&self, idx: NodeIndex) -> Option<&QualifierNode> {
+        match self.get(idx) {
+            Some(MathNode::Qualifier(qualifiernode)) => Some(qualifiernode),
+            _ => None,
+        }
+    }
+
+    /// Visits every node below and including the root in pre-order.
+    pub fn depth_first(&self) -> DepthFirst<'_> {
+        self.depth_first_from(self.root())
+    }
+
+    /// Visits every node below and including `start` in pre-order.
+    pub fn depth_first_from(&self, start: NodeIndex) -> DepthFirst<'_> {
+        DepthFirst {
+            tree: self,
+            stack: self.get(start).map(|_| start).into_iter().collect(),
+        }
+    }
+
+    /// Visits every node below and including the root level by level.
+    pub fn breadth_first(&self) -> BreadthFirst<'_> {
+        self.breadth_first_from(self.root())
+    }
+
+    /// Visits every node below and including `start` level by level.
+    pub fn breadth_first_from(&self, start: NodeIndex) -> BreadthFirst<'_> {
+        BreadthFirst {
+            tree: self,
+            queue: self.get(start).map(|_| start).into_iter().collect(),
+        }
+    }
+}
+
+impl AsRef<[MathNode]> for MathTree {
+    fn as_ref(&self) -> &[MathNode] {
+        &self.nodes
+    }
+}
+
+/// Pre-order iterator over the indices of a [`MathTree`].
+pub struct DepthFirst<'a> {
+    tree: &'a MathTree,
+    stack: Vec<NodeIndex>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = NodeIndex;
+
+    fn next(&mut self) -> Option<NodeIndex> {
+        let idx = self.stack.pop()?;
+        self.stack
+            .extend(self.tree.children(idx).iter().rev().copied());
+        Some(idx)
+    }
+}
+
+/// Level-order iterator over the indices of a [`MathTree`].
+pub struct BreadthFirst<'a> {
+    tree: &'a MathTree,
+    queue: VecDeque<NodeIndex>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = NodeIndex;
+
+    fn next(&mut self) -> Option<NodeIndex> {
+        let idx = self.queue.pop_front()?;
+        self.queue.extend(self.tree.children(idx).iter().copied());
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    #[test]
+    fn tree_navigation() {
+        let math = "<math>
+                <piecewise>
+                    <piece>
+                        <apply><times/><ci>k</ci><ci>A</ci></apply>
+                        <apply><gt/><ci>t</ci><cn>5</cn></apply>
+                    </piece>
+                    <otherwise><cn>0</cn></otherwise>
+                </piecewise>
+            </math>";
+        let tree = parse_str(math).unwrap();
+
+        let piecewise = tree.expr().unwrap();
+        assert!(tree.as_piecewise(piecewise).is_some());
+        assert_eq!(tree.parent(piecewise), Some(tree.root()));
+
+        let piece = tree.pieces_of(piecewise)[0];
+        let (value, condition) = tree.piece_parts(piece).unwrap();
+        assert_eq!(tree.op_of(value), Some(&Op::Times));
+        assert_eq!(tree.op_of(condition), Some(&Op::Gt));
+        let names: Vec<_> = tree
+            .operands_of(value)
+            .iter()
+            .filter_map(|&idx| tree.ci_name(idx))
+            .collect();
+        assert_eq!(names, ["k", "A"]);
+
+        let otherwise = tree.otherwise_of(piecewise).unwrap();
+        assert_eq!(tree.cn_value(otherwise), Some(&Number::Real(0.0)));
+
+        // out of range or mismatched indices never panic
+        assert_eq!(tree.children(1000), &[] as &[NodeIndex]);
+        assert!(tree.operator_of(piecewise).is_none());
+        assert!(tree.as_ci(tree.root()).is_none());
+
+        let depth_first: Vec<_> = tree.depth_first().collect();
+        let breadth_first: Vec<_> = tree.breadth_first().collect();
+        assert_eq!(depth_first.len(), tree.len());
+        assert_eq!(breadth_first.len(), tree.len());
+        assert_eq!(depth_first[..4], [0, piecewise, piece, value]);
+        assert_eq!(
+            breadth_first[..4],
+            [0, piecewise, piece, tree.children(piecewise)[1]]
+        );
+    }
+
+    #[test]
+    fn trees_from_nodes() {
+        let tree = parse_formula("f(x) + 1").unwrap();
+        let nodes = tree.clone().into_nodes();
+        let rebuilt = MathTree::try_from_nodes(nodes.clone()).unwrap();
+        assert_eq!(rebuilt.len(), tree.len());
+
+        assert_eq!(
+            MathTree::try_from_nodes(Vec::new()).unwrap_err().reason,
+            "no root"
+        );
+        assert_eq!(
+            MathTree::try_from_nodes(nodes[1..].to_vec()).unwrap_err(),
+            TreeError {
+                reason: "not a root",
+                node: 0
+            }
+        );
+        let mut dangling = nodes.clone();
+        dangling.pop();
+        assert_eq!(
+            MathTree::try_from_nodes(dangling).unwrap_err().reason,
+            "child out of range"
+        );
+        let mut orphaned = nodes;
+        orphaned.push(MathNode::default());
+        assert_eq!(
+            MathTree::try_from_nodes(orphaned).unwrap_err().reason,
+            "node without a parent"
+        );
+    }
+}
@@ -1,11 +1,20 @@
 pub mod apply;
+pub mod attributes;
 pub mod bindings;
 pub mod ci;
 pub mod cn;
 pub mod constants;
+pub mod container;
+pub mod csymbol;
+pub mod error;
+pub mod expr;
 pub mod lambda;
 pub mod math_node;
+pub mod math_tree;
 pub mod numbers;
 pub mod op;
 pub mod piecewise;
+pub mod qualifier;
 pub mod root;
+pub mod semantics;
+pub mod value;
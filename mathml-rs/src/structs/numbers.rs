@@ -1,6 +1,7 @@
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum NumType {
     Real,
     Integer,
@@ -9,6 +10,8 @@ pub enum NumType {
     ComplexPolar,
     Constant,
     ENotation,
+    /// A real given by the 16 hexadecimal digits of its IEEE 754 bits.
+    HexDouble,
 }
 
 impl FromStr for NumType {
@@ -23,15 +26,32 @@ impl FromStr for NumType {
             "complex-cartesian" => Ok(NumType::ComplexCartesian),
             "complex-polar" => Ok(NumType::ComplexPolar),
             "constant" => Ok(NumType::Constant),
+            "hexdouble" => Ok(NumType::HexDouble),
             _ => Err(()),
         }
     }
 }
 
+impl fmt::Display for NumType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            NumType::Integer => "integer",
+            NumType::Real => "real",
+            NumType::ENotation => "e-notation",
+            NumType::Rational => "rational",
+            NumType::ComplexCartesian => "complex-cartesian",
+            NumType::ComplexPolar => "complex-polar",
+            NumType::Constant => "constant",
+            NumType::HexDouble => "hexdouble",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Number {
     Real(f64),
-    Integer(i32),
+    Integer(i64),
     Rational(i64, i64),
     ComplexCartesian(f64, f64),
     ComplexPolar(f64, f64),
@@ -39,6 +59,54 @@ pub enum Number {
     ENotation(f64, i64),
 }
 
+impl Number {
+    /// Parses the content of a `cn` element of the given type, split into
+    /// the parts separated by its `sep` elements, with digits in `base`.
+    ///
+    /// Real numbers in base 10 may use exponents as well as `INF` and `NaN`,
+    /// while the exponent of an e-notation number is always in base 10.
+    /// Returns `None` if the content does not fit the type.
+    pub fn parse(r#type: &NumType, base: u32, parts: &[&str]) -> Option<Number> {
+        let number = match (r#type, parts) {
+            (NumType::Real, &[real]) => Number::Real(parse_real(real, base)?),
+            (NumType::Integer, &[integer]) => Number::Integer(parse_integer(integer, base)?),
+            (NumType::Constant, &[name]) => Number::Constant(name.trim().to_string()),
+            (NumType::HexDouble, &[bits]) => Number::Real(parse_hexdouble(bits)?),
+            (NumType::ENotation, &[mantissa, exponent]) => {
+                Number::ENotation(parse_real(mantissa, base)?, exponent.trim().parse().ok()?)
+            }
+            (NumType::Rational, &[numerator, denominator]) => {
+                let denominator = parse_integer(denominator, base)?;
+                if denominator == 0 {
+                    return None;
+                }
+                Number::Rational(parse_integer(numerator, base)?, denominator)
+            }
+            (NumType::ComplexCartesian, &[real, imaginary]) => {
+                Number::ComplexCartesian(parse_real(real, base)?, parse_real(imaginary, base)?)
+            }
+            (NumType::ComplexPolar, &[magnitude, angle]) => {
+                Number::ComplexPolar(parse_real(magnitude, base)?, parse_real(angle, base)?)
+            }
+            _ => return None,
+        };
+        Some(number)
+    }
+
+    /// The `type` attribute value that describes this number.
+    pub fn num_type(&self) -> NumType {
+        match self {
+            Number::Real(..) => NumType::Real,
+            Number::Integer(..) => NumType::Integer,
+            Number::Rational(..) => NumType::Rational,
+            Number::ComplexCartesian(..) => NumType::ComplexCartesian,
+            Number::ComplexPolar(..) => NumType::ComplexPolar,
+            Number::Constant(..) => NumType::Constant,
+            Number::ENotation(..) => NumType::ENotation,
+        }
+    }
+}
+
 impl Eq for Number {}
 impl PartialEq for Number {
     fn eq(&self, other: &Self) -> bool {
@@ -57,3 +125,45 @@ impl PartialEq for Number {
         }
     }
 }
+
+fn parse_integer(text: &str, base: u32) -> Option<i64> {
+    let text = text.trim();
+    i64::from_str_radix(text.strip_prefix('+').unwrap_or(text), base).ok()
+}
+
+// Parses a real with an optional sign and fractional part. Only base 10
+// allows exponents and the names of the special values.
+// The base attribute does not apply, the digits are always hexadecimal.
+fn parse_hexdouble(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.len() != 16 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(f64::from_bits(u64::from_str_radix(text, 16).ok()?))
+}
+
+fn parse_real(text: &str, base: u32) -> Option<f64> {
+    let text = text.trim();
+    if base == 10 {
+        return text.parse().ok();
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let radix = f64::from(base);
+    let mut value = 0.0;
+    for c in whole.chars() {
+        value = value * radix + f64::from(c.to_digit(base)?);
+    }
+    let mut scale = 1.0;
+    for c in fraction.chars() {
+        scale /= radix;
+        value += f64::from(c.to_digit(base)?) * scale;
+    }
+    Some(if negative { -value } else { value })
+}
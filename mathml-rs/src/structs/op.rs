@@ -1,3 +1,4 @@
+use super::attributes::Attributes;
 use super::math_node::NodeIndex;
 use std::fmt;
 
@@ -5,6 +6,7 @@ use std::fmt;
 pub struct OpNode {
     pub op: Option<Op>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl fmt::Display for OpNode {
@@ -94,6 +96,7 @@ pub enum Op {
     Intersect,
     Cartesianproduct,
     Compose,
+    /// The deprecated `fn` element, which the parser rejects.
     r#Fn,
     Int,
     Sum,
@@ -110,3 +113,111 @@ pub enum Op {
     Leq,
     Root,
 }
+
+impl Op {
+    /// The name of the MathML element for this operator.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Factorial => "factorial",
+            Op::Minus => "minus",
+            Op::Abs => "abs",
+            Op::Conjugate => "conjugate",
+            Op::Arg => "arg",
+            Op::Real => "real",
+            Op::Imaginary => "imaginary",
+            Op::Floor => "floor",
+            Op::Ceiling => "ceiling",
+            Op::Not => "not",
+            Op::Inverse => "inverse",
+            Op::Ident => "ident",
+            Op::Domain => "domain",
+            Op::Codomain => "codomain",
+            Op::Image => "image",
+            Op::Sin => "sin",
+            Op::Cos => "cos",
+            Op::Tan => "tan",
+            Op::Sec => "sec",
+            Op::Csc => "csc",
+            Op::Cot => "cot",
+            Op::Sinh => "sinh",
+            Op::Cosh => "cosh",
+            Op::Tanh => "tanh",
+            Op::Sech => "sech",
+            Op::Csch => "csch",
+            Op::Coth => "coth",
+            Op::Arcsin => "arcsin",
+            Op::Arccos => "arccos",
+            Op::Arctan => "arctan",
+            Op::Arccosh => "arccosh",
+            Op::Arccot => "arccot",
+            Op::Arccoth => "arccoth",
+            Op::Arccsc => "arccsc",
+            Op::Arccsch => "arccsch",
+            Op::Arcsec => "arcsec",
+            Op::Arcsech => "arcsech",
+            Op::Arcsinh => "arcsinh",
+            Op::Arctanh => "arctanh",
+            Op::Exp => "exp",
+            Op::Ln => "ln",
+            Op::Log => "log",
+            Op::Determinant => "determinant",
+            Op::Transpose => "transpose",
+            Op::Divergence => "divergence",
+            Op::Grad => "grad",
+            Op::Curl => "curl",
+            Op::Laplacian => "laplacian",
+            Op::Card => "card",
+            Op::Quotient => "quotient",
+            Op::Divide => "divide",
+            Op::Power => "power",
+            Op::Rem => "rem",
+            Op::Implies => "implies",
+            Op::Equivalent => "equivalent",
+            Op::Approx => "approx",
+            Op::Setdiff => "setdiff",
+            Op::Vectorproduct => "vectorproduct",
+            Op::Scalarproduct => "scalarproduct",
+            Op::Outerproduct => "outerproduct",
+            Op::Plus => "plus",
+            Op::Times => "times",
+            Op::Max => "max",
+            Op::Min => "min",
+            Op::Gcd => "gcd",
+            Op::Lcm => "lcm",
+            Op::Mean => "mean",
+            Op::Sdev => "sdev",
+            Op::Variance => "variance",
+            Op::Median => "median",
+            Op::Mode => "mode",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Xor => "xor",
+            Op::Selector => "selector",
+            Op::Union => "union",
+            Op::Intersect => "intersect",
+            Op::Cartesianproduct => "cartesianproduct",
+            Op::Compose => "compose",
+            Op::r#Fn => "fn",
+            Op::Int => "int",
+            Op::Sum => "sum",
+            Op::Product => "product",
+            Op::Diff => "diff",
+            Op::Partialdiff => "partialdiff",
+            Op::Forall => "forall",
+            Op::Exists => "exists",
+            Op::Eq => "eq",
+            Op::Neq => "neq",
+            Op::Gt => "gt",
+            Op::Lt => "lt",
+            Op::Geq => "geq",
+            Op::Leq => "leq",
+            Op::Root => "root",
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
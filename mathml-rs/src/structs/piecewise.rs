@@ -1,3 +1,4 @@
+use super::attributes::Attributes;
 use super::math_node::{MathNodeType, NodeIndex};
 use std::fmt;
 
@@ -7,32 +8,41 @@ pub struct Piecewise {
     pub pieces: Vec<NodeIndex>,
     pub otherwise: Option<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl Piecewise {
-    pub fn index(&mut self, tag_type: MathNodeType, location: NodeIndex) {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        location: NodeIndex,
+    ) -> Result<(), &'static str> {
         match tag_type {
             MathNodeType::Piece => {
                 self.pieces.push(location);
+                Ok(())
             }
             MathNodeType::Otherwise => {
-                if self.otherwise == None {
+                if self.otherwise.is_none() {
                     self.otherwise = Some(location);
+                    Ok(())
                 } else {
-                    panic!("Can't have multiple otherwise expressions in an piecewise function!");
+                    Err("Can't have multiple otherwise expressions in an piecewise function!")
                 }
             }
             MathNodeType::Op
             | MathNodeType::Ci
+            | MathNodeType::Csymbol
             | MathNodeType::Cn
             | MathNodeType::Root
             | MathNodeType::BVar
             | MathNodeType::Apply
             | MathNodeType::Lambda
             | MathNodeType::Piecewise
-            | MathNodeType::Constant => {
-                panic!("Can't have {} in a piecewise function!", tag_type);
-            }
+            | MathNodeType::Constant
+            | MathNodeType::Qualifier
+            | MathNodeType::Semantics
+            | MathNodeType::Container => Err("Can't have this element in a piecewise function!"),
         }
     }
 }
@@ -53,16 +63,23 @@ pub struct Piece {
     pub expr: Option<NodeIndex>,
     pub condition: Option<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl Piece {
-    pub fn index(&mut self, _tag_type: MathNodeType, location: NodeIndex) {
+    pub fn index(
+        &mut self,
+        _tag_type: MathNodeType,
+        location: NodeIndex,
+    ) -> Result<(), &'static str> {
         if self.children.len() == 1 {
             self.expr = Some(location);
+            Ok(())
         } else if self.children.len() == 2 {
             self.condition = Some(location);
+            Ok(())
         } else {
-            panic!("A piece in a piecewise function can have only two children.");
+            Err("A piece in a piecewise function can have only two children.")
         }
     }
 }
@@ -82,30 +99,38 @@ pub struct Otherwise {
     pub children: Vec<NodeIndex>,
     pub expr: Option<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
 }
 
 impl Otherwise {
-    pub fn index(&mut self, tag_type: MathNodeType, location: NodeIndex) {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        location: NodeIndex,
+    ) -> Result<(), &'static str> {
         match tag_type {
             MathNodeType::Apply
             | MathNodeType::Lambda
             | MathNodeType::Ci
+            | MathNodeType::Csymbol
             | MathNodeType::Cn
             | MathNodeType::Piecewise
-            | MathNodeType::Constant => {
-                if self.expr == None {
+            | MathNodeType::Constant
+            | MathNodeType::Semantics
+            | MathNodeType::Container => {
+                if self.expr.is_none() {
                     self.expr = Some(location);
+                    Ok(())
                 } else {
-                    panic!("Can't have two children in an \"otherwise\" branch!");
+                    Err("Can't have two children in an \"otherwise\" branch!")
                 }
             }
             MathNodeType::Root
             | MathNodeType::Op
             | MathNodeType::Otherwise
             | MathNodeType::BVar
-            | MathNodeType::Piece => {
-                panic!("Can't have {} in an \"otherwise\" branch!", tag_type);
-            }
+            | MathNodeType::Piece
+            | MathNodeType::Qualifier => Err("Can't have this element in an \"otherwise\" branch!"),
         }
     }
 }
@@ -0,0 +1,92 @@
+use super::attributes::Attributes;
+use super::math_node::{MathNodeType, NodeIndex};
+use std::fmt;
+
+/// A qualifier node, which refines the operator of an apply or the
+/// variable of a bvar, like the degree of a root or the base of a log.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct QualifierNode {
+    pub qualifier: Option<Qualifier>,
+    pub children: Vec<NodeIndex>,
+    pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
+}
+
+impl QualifierNode {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        _location: NodeIndex,
+    ) -> Result<(), &'static str> {
+        // an interval holds its two bounds, every other qualifier one value
+        let capacity = match self.qualifier {
+            Some(Qualifier::Interval) => 2,
+            _ => 1,
+        };
+        match tag_type {
+            MathNodeType::Apply
+            | MathNodeType::Ci
+            | MathNodeType::Csymbol
+            | MathNodeType::Cn
+            | MathNodeType::Constant
+            | MathNodeType::Lambda
+            | MathNodeType::Piecewise
+            | MathNodeType::Semantics
+            | MathNodeType::Container => {
+                if self.children.len() <= capacity {
+                    Ok(())
+                } else {
+                    Err("Too many children in a qualifier!")
+                }
+            }
+            MathNodeType::Root
+            | MathNodeType::Op
+            | MathNodeType::BVar
+            | MathNodeType::Piece
+            | MathNodeType::Otherwise
+            | MathNodeType::Qualifier => Err("Can't have this element in a qualifier!"),
+        }
+    }
+}
+
+impl fmt::Display for QualifierNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "qualifier: {:?}, children: {:?}, parent: {:?}",
+            self.qualifier, self.children, self.parent
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Qualifier {
+    Degree,
+    LogBase,
+    LowLimit,
+    UpLimit,
+    Interval,
+    Condition,
+    DomainOfApplication,
+}
+
+impl Qualifier {
+    /// The name of the MathML element for this qualifier.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Qualifier::Degree => "degree",
+            Qualifier::LogBase => "logbase",
+            Qualifier::LowLimit => "lowlimit",
+            Qualifier::UpLimit => "uplimit",
+            Qualifier::Interval => "interval",
+            Qualifier::Condition => "condition",
+            Qualifier::DomainOfApplication => "domainofapplication",
+        }
+    }
+}
+
+impl fmt::Display for Qualifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -1,10 +1,25 @@
-use super::math_node::NodeIndex;
+use super::attributes::Attributes;
+use super::math_node::{MathNodeType, NodeIndex};
 use std::fmt;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct Root {
     pub children: Vec<NodeIndex>,
     pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
+}
+
+impl Root {
+    pub fn index(
+        &mut self,
+        _tag_type: MathNodeType,
+        _location: NodeIndex,
+    ) -> Result<(), &'static str> {
+        if self.children.len() > 1 {
+            return Err("Can't have more than one expression in a math element!");
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for Root {
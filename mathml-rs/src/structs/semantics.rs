@@ -0,0 +1,93 @@
+use super::attributes::Attributes;
+use super::math_node::{MathNodeType, NodeIndex};
+use std::fmt;
+
+/// An expression together with other forms of it, like its presentation
+/// markup or a tool-specific encoding.
+///
+/// The annotated expression is the only child in the tree. Annotations are
+/// kept as they were written and never interpreted.
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct Semantics {
+    pub children: Vec<NodeIndex>,
+    pub expr: Option<NodeIndex>,
+    pub annotations: Vec<Annotation>,
+    pub parent: Option<NodeIndex>,
+    pub attributes: Attributes,
+}
+
+impl Semantics {
+    pub fn index(
+        &mut self,
+        tag_type: MathNodeType,
+        location: NodeIndex,
+    ) -> Result<(), &'static str> {
+        match tag_type {
+            MathNodeType::Apply
+            | MathNodeType::Ci
+            | MathNodeType::Csymbol
+            | MathNodeType::Cn
+            | MathNodeType::Constant
+            | MathNodeType::Lambda
+            | MathNodeType::Piecewise
+            | MathNodeType::Semantics
+            | MathNodeType::Container => {
+                if self.expr.is_none() {
+                    self.expr = Some(location);
+                    Ok(())
+                } else {
+                    Err("Can't have two expressions in a semantics element!")
+                }
+            }
+            MathNodeType::Root
+            | MathNodeType::Op
+            | MathNodeType::BVar
+            | MathNodeType::Piece
+            | MathNodeType::Otherwise
+            | MathNodeType::Qualifier => Err("Can't have this element in a semantics element!"),
+        }
+    }
+
+    /// The first annotation with the given encoding.
+    pub fn annotation(&self, encoding: &str) -> Option<&Annotation> {
+        self.annotations
+            .iter()
+            .find(|annotation| annotation.encoding.as_deref() == Some(encoding))
+    }
+}
+
+impl fmt::Display for Semantics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Expr: {:?}, Annotations: {}, Children: {:?}, Parent: {:?}",
+            self.expr,
+            self.annotations.len(),
+            self.children,
+            self.parent
+        )
+    }
+}
+
+/// An `annotation` or `annotation-xml` element of a [`Semantics`].
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct Annotation {
+    pub encoding: Option<String>,
+    /// Whether this is an `annotation-xml`, whose content is markup.
+    pub xml: bool,
+    /// The content as raw, escaped XML, byte for byte as it was read except
+    /// for whitespace inside end tags, like the space in `</mi >`.
+    pub content: String,
+    pub attributes: Attributes,
+}
+
+impl Annotation {
+    /// The name of the MathML element for this annotation.
+    pub fn name(&self) -> &'static str {
+        if self.xml {
+            "annotation-xml"
+        } else {
+            "annotation"
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The value of an evaluated expression.
+///
+/// Integers and rationals stay exact under `plus`, `minus`, `times` and
+/// `divide` for as long as the result fits into an `i64`, and become reals
+/// otherwise.
+///
+/// Vectors and matrices are not values. A `Value` is `Copy`, and every
+/// operator that produces one, including those in compiled expressions,
+/// works on single numbers. Vectors and matrices are evaluated separately
+/// by [`evaluate_array`](crate::evaluate_array), whose
+/// [`ArrayValue`](crate::ArrayValue) holds scalars, vectors or matrices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    Integer(i64),
+    /// A fraction in lowest terms with a positive denominator.
+    Rational(i64, i64),
+    Real(f64),
+    /// A complex number given by its real and imaginary parts.
+    Complex(f64, f64),
+}
+
+impl Value {
+    /// The fraction `numerator / denominator` in lowest terms, which is an
+    /// integer if the denominator divides the numerator. A zero denominator
+    /// gives an infinite or NaN real.
+    pub fn rational(numerator: i64, denominator: i64) -> Value {
+        if denominator == 0 {
+            return Value::Real(numerator as f64 / 0.0);
+        }
+        let divisor = gcd(numerator, denominator);
+        let (mut numerator, mut denominator) = (numerator / divisor, denominator / divisor);
+        if denominator < 0 {
+            match (numerator.checked_neg(), denominator.checked_neg()) {
+                (Some(n), Some(d)) => {
+                    numerator = n;
+                    denominator = d;
+                }
+                _ => return Value::Real(numerator as f64 / denominator as f64),
+            }
+        }
+        if denominator == 1 {
+            Value::Integer(numerator)
+        } else {
+            Value::Rational(numerator, denominator)
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The value as a real number, or `None` for booleans and for complex
+    /// numbers with an imaginary part.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Boolean(..) => None,
+            Value::Complex(_, im) if *im != 0.0 => None,
+            _ => Some(self.real()),
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        !matches!(self, Value::Boolean(..))
+    }
+
+    // The real part of a number.
+    fn real(&self) -> f64 {
+        match self {
+            Value::Integer(i) => *i as f64,
+            Value::Rational(n, d) => *n as f64 / *d as f64,
+            Value::Real(r) => *r,
+            Value::Complex(re, _) => *re,
+            Value::Boolean(..) => f64::NAN,
+        }
+    }
+
+    fn complex(&self) -> (f64, f64) {
+        match self {
+            Value::Complex(re, im) => (*re, *im),
+            other => (other.real(), 0.0),
+        }
+    }
+
+    fn fraction(&self) -> Option<(i64, i64)> {
+        match self {
+            Value::Integer(i) => Some((*i, 1)),
+            Value::Rational(n, d) => Some((*n, *d)),
+            _ => None,
+        }
+    }
+
+    // Combines two numbers exactly if both are integers or rationals and
+    // `exact` does not overflow, as complex numbers if either one is, and
+    // as reals otherwise.
+    fn combine(
+        self,
+        other: Value,
+        exact: impl Fn((i64, i64), (i64, i64)) -> Option<(i64, i64)>,
+        real: impl Fn(f64, f64) -> f64,
+        complex: impl Fn((f64, f64), (f64, f64)) -> (f64, f64),
+    ) -> Value {
+        if let (Some(a), Some(b)) = (self.fraction(), other.fraction()) {
+            if let Some((numerator, denominator)) = exact(a, b) {
+                return Value::rational(numerator, denominator);
+            }
+        }
+        match (self, other) {
+            (Value::Complex(..), _) | (_, Value::Complex(..)) => {
+                let (re, im) = complex(self.complex(), other.complex());
+                Value::Complex(re, im)
+            }
+            _ => Value::Real(real(self.real(), other.real())),
+        }
+    }
+
+    // The arithmetic below expects numbers; the evaluator rules out
+    // booleans before it gets here.
+
+    pub(crate) fn add(self, other: Value) -> Value {
+        self.combine(
+            other,
+            |(a, b), (c, d)| {
+                Some((
+                    a.checked_mul(d)?.checked_add(c.checked_mul(b)?)?,
+                    b.checked_mul(d)?,
+                ))
+            },
+            |a, b| a + b,
+            |(a, b), (c, d)| (a + c, b + d),
+        )
+    }
+
+    pub(crate) fn subtract(self, other: Value) -> Value {
+        self.combine(
+            other,
+            |(a, b), (c, d)| {
+                Some((
+                    a.checked_mul(d)?.checked_sub(c.checked_mul(b)?)?,
+                    b.checked_mul(d)?,
+                ))
+            },
+            |a, b| a - b,
+            |(a, b), (c, d)| (a - c, b - d),
+        )
+    }
+
+    pub(crate) fn multiply(self, other: Value) -> Value {
+        self.combine(
+            other,
+            |(a, b), (c, d)| Some((a.checked_mul(c)?, b.checked_mul(d)?)),
+            |a, b| a * b,
+            |(a, b), (c, d)| (a * c - b * d, a * d + b * c),
+        )
+    }
+
+    /// Division by an exact zero gives a real infinity or NaN, as it would
+    /// for reals.
+    pub(crate) fn divide(self, other: Value) -> Value {
+        self.combine(
+            other,
+            |(a, b), (c, d)| {
+                if c == 0 {
+                    None
+                } else {
+                    Some((a.checked_mul(d)?, b.checked_mul(c)?))
+                }
+            },
+            |a, b| a / b,
+            |(a, b), (c, d)| {
+                let norm = c * c + d * d;
+                ((a * c + b * d) / norm, (b * c - a * d) / norm)
+            },
+        )
+    }
+
+    pub(crate) fn negate(self) -> Value {
+        match self {
+            Value::Integer(i) => i
+                .checked_neg()
+                .map_or(Value::Real(-(i as f64)), Value::Integer),
+            Value::Rational(n, d) => n
+                .checked_neg()
+                .map_or(Value::Real(-(n as f64) / d as f64), |n| {
+                    Value::Rational(n, d)
+                }),
+            Value::Complex(re, im) => Value::Complex(-re, -im),
+            other => Value::Real(-other.real()),
+        }
+    }
+
+    pub(crate) fn abs(self) -> Value {
+        match self {
+            Value::Integer(i) => i
+                .checked_abs()
+                .map_or(Value::Real((i as f64).abs()), Value::Integer),
+            Value::Rational(n, d) => n
+                .checked_abs()
+                .map_or(Value::Real((n as f64).abs() / d as f64), |n| {
+                    Value::Rational(n, d)
+                }),
+            Value::Complex(re, im) => Value::Real(re.hypot(im)),
+            other => Value::Real(other.real().abs()),
+        }
+    }
+
+    /// Whether two values are equal, or `None` if a boolean is compared with
+    /// a number. Reals are equal when they differ by at most `f64::EPSILON`.
+    pub(crate) fn equals(self, other: Value) -> Option<bool> {
+        match (self, other) {
+            (Value::Boolean(a), Value::Boolean(b)) => Some(a == b),
+            (Value::Boolean(..), _) | (_, Value::Boolean(..)) => None,
+            _ => {
+                if let (Some((a, b)), Some((c, d))) = (self.fraction(), other.fraction()) {
+                    return Some(a as i128 * d as i128 == c as i128 * b as i128);
+                }
+                let ((a, b), (c, d)) = (self.complex(), other.complex());
+                Some((a - c).abs() <= f64::EPSILON && (b - d).abs() <= f64::EPSILON)
+            }
+        }
+    }
+}
+
+// The greatest common divisor, which is nonzero if `b` is.
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+    // 2^63 does not fit, so such fractions are left unreduced
+    i64::try_from(a).unwrap_or(1)
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Rational(numerator, denominator) => {
+                write!(f, "{}/{}", numerator, denominator)
+            }
+            Value::Real(value) => write!(f, "{}", value),
+            Value::Complex(re, im) if *im < 0.0 => write!(f, "{} - {}i", re, -im),
+            Value::Complex(re, im) => write!(f, "{} + {}i", re, im),
+        }
+    }
+}